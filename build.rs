@@ -0,0 +1,102 @@
+#![allow(dead_code)]
+
+use std::fs::File;
+
+include!("src/cli.rs");
+
+fn main() -> io::Result<()> {
+    println!("cargo:rerun-if-changed=src/cli.rs");
+    println!(
+        "cargo:rustc-env=CORUN_TARGET={}",
+        env::var("TARGET").expect("TARGET not set")
+    );
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR not set"));
+
+    for (shell, file_name) in &[
+        (Shell::Bash, "git-corun.bash"),
+        (Shell::Zsh, "_git-corun"),
+        (Shell::Fish, "git-corun.fish"),
+    ] {
+        write_completions(*shell, &mut File::create(out_dir.join(file_name))?)?;
+    }
+
+    write_man_page(&mut File::create(out_dir.join("git-corun.1"))?)?;
+
+    Ok(())
+}
+
+fn write_man_page(out: &mut dyn Write) -> io::Result<()> {
+    let app = app();
+
+    writeln!(
+        out,
+        ".TH GIT-CORUN 1 \"\" \"git-corun {}\" \"Git Manual\"",
+        env!("CARGO_PKG_VERSION")
+    )?;
+
+    writeln!(out, ".SH NAME")?;
+    writeln!(
+        out,
+        "git-corun \\- {}",
+        roff(app.p.meta.about.unwrap_or_default())
+    )?;
+
+    writeln!(out, ".SH SYNOPSIS")?;
+    writeln!(out, ".B git corun")?;
+    writeln!(out, "[OPTIONS] [COMMITS...] \\-\\- COMMAND [ARGS...]")?;
+    writeln!(out, ".br")?;
+    writeln!(out, ".B git corun")?;
+    writeln!(out, "SUBCOMMAND [ARGS...]")?;
+
+    writeln!(out, ".SH OPTIONS")?;
+    for flag in &app.p.flags {
+        write_man_arg(out, flag.s.short, flag.s.long, None, flag.b.help)?;
+    }
+    for opt in &app.p.opts {
+        let value = opt
+            .v
+            .val_names
+            .as_ref()
+            .and_then(|names| names.values().next().copied());
+        write_man_arg(
+            out,
+            opt.s.short,
+            opt.s.long,
+            Some(value.unwrap_or(opt.b.name)),
+            opt.b.help,
+        )?;
+    }
+
+    writeln!(out, ".SH SUBCOMMANDS")?;
+    for subcommand in &app.p.subcommands {
+        writeln!(out, ".TP")?;
+        writeln!(out, "\\fB{}\\fR", roff(subcommand.get_name()))?;
+        writeln!(out, "{}", roff(subcommand.p.meta.about.unwrap_or_default()))?;
+    }
+
+    Ok(())
+}
+
+fn write_man_arg(
+    out: &mut dyn Write,
+    short: Option<char>,
+    long: Option<&str>,
+    value: Option<&str>,
+    help: Option<&str>,
+) -> io::Result<()> {
+    let mut names = Vec::new();
+    names.extend(short.map(|short| format!("\\fB\\-{}\\fR", short)));
+    names.extend(long.map(|long| format!("\\fB\\-\\-{}\\fR", roff(long))));
+
+    writeln!(out, ".TP")?;
+    match value {
+        Some(value) => writeln!(out, "{} \\fI{}\\fR", names.join(", "), roff(value))?,
+        None => writeln!(out, "{}", names.join(", "))?,
+    }
+    writeln!(out, "{}", roff(help.unwrap_or_default()))
+}
+
+fn roff(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('-', "\\-")
+}
@@ -0,0 +1,61 @@
+//! Detecting the command to run from the files in each commit, for running
+//! the usual tests of a project without having to give a command.
+//!
+//! Which command is run for which file can be set in git config, with files
+//! that are checked in order before the default ones:
+//!
+//! ```text
+//! [corun]
+//!     autoCommand = "meson.build: meson test -C build"
+//!     autoCommand = "Makefile: make test"
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use crate::git;
+use crate::runner::shell_quote;
+
+const DEFAULT_COMMANDS: &[(&str, &str)] = &[
+    ("Cargo.toml", "cargo test"),
+    ("package.json", "npm test"),
+    ("go.mod", "go test ./..."),
+    ("Makefile", "make check"),
+];
+
+/// Get the command to run with `--auto`, which is a shell script that runs
+/// the command for the first file that exists in the checkout, or exits with
+/// 125 (inconclusive) if there is none.
+pub fn command(git_dir: Option<&Path>) -> io::Result<Vec<String>> {
+    let mut commands = Vec::new();
+    if let Some(git_dir) = git_dir {
+        for value in git::get_config_values(git_dir, "corun.autoCommand")? {
+            let (file, command) = value.split_once(':').ok_or_else(|| {
+                io::Error::other(format!(
+                    "invalid corun.autoCommand (expected FILE: COMMAND): {}",
+                    value
+                ))
+            })?;
+            commands.push((file.trim().to_string(), command.trim().to_string()));
+        }
+    }
+    commands.extend(
+        DEFAULT_COMMANDS
+            .iter()
+            .map(|(file, command)| (file.to_string(), command.to_string())),
+    );
+
+    let mut script = String::new();
+    for (file, command) in &commands {
+        let keyword = if script.is_empty() { "if" } else { "elif" };
+        script.push_str(&format!(
+            "{} [ -e {} ]; then {}; ",
+            keyword,
+            shell_quote(file),
+            command
+        ));
+    }
+    script.push_str("else echo 'git-corun: no command found for this commit' >&2; exit 125; fi");
+
+    Ok(vec!["/bin/sh".to_string(), "-c".to_string(), script])
+}
@@ -0,0 +1,40 @@
+//! Benchmarking mode, which makes timings of commits comparable with each
+//! other by reducing noise from the system: pausing between commands to let
+//! the CPU cool down, pinning commands to CPUs, and running commands first to
+//! warm up caches without measuring them.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// Settings of a benchmarking run, which are recorded with its results.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// Pause before each command, in seconds.
+    pub cooldown: f64,
+    /// CPUs commands were pinned to, or empty if they were not pinned.
+    pub cpus: Vec<usize>,
+    /// Number of times the command was run on each commit before measuring it.
+    pub warmup: usize,
+}
+
+/// Pin corun, and the commands it runs, to a set of CPUs.
+#[cfg(target_os = "linux")]
+pub fn pin_cpus(cpus: &[usize]) -> io::Result<()> {
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for &cpu in cpus {
+        if cpu >= 8 * std::mem::size_of::<libc::cpu_set_t>() {
+            return Err(io::Error::other(format!("no such CPU: {}", cpu)));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_cpus(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::other("only supported on Linux"))
+}
@@ -0,0 +1,31 @@
+//! Download caches of package managers that are shared between runs, so that
+//! commands on each commit do not download the same packages again.
+//!
+//! The caches are kept in `cache/` in the base directory of corun, and are
+//! given to commands with the environment variables the package managers use.
+
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Cache {
+    Cargo,
+    Npm,
+    Go,
+    Pip,
+}
+
+impl Cache {
+    /// Environment variables to set for the cache, with the directories they
+    /// are set to.
+    pub fn variables(self, cache_dir: &Path) -> Vec<(&'static str, PathBuf)> {
+        match self {
+            Cache::Cargo => vec![("CARGO_HOME", cache_dir.join("cargo"))],
+            Cache::Npm => vec![("npm_config_cache", cache_dir.join("npm"))],
+            Cache::Go => vec![
+                ("GOMODCACHE", cache_dir.join("go").join("mod")),
+                ("GOCACHE", cache_dir.join("go").join("build")),
+            ],
+            Cache::Pip => vec![("PIP_CACHE_DIR", cache_dir.join("pip"))],
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Guessing which commits are likely to break something, from cheap heuristics
+//! on what they change, to run on those first or only on those.
+//!
+//! Commits that only change documentation and similar files are not
+//! candidates. Other commits are scored higher the larger they are, and when
+//! they change build files or files the command is given as arguments. The
+//! patterns can be set in git config:
+//!
+//! ```text
+//! [corun]
+//!     candidateIgnore = docs/
+//!     candidateIgnore = *.md
+//!     candidateBuildFiles = Cargo.toml
+//! ```
+//!
+//! Patterns ending with `/` match directories, patterns with other slashes
+//! match whole paths, and other patterns match file names, with `*` and `?`
+//! as wildcards.
+//!
+//! The heuristics can also be replaced with a shell command, set with
+//! `corun.candidateCommand`, which is run with the commit in `CORUN_COMMIT`,
+//! and exits with 0 for candidates.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::git;
+
+const DEFAULT_IGNORE: &[&str] = &[
+    "*.md",
+    "*.rst",
+    "*.adoc",
+    "*.txt",
+    "docs/",
+    "doc/",
+    "LICENSE*",
+    "COPYING*",
+    "AUTHORS*",
+    "CHANGELOG*",
+    ".github/",
+    ".gitignore",
+    ".mailmap",
+];
+
+const DEFAULT_BUILD_FILES: &[&str] = &[
+    "Cargo.toml",
+    "Cargo.lock",
+    "build.rs",
+    "Makefile",
+    "*.mk",
+    "CMakeLists.txt",
+    "*.cmake",
+    "meson.build",
+    "configure.ac",
+    "package.json",
+    "package-lock.json",
+    "yarn.lock",
+    "go.mod",
+    "go.sum",
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "requirements*.txt",
+    "*.gradle",
+    "pom.xml",
+    "Dockerfile",
+];
+
+/// Score for changing build files or files the command is given.
+const INPUT_SCORE: u32 = 10;
+
+/// Number of changed lines that add one to the score.
+const LINES_PER_POINT: u32 = 100;
+
+pub struct Heuristics {
+    ignore: Vec<String>,
+    build_files: Vec<String>,
+    /// Paths given as arguments to the command.
+    inputs: Vec<String>,
+    command: Option<String>,
+}
+
+impl Heuristics {
+    /// Get the heuristics for running a command, with patterns from the git
+    /// config of a repository if set.
+    pub fn load(git_dir: &Path, command: &[String]) -> io::Result<Self> {
+        let or_default = |values: Vec<String>, default: &[&str]| {
+            if values.is_empty() {
+                default.iter().map(|value| value.to_string()).collect()
+            } else {
+                values
+            }
+        };
+
+        Ok(Heuristics {
+            ignore: or_default(
+                git::get_config_values(git_dir, "corun.candidateIgnore")?,
+                DEFAULT_IGNORE,
+            ),
+            build_files: or_default(
+                git::get_config_values(git_dir, "corun.candidateBuildFiles")?,
+                DEFAULT_BUILD_FILES,
+            ),
+            inputs: command
+                .iter()
+                .skip(1)
+                .filter(|arg| !arg.starts_with('-') && (arg.contains('/') || arg.contains('.')))
+                .map(|arg| arg.trim_start_matches("./").to_string())
+                .collect(),
+            command: git::get_config_values(git_dir, "corun.candidateCommand")?.pop(),
+        })
+    }
+}
+
+/// Score commits by how likely they are to break something, where commits
+/// with a score of 0 are not candidates at all.
+pub fn scores(
+    git_dir: &Path,
+    commits: &[String],
+    heuristics: &Heuristics,
+) -> io::Result<HashMap<String, u32>> {
+    if let Some(command) = &heuristics.command {
+        return commits
+            .iter()
+            .map(|commit| {
+                let status = Command::new("/bin/sh")
+                    .args(["-c", command])
+                    .env("CORUN_COMMIT", commit)
+                    .status()?;
+                Ok((commit.clone(), status.success() as u32))
+            })
+            .collect();
+    }
+
+    let mut scores = HashMap::new();
+    for (commit, changes) in git::changed_lines(git_dir, commits)? {
+        let relevant = changes
+            .iter()
+            .filter(|(path, _)| !matches_any(&heuristics.ignore, path))
+            .collect::<Vec<_>>();
+
+        let score = if relevant.is_empty() {
+            0
+        } else {
+            let lines = relevant.iter().map(|(_, lines)| lines).sum::<u32>();
+            let inputs = relevant.iter().any(|(path, _)| {
+                matches_any(&heuristics.build_files, path)
+                    || heuristics
+                        .inputs
+                        .iter()
+                        .any(|input| path == input || path.starts_with(&format!("{}/", input)))
+            });
+            1 + lines / LINES_PER_POINT + if inputs { INPUT_SCORE } else { 0 }
+        };
+        scores.insert(commit, score);
+    }
+    Ok(scores)
+}
+
+fn matches_any(patterns: &[String], path: &str) -> bool {
+    patterns.iter().any(|pattern| matches(pattern, path))
+}
+
+fn matches(pattern: &str, path: &str) -> bool {
+    if let Some(dir) = pattern.strip_suffix('/') {
+        path.starts_with(pattern) || path.contains(&format!("/{}/", dir))
+    } else if pattern.contains('/') {
+        glob(pattern.as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        glob(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// Match text against a pattern with `*` and `?` wildcards.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, _) => text.is_empty(),
+        (Some((b'*', rest)), _) => {
+            glob(rest, text) || (!text.is_empty() && glob(pattern, &text[1..]))
+        }
+        (Some((b'?', rest)), Some((_, text_rest))) => glob(rest, text_rest),
+        (Some((c, rest)), Some((t, text_rest))) if c == t => glob(rest, text_rest),
+        _ => false,
+    }
+}
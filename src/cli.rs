@@ -0,0 +1,1501 @@
+// Command line interface.
+//
+// This file is also included by `build.rs` to generate completions and the man
+// page, so it must not depend on anything but `std` and `structopt`.
+
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use structopt::clap::{self, App, Shell};
+use structopt::StructOpt;
+
+/// Define an enum that can be parsed from a fixed set of command line values.
+macro_rules! value_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident => $value:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl $name {
+            pub const VALUES: &'static [&'static str] = &[$($value,)*];
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, String> {
+                match s {
+                    $($value => Ok($name::$variant),)*
+                    _ => Err(format!("invalid value: {}", s)),
+                }
+            }
+        }
+    };
+}
+
+// parsed once, so the size of `Options` does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(name = "git-corun")]
+pub enum Cli {
+    #[structopt(about = "Check out commits and run a command on each (default)")]
+    Run(Options),
+
+    #[structopt(about = "Manage named commit sets")]
+    Set(SetCommand),
+
+    #[structopt(about = "List results of past runs")]
+    List(ListCommand),
+
+    #[structopt(about = "List commits whose results changed between the two latest runs")]
+    Regressions(RegressionsCommand),
+
+    #[structopt(
+        name = "diff-runs",
+        about = "List commits whose status or duration changed between two runs"
+    )]
+    DiffRuns(DiffRunsCommand),
+
+    #[structopt(
+        name = "range-diff",
+        about = "Run a command on two versions of a series of commits, and list pairs of commits whose status differs"
+    )]
+    RangeDiff(RangeDiffCommand),
+
+    #[structopt(
+        name = "exec-lines",
+        about = "Print a rebase todo list that runs a command after picking each commit"
+    )]
+    ExecLines(ExecLinesCommand),
+
+    #[structopt(about = "Add a note to the results of a past run on a commit")]
+    Annotate(AnnotateCommand),
+
+    #[structopt(about = "Import a JSON report into the results of past runs")]
+    Import(ImportCommand),
+
+    #[structopt(about = "Print the directory a past run checked out commits in")]
+    Dir(DirCommand),
+
+    #[structopt(about = "Run a past run again, on the same commits with the same command")]
+    Replay(ReplayCommand),
+
+    #[structopt(about = "Remove old results of past runs")]
+    Gc(GcCommand),
+
+    #[structopt(about = "Query results of past runs with SQL")]
+    Query(QueryCommand),
+
+    #[structopt(about = "Print shell completions")]
+    Completions(CompletionsCommand),
+
+    #[structopt(
+        name = "self-update",
+        about = "Update git-corun to the latest release (if built with the self-update feature)"
+    )]
+    SelfUpdate(SelfUpdateCommand),
+
+    #[structopt(name = "__complete-revs", setting = clap::AppSettings::Hidden)]
+    CompleteRevs(CompleteRevsCommand),
+
+    #[structopt(name = "__remote-job", setting = clap::AppSettings::Hidden)]
+    RemoteJob(RemoteJobCommand),
+}
+
+impl Cli {
+    pub const SUBCOMMANDS: &'static [&'static str] = &[
+        "run",
+        "set",
+        "list",
+        "regressions",
+        "diff-runs",
+        "range-diff",
+        "exec-lines",
+        "annotate",
+        "import",
+        "dir",
+        "replay",
+        "gc",
+        "query",
+        "completions",
+        "self-update",
+        "__complete-revs",
+        "__remote-job",
+    ];
+
+    pub fn from_args_safe() -> clap::Result<Self> {
+        let args = env::args_os().collect::<Vec<_>>();
+
+        // subcommands are optional; anything else is parsed as `run`
+        match args.get(1).and_then(|arg| arg.to_str()) {
+            Some(name) if Self::SUBCOMMANDS.contains(&name) => Self::from_iter_safe(args),
+            _ => Options::from_iter_safe(args).map(Cli::Run),
+        }
+    }
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum SetCommand {
+    #[structopt(about = "Expand revisions and save the resulting commits as a named set")]
+    Save {
+        #[structopt(help = "Name of commit set")]
+        name: String,
+
+        #[structopt(help = "List of commits to save", required = true)]
+        revs: Vec<String>,
+    },
+
+    #[structopt(about = "List saved commit sets")]
+    List,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct ListCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(help = "Do not show output in a pager", long = "no-pager")]
+    pub no_pager: bool,
+
+    #[structopt(
+        help = "Use colors in output (default: color.ui)",
+        long = "color",
+        value_name = "when",
+        possible_values = ColorWhen::VALUES
+    )]
+    pub color: Option<ColorWhen>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct RegressionsCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(help = "Do not show output in a pager", long = "no-pager")]
+    pub no_pager: bool,
+
+    #[structopt(
+        help = "Use colors in output (default: color.ui)",
+        long = "color",
+        value_name = "when",
+        possible_values = ColorWhen::VALUES
+    )]
+    pub color: Option<ColorWhen>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct DiffRunsCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(help = "Do not show output in a pager", long = "no-pager")]
+    pub no_pager: bool,
+
+    #[structopt(
+        help = "Use colors in output (default: color.ui)",
+        long = "color",
+        value_name = "when",
+        possible_values = ColorWhen::VALUES
+    )]
+    pub color: Option<ColorWhen>,
+
+    #[structopt(help = "Earlier run, as a run id or a JSON report")]
+    pub run_a: String,
+
+    #[structopt(help = "Later run, as a run id or a JSON report")]
+    pub run_b: String,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct AnnotateCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(
+        help = "Only add the note to results with this label (e.g. a step or matrix variant)",
+        long = "label"
+    )]
+    pub label: Option<String>,
+
+    #[structopt(help = "Note to add", short = "m", long = "message")]
+    pub message: String,
+
+    #[structopt(help = "Run id")]
+    pub run: String,
+
+    #[structopt(help = "Commit to add the note to the results of")]
+    pub commit: String,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct ImportCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(help = "JSON reports written with --json", required = true)]
+    pub reports: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct DirCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(help = "Use the latest run (default)", long = "last")]
+    pub last: bool,
+
+    #[structopt(
+        help = "Run id, or a commit to use the latest run on",
+        value_name = "run-id|commit",
+        conflicts_with = "last"
+    )]
+    pub run: Option<String>,
+
+    #[structopt(
+        help = "Decompress logs compressed with --compress-logs",
+        long = "decompress"
+    )]
+    pub decompress: bool,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct ReplayCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(help = "Run to replay, as a run id or a JSON report")]
+    pub run: String,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct GcCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(
+        help = "Remove results of past runs that are older or more than allowed",
+        long = "results"
+    )]
+    pub results: bool,
+
+    #[structopt(
+        help = "Remove runs older than this, e.g. 30d (default: corun.resultsMaxAge)",
+        long = "results-max-age",
+        value_name = "duration",
+        parse(try_from_str = parse_duration)
+    )]
+    pub results_max_age: Option<Duration>,
+
+    #[structopt(
+        help = "Keep only this many of the latest runs (default: corun.resultsMaxEntries)",
+        long = "results-max-entries",
+        value_name = "count"
+    )]
+    pub results_max_entries: Option<usize>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct RangeDiffCommand {
+    #[structopt(help = "Old version of the commits, e.g. main..topic@{1}")]
+    pub old: String,
+
+    #[structopt(help = "New version of the commits, e.g. main..topic")]
+    pub new: String,
+
+    #[structopt(help = "Command to execute", required = true, last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct ExecLinesCommand {
+    #[structopt(help = "Run as shell command", short = "c")]
+    pub shell_command: bool,
+
+    #[structopt(
+        help = "Shell to run shell commands and steps with (e.g. sh, bash, zsh or fish)",
+        long = "shell",
+        default_value = "/bin/bash"
+    )]
+    pub shell: String,
+
+    #[structopt(
+        help = "Run the command once for each value of an environment variable (NAME=VALUE,...)",
+        long = "matrix",
+        number_of_values = 1
+    )]
+    pub matrix: Vec<MatrixDimension>,
+
+    #[structopt(
+        help = "Shell command to run as a named step, after the steps it depends on (NAME[:DEP,...]=COMMAND)",
+        long = "step",
+        number_of_values = 1,
+        conflicts_with = "command"
+    )]
+    pub steps: Vec<StepSpec>,
+
+    #[structopt(help = "Range of commits, e.g. main..topic")]
+    pub range: String,
+
+    #[structopt(help = "Command to execute", required_unless = "steps", last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct QueryCommand {
+    #[structopt(
+        help = "Version control system of the repository",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(help = "Do not show output in a pager", long = "no-pager")]
+    pub no_pager: bool,
+
+    #[structopt(
+        help = "Format to print results in",
+        long = "format",
+        default_value = "table",
+        possible_values = QueryFormat::VALUES
+    )]
+    pub format: QueryFormat,
+
+    #[structopt(help = "SQL query, on the tables runs and results")]
+    pub sql: String,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct CompletionsCommand {
+    #[structopt(
+        help = "Shell to print completions for",
+        possible_values = &Shell::variants(),
+        case_insensitive = true
+    )]
+    pub shell: Shell,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct SelfUpdateCommand {
+    #[structopt(
+        help = "Only check whether a newer version is available",
+        long = "check"
+    )]
+    #[cfg_attr(not(feature = "self-update"), allow(dead_code))]
+    pub check: bool,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct CompleteRevsCommand {
+    #[structopt(help = "Word being completed")]
+    pub current: Option<String>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct RemoteJobCommand {
+    #[structopt(help = "URL of the job runner", long = "url")]
+    pub url: String,
+
+    #[structopt(
+        help = "Environment variable to give the job, with its value from the environment",
+        long = "env",
+        number_of_values = 1
+    )]
+    pub env: Vec<String>,
+
+    #[structopt(help = "Command to execute", required = true, last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(
+    about = "Check out commits into a temporary directory and run a command on each",
+    usage = "git-corun [FLAGS] [OPTIONS] [commits]... -- <command>...",
+    after_help = "SUBCOMMANDS:\n    run            Check out commits and run a command on each (default)\n    set            Manage named commit sets\n    list           List results of past runs\n    regressions    List commits whose results changed between the two latest runs\n    diff-runs      List commits whose status or duration changed between two runs\n    range-diff     Run a command on two versions of a series of commits, and compare them\n    exec-lines     Print a rebase todo list that runs a command after picking each commit\n    annotate       Add a note to the results of a past run on a commit\n    import         Import a JSON report into the results of past runs\n    dir            Print the directory a past run checked out commits in\n    replay         Run a past run again, on the same commits with the same command\n    gc             Remove old results of past runs\n    query          Query results of past runs with SQL\n    completions    Print shell completions\n    self-update    Update git-corun to the latest release"
+)]
+pub struct Options {
+    #[structopt(
+        help = "Directory to check out and run code in",
+        short = "d",
+        long = "dir"
+    )]
+    pub dir: Option<PathBuf>,
+
+    #[structopt(
+        help = "Run on the repository in a directory, as if started in it",
+        long = "repo",
+        value_name = "DIR"
+    )]
+    pub repo: Option<PathBuf>,
+
+    #[structopt(
+        help = "Apply latest stash before running (repeat to apply more entries, oldest first)",
+        short = "s",
+        long = "stash",
+        parse(from_occurrences)
+    )]
+    pub apply_stash: usize,
+
+    #[structopt(
+        help = "Apply all stash entries before running, oldest first",
+        long = "stash-all"
+    )]
+    pub apply_all_stashes: bool,
+
+    #[structopt(skip)]
+    pub apply_index: bool,
+
+    #[structopt(
+        help = "Also run on the latest stash itself, on top of its parent commit",
+        long = "stash-as-commit"
+    )]
+    pub stash_as_commit: bool,
+
+    #[structopt(help = "Run as shell command", short = "c")]
+    pub shell_command: bool,
+
+    #[structopt(
+        help = "Only let commands write in the checkout, the temporary directory and --jail-allow directories (Linux 5.13+)",
+        long = "jail"
+    )]
+    pub jail: bool,
+
+    #[structopt(
+        help = "Also let jailed commands write in a directory, e.g. a cache",
+        long = "jail-allow",
+        number_of_values = 1,
+        requires = "jail"
+    )]
+    pub jail_allow: Vec<PathBuf>,
+
+    #[structopt(
+        help = "Shell to run shell commands and steps with (e.g. sh, bash, zsh or fish)",
+        long = "shell",
+        default_value = "/bin/bash"
+    )]
+    pub shell: String,
+
+    #[structopt(
+        help = "Show output from commands (repeat to also show git commands run by corun, and again to show the environment and timing of commands)",
+        short = "v",
+        long = "verbose",
+        parse(from_occurrences)
+    )]
+    pub verbose: u8,
+
+    #[structopt(
+        help = "Print each command with its directory and environment before running it, to copy and paste",
+        long = "show-command"
+    )]
+    pub show_command: bool,
+
+    #[structopt(
+        help = "Show output from commands that fail, after they finish",
+        long = "verbose-failed",
+        conflicts_with = "verbose"
+    )]
+    pub verbose_failed: bool,
+
+    #[structopt(
+        help = "Kill commands that run for longer than this, e.g. 90s, 5m or 1h",
+        long = "timeout",
+        parse(try_from_str = parse_duration)
+    )]
+    pub timeout: Option<Duration>,
+
+    #[structopt(
+        help = "Limit the virtual memory of commands, e.g. 512M or 4G",
+        long = "memory-limit",
+        parse(try_from_str = parse_size)
+    )]
+    pub memory_limit: Option<u64>,
+
+    #[structopt(
+        help = "Run commands with their output connected to a pseudo-terminal",
+        long = "pty"
+    )]
+    pub pty: bool,
+
+    #[structopt(
+        help = "Prefix each line in logs with the time since the command started",
+        long = "timestamps"
+    )]
+    pub timestamps: bool,
+
+    #[structopt(
+        help = "Compress logs with zstd once each command is done",
+        long = "compress-logs"
+    )]
+    pub compress_logs: bool,
+
+    #[structopt(
+        help = "Stop daemons started by build tools that keep using the directory after running",
+        long = "kill-daemons"
+    )]
+    pub kill_daemons: bool,
+
+    #[structopt(
+        help = "Mark results as refs/corun/pass/<hash> or refs/corun/fail/<hash>",
+        long = "mark"
+    )]
+    pub mark: bool,
+
+    #[structopt(help = "Skip commits already marked as passing", long = "skip-marked")]
+    pub skip_marked: bool,
+
+    #[structopt(
+        help = "What to do if the commits to run on change during the run",
+        long = "on-ref-change",
+        default_value = "warn",
+        possible_values = OnRefChange::VALUES
+    )]
+    pub on_ref_change: OnRefChange,
+
+    #[structopt(
+        help = "Do not ask for confirmation before running on a lot of commits",
+        short = "y",
+        long = "yes"
+    )]
+    pub yes: bool,
+
+    #[structopt(
+        help = "Run on some commits first: recent, random, candidates or touching:PATHSPEC",
+        long = "prioritize"
+    )]
+    pub prioritize: Option<Priority>,
+
+    #[structopt(
+        help = "Fail if any of the revisions is invalid or matches no commits, instead of running on the others",
+        long = "strict-revs"
+    )]
+    pub strict_revs: bool,
+
+    #[structopt(
+        help = "Run on parents before their children, with each line of history one commit after another",
+        long = "topo-order",
+        conflicts_with_all = &["date-order", "stream"]
+    )]
+    pub topo_order: bool,
+
+    #[structopt(
+        help = "Run on parents before their children, and otherwise by commit date",
+        long = "date-order",
+        conflicts_with = "stream"
+    )]
+    pub date_order: bool,
+
+    #[structopt(
+        help = "Only run on commits that are likely to break something, e.g. not ones that only change docs",
+        long = "only-breaking-candidates"
+    )]
+    pub only_breaking_candidates: bool,
+
+    #[structopt(
+        help = "Run the command in a submodule, at the commit of it that each commit of the superproject uses",
+        long = "submodule",
+        value_name = "path"
+    )]
+    pub submodule: Option<PathBuf>,
+
+    #[structopt(
+        help = "Start running on a very large range of commits while it is still being walked, newest commits first",
+        long = "stream",
+        conflicts_with_all = &["prioritize", "only-breaking-candidates", "reachable-from", "ours", "stack", "stack-independent", "set", "from-report", "plan", "split-by-path"]
+    )]
+    pub stream: bool,
+
+    #[structopt(help = "Stop after the first commit that fails", long = "fail-fast")]
+    pub fail_fast: bool,
+
+    #[structopt(
+        help = "Do not start running on more commits after this long, e.g. 6h, or after a time of day, e.g. 06:30",
+        long = "deadline",
+        value_name = "duration|time"
+    )]
+    pub deadline: Option<Deadline>,
+
+    #[structopt(
+        help = "What to do when a command fails (shell: start a shell in the checkout, and continue when it exits)",
+        long = "on-failure",
+        default_value = "continue",
+        possible_values = OnFailure::VALUES
+    )]
+    pub on_failure: OnFailure,
+
+    #[structopt(help = "Show progress in the terminal title", long = "title")]
+    pub title: bool,
+
+    #[structopt(
+        help = "Ring the terminal bell when a commit fails and when the run is done",
+        long = "bell"
+    )]
+    pub bell: bool,
+
+    #[structopt(
+        help = "Run a shell command when the run is done, with the results as JSON on stdin",
+        long = "on-finish",
+        number_of_values = 1
+    )]
+    pub on_finish: Vec<String>,
+
+    #[structopt(
+        help = "Version control system of the repository (jj and hg are experimental)",
+        long = "vcs",
+        default_value = "git",
+        possible_values = VcsKind::VALUES
+    )]
+    pub vcs: VcsKind,
+
+    #[structopt(
+        help = "Run each commit once for each value of an environment variable (NAME=VALUE,...)",
+        long = "matrix",
+        number_of_values = 1
+    )]
+    pub matrix: Vec<MatrixDimension>,
+
+    #[structopt(
+        help = "Use a cargo home shared between runs, to not download crates again (sets CARGO_HOME)",
+        long = "cargo-home"
+    )]
+    pub cargo_home: bool,
+
+    #[structopt(
+        help = "Use an npm cache shared between runs (sets npm_config_cache)",
+        long = "npm-cache"
+    )]
+    pub npm_cache: bool,
+
+    #[structopt(
+        help = "Use Go module and build caches shared between runs (sets GOMODCACHE and GOCACHE)",
+        long = "go-cache"
+    )]
+    pub go_cache: bool,
+
+    #[structopt(
+        help = "Where cargo builds in (sets CARGO_TARGET_DIR), instead of target/ in the checkout, which is cleaned for each commit",
+        long = "cargo-target",
+        possible_values = CargoTarget::VALUES
+    )]
+    pub cargo_target: Option<CargoTarget>,
+
+    #[structopt(
+        help = "Fetch dependencies in a step of its own before running, with a shared cache, and run commands offline",
+        long = "prefetch",
+        possible_values = Prefetch::VALUES
+    )]
+    pub prefetch: Option<Prefetch>,
+
+    #[structopt(
+        help = "Build an image from this Dockerfile at each commit, and run commands in it",
+        long = "dockerfile",
+        value_name = "PATH",
+        conflicts_with = "jail"
+    )]
+    pub dockerfile: Option<PathBuf>,
+
+    #[structopt(
+        help = "Run commands as jobs on a job runner at this URL instead of locally (experimental)",
+        long = "remote",
+        value_name = "URL",
+        conflicts_with_all = &["dockerfile", "jail", "prefetch", "cargo-home", "npm-cache", "go-cache", "cargo-target", "isolate-home", "apply-stash", "apply-all-stashes"]
+    )]
+    pub remote: Option<String>,
+
+    #[structopt(
+        help = "Give commands a home directory of their own in the clone (sets HOME and XDG directories)",
+        long = "isolate-home"
+    )]
+    pub isolate_home: bool,
+
+    #[structopt(
+        help = "Directory to copy into the home directory of commands, e.g. with configuration for tools",
+        long = "home-template",
+        value_name = "dir",
+        requires = "isolate-home"
+    )]
+    pub home_template: Option<PathBuf>,
+
+    #[structopt(
+        help = "Run the command this many times on each commit",
+        long = "repeat",
+        value_name = "n"
+    )]
+    pub repeat: Option<usize>,
+
+    #[structopt(
+        help = "Benchmark commands, with less noise from the system, e.g. to compare timings of commits",
+        long = "bench"
+    )]
+    pub bench: bool,
+
+    #[structopt(
+        help = "With --bench, pause before each command, e.g. 10s, to let the CPU cool down",
+        long = "cooldown",
+        requires = "bench",
+        parse(try_from_str = parse_duration)
+    )]
+    pub cooldown: Option<Duration>,
+
+    #[structopt(
+        help = "With --bench, run commands only on these CPUs, e.g. 2,3 or 0-3",
+        long = "pin-cpus",
+        value_name = "cpus",
+        requires = "bench"
+    )]
+    pub pin_cpus: Option<CpuList>,
+
+    #[structopt(
+        help = "With --bench, run the command this many times on each commit before measuring it (default: 1)",
+        long = "warmup",
+        value_name = "n",
+        requires = "bench"
+    )]
+    pub warmup: Option<usize>,
+
+    #[structopt(
+        help = "Check that a file or directory is the same every time the command is run (implies --repeat 2)",
+        long = "check-reproducible",
+        value_name = "path"
+    )]
+    pub check_reproducible: Option<PathBuf>,
+
+    #[structopt(
+        help = "Pass if a file exists in the checkout after the command (or the last step), instead of by exit code",
+        long = "expect-file",
+        value_name = "path"
+    )]
+    pub expect_file: Option<PathBuf>,
+
+    #[structopt(
+        help = "With --expect-file, only pass if the file is not empty",
+        long = "expect-non-empty",
+        requires = "expect-file"
+    )]
+    pub expect_non_empty: bool,
+
+    #[structopt(
+        help = "Extract a number from the output of the command (or the last step), from the first capture group of the last match of a regular expression",
+        long = "extract",
+        value_name = "regex"
+    )]
+    pub extract: Option<String>,
+
+    #[structopt(
+        help = "Fail commits where the extracted number is above this",
+        long = "threshold",
+        value_name = "number",
+        requires = "extract"
+    )]
+    pub threshold: Option<f64>,
+
+    #[structopt(
+        help = "Fail commits where the extracted number is worse than on their parent by more than this percentage",
+        long = "max-regression-pct",
+        value_name = "percent",
+        requires = "extract"
+    )]
+    pub max_regression_pct: Option<f64>,
+
+    #[structopt(
+        help = "Higher extracted numbers are better (e.g. test counts), so --threshold is a minimum",
+        long = "higher-is-better",
+        requires = "extract"
+    )]
+    pub higher_is_better: bool,
+
+    #[structopt(
+        help = "Print a chart of the extracted numbers (or durations) across commits at the end",
+        long = "plot"
+    )]
+    pub plot: bool,
+
+    #[structopt(
+        help = "Reuse the results of the previous commit when nothing in these paths changed since it",
+        long = "relevant-paths",
+        value_name = "path",
+        number_of_values = 1
+    )]
+    pub relevant_paths: Vec<PathBuf>,
+
+    #[structopt(
+        help = "Give the value of a trailer in the commit message to the command, as CORUN_PARAM_<NAME>",
+        long = "param-from-trailer",
+        value_name = "name",
+        number_of_values = 1
+    )]
+    pub param_from_trailer: Vec<String>,
+
+    #[structopt(
+        help = "Parent of merge commits to run against, given to the command as CORUN_PARENT (default: 1)",
+        long = "merge-parent",
+        value_name = "n"
+    )]
+    pub merge_parent: Option<usize>,
+
+    #[structopt(
+        help = "Set git config for git and commands run by corun, like git -c (KEY=VALUE)",
+        long = "git-config",
+        value_name = "key=value",
+        number_of_values = 1
+    )]
+    pub git_config: Vec<GitConfig>,
+
+    #[structopt(
+        help = "Write results and progress as a stream of JSON lines to a file, or - for stdout",
+        long = "json-lines"
+    )]
+    pub json_lines: Option<PathBuf>,
+
+    #[structopt(
+        help = "Name of the run, shown when listing past runs and in reports",
+        long = "label"
+    )]
+    pub label: Option<String>,
+
+    #[structopt(
+        help = "Format of commit dates in output and reports (relative, iso, local or format:STRFTIME)",
+        long = "date"
+    )]
+    pub date: Option<DateFormat>,
+
+    #[structopt(help = "Do not show summaries in a pager", long = "no-pager")]
+    pub no_pager: bool,
+
+    #[structopt(
+        help = "Use colors in output (default: color.ui)",
+        long = "color",
+        value_name = "when",
+        possible_values = ColorWhen::VALUES
+    )]
+    pub color: Option<ColorWhen>,
+
+    #[structopt(
+        help = "Do not use colors in output (same as --color never)",
+        long = "no-color",
+        conflicts_with = "color"
+    )]
+    pub no_color: bool,
+
+    #[structopt(
+        help = "Show statuses as words (e.g. FAIL exit 1) instead of symbols",
+        long = "no-unicode"
+    )]
+    pub no_unicode: bool,
+
+    #[structopt(
+        help = "Make output usable with screen readers and dumb terminals: statuses as words, no colors, and lines are never rewritten",
+        long = "screen-reader"
+    )]
+    pub screen_reader: bool,
+
+    #[structopt(
+        help = "Write status lines of commits to this file descriptor, e.g. 2 for stderr",
+        long = "status-fd",
+        value_name = "fd",
+        default_value = "1"
+    )]
+    pub status_fd: i32,
+
+    #[structopt(help = "Write results as a JSON report", long = "json")]
+    pub json: Option<PathBuf>,
+
+    #[structopt(
+        help = "Write the result of each commit to a file, named by a template like results/{short}.status",
+        long = "result-file",
+        value_name = "template"
+    )]
+    pub result_file: Option<String>,
+
+    #[structopt(help = "Write results as an HTML report", long = "html")]
+    pub html: Option<PathBuf>,
+
+    #[structopt(help = "Write results as CSV, e.g. for spreadsheets", long = "csv")]
+    pub csv: Option<PathBuf>,
+
+    #[structopt(
+        help = "Print a rebase todo list that adds a Tested-by trailer to the commits that passed",
+        long = "suggest-trailers"
+    )]
+    pub suggest_trailers: bool,
+
+    #[structopt(
+        help = "Print a summary of results grouped by author or by day of commit",
+        long = "group-by",
+        default_value = "none",
+        possible_values = GroupBy::VALUES
+    )]
+    pub group_by: GroupBy,
+
+    #[structopt(
+        help = "Print a summary of results grouped by the value of a commit trailer, e.g. Ticket",
+        long = "group-by-trailer"
+    )]
+    pub group_by_trailer: Option<String>,
+
+    #[structopt(
+        help = "Only fail on failures that did not also fail in a stored run or JSON report",
+        long = "baseline",
+        value_name = "run-id|json"
+    )]
+    pub baseline: Option<String>,
+
+    #[structopt(help = "Also run on commits from a saved commit set", long = "set")]
+    pub set: Option<String>,
+
+    #[structopt(
+        help = "Also run on commits from a past run, as a run id or a JSON report",
+        long = "from-report",
+        value_name = "run-id|json"
+    )]
+    pub from_report: Option<String>,
+
+    #[structopt(
+        help = "With --from-report, only run on commits with this status (failed for any failure, passed for success)",
+        long = "status",
+        value_name = "status",
+        number_of_values = 1,
+        possible_values = &["failed", "passed", "success", "failure", "inconclusive", "abort", "timeout", "oom", "skipped", "xfail", "xpass", "not-run", "cancelled"],
+        requires = "from-report"
+    )]
+    pub status: Vec<String>,
+
+    #[structopt(
+        help = "Also run on each step of a rebase todo list, as synthetic commits",
+        long = "plan",
+        value_name = "file"
+    )]
+    pub plan: Option<PathBuf>,
+
+    #[structopt(
+        help = "Also run on a commit split into one commit per top-level directory it changes (experimental)",
+        long = "split-by-path",
+        value_name = "commit"
+    )]
+    pub split_by_path: Option<String>,
+
+    #[structopt(
+        help = "Check that commits are a linear stack, each run with the commits before it",
+        long = "stack"
+    )]
+    pub stack: bool,
+
+    #[structopt(
+        help = "Run on each commit of a stack applied by itself onto the base of the stack",
+        long = "stack-independent"
+    )]
+    pub stack_independent: bool,
+
+    #[structopt(help = "List of commits to run on [default: HEAD]")]
+    pub commits: Vec<String>,
+
+    #[structopt(
+        help = "Shell command to run as a named step, after the steps it depends on (NAME[:DEP,...]=COMMAND)",
+        long = "step",
+        number_of_values = 1,
+        conflicts_with = "command"
+    )]
+    pub steps: Vec<StepSpec>,
+
+    #[structopt(
+        help = "Fail if any commits are not reachable from a revision, e.g. to catch a mistyped range",
+        long = "reachable-from",
+        value_name = "rev"
+    )]
+    pub reachable_from: Option<String>,
+
+    #[structopt(
+        help = "Fail if any commits are not reachable from HEAD (same as --reachable-from HEAD)",
+        long = "ours",
+        conflicts_with = "reachable-from"
+    )]
+    pub ours: bool,
+
+    #[structopt(
+        help = "Run the usual tests of the project, detected from the files in each commit",
+        long = "auto",
+        conflicts_with_all = &["command", "steps"]
+    )]
+    pub auto: bool,
+
+    #[structopt(
+        help = "Command to execute",
+        required_unless_one = &["steps", "auto"],
+        last = true
+    )]
+    pub command: Vec<String>,
+}
+
+value_enum! {
+    pub enum OnRefChange {
+        /// Keep running on the original commits.
+        Ignore => "ignore",
+        /// Keep running on the original commits, but print a warning.
+        Warn => "warn",
+        /// Stop running.
+        Abort => "abort",
+        /// Expand the commits again, and continue with the commits not yet run on.
+        Restart => "restart",
+    }
+}
+
+value_enum! {
+    pub enum OnFailure {
+        /// Continue with the next command.
+        Continue => "continue",
+        /// Start an interactive shell in the checkout, and continue when it exits.
+        Shell => "shell",
+    }
+}
+
+value_enum! {
+    pub enum CargoTarget {
+        /// One directory for all commits of a run, so builds are incremental.
+        Shared => "shared",
+        /// A new directory for each commit, which is kept after the run.
+        PerCommit => "per-commit",
+    }
+}
+
+value_enum! {
+    pub enum Prefetch {
+        /// `cargo fetch`, and run with `CARGO_NET_OFFLINE`.
+        Cargo => "cargo",
+        /// `npm ci`, and run with `npm_config_offline`.
+        Npm => "npm",
+        /// `pip download` of `requirements.txt`, and run with `PIP_NO_INDEX`.
+        Pip => "pip",
+    }
+}
+
+value_enum! {
+    pub enum ColorWhen {
+        /// Only when output goes to a terminal.
+        Auto => "auto",
+        Always => "always",
+        Never => "never",
+    }
+}
+
+value_enum! {
+    pub enum QueryFormat {
+        /// Columns aligned in a table.
+        Table => "table",
+        Csv => "csv",
+        Json => "json",
+    }
+}
+
+value_enum! {
+    pub enum GroupBy {
+        None => "none",
+        Author => "author",
+        /// Day of the author date.
+        Day => "day",
+    }
+}
+
+value_enum! {
+    pub enum VcsKind {
+        Git => "git",
+        /// Jujutsu, for repositories colocated with git.
+        Jj => "jj",
+        /// Mercurial.
+        Hg => "hg",
+    }
+}
+
+/// An environment variable with a list of values to run with.
+#[derive(Clone, Debug)]
+pub struct MatrixDimension {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+impl std::str::FromStr for MatrixDimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(values)) if !name.is_empty() => Ok(MatrixDimension {
+                name: name.to_string(),
+                values: values.split(',').map(str::to_string).collect(),
+            }),
+            _ => Err(format!("expected NAME=VALUE,..., got: {}", s)),
+        }
+    }
+}
+
+/// A list of CPUs, e.g. `0,2,4-7`.
+#[derive(Clone, Debug)]
+pub struct CpuList(pub Vec<usize>);
+
+impl std::str::FromStr for CpuList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let parse = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid CPU: {}", cpu))
+        };
+
+        let mut cpus = Vec::new();
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => cpus.extend(parse(first)?..=parse(last)?),
+                None => cpus.push(parse(part)?),
+            }
+        }
+        Ok(CpuList(cpus))
+    }
+}
+
+/// A git config setting, as given to `git -c`.
+#[derive(Clone, Debug)]
+pub struct GitConfig {
+    pub key: String,
+    pub value: String,
+}
+
+impl std::str::FromStr for GitConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) if key.contains('.') => Ok(GitConfig {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!("expected SECTION.KEY=VALUE, got: {}", s)),
+        }
+    }
+}
+
+/// Which commits to run on first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Newest commits first.
+    Recent,
+    /// Commits that change files matching a pathspec first.
+    Touching(String),
+    /// Commits in random order.
+    Random,
+    /// Commits that are most likely to break something first.
+    Candidates,
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "recent" => Ok(Priority::Recent),
+            "random" => Ok(Priority::Random),
+            "candidates" => Ok(Priority::Candidates),
+            _ => match s.strip_prefix("touching:") {
+                Some(pathspec) if !pathspec.is_empty() => {
+                    Ok(Priority::Touching(pathspec.to_string()))
+                }
+                _ => Err(format!(
+                    "expected recent, random, candidates or touching:PATHSPEC, got: {}",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// How to show commit dates, like `git log --date`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DateFormat {
+    /// Relative to the current time, e.g. `2 hours ago`.
+    Relative,
+    /// ISO 8601-like, in the time zone of the commit.
+    Iso,
+    /// In the local time zone.
+    Local,
+    /// A custom `strftime` format.
+    Format(String),
+}
+
+impl Default for DateFormat {
+    /// Day, month, year and time, e.g. ` 5 Oct 2020 14:03`.
+    fn default() -> Self {
+        DateFormat::Format("%e %b %Y %H:%M".to_string())
+    }
+}
+
+impl std::str::FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "relative" => Ok(DateFormat::Relative),
+            "iso" => Ok(DateFormat::Iso),
+            "local" => Ok(DateFormat::Local),
+            _ => match s.strip_prefix("format:") {
+                Some(format) => Ok(DateFormat::Format(format.to_string())),
+                _ => Err(format!(
+                    "expected relative, iso, local or format:STRFTIME, got: {}",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// When to stop starting to run on more commits.
+#[derive(Clone, Debug)]
+pub enum Deadline {
+    /// After running for this long.
+    After(Duration),
+    /// At a time of day, as hours and minutes.
+    At(u32, u32),
+}
+
+impl std::str::FromStr for Deadline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (hours, minutes) = match s.split_once(':') {
+            Some(time) => time,
+            None => return parse_duration(s).map(Deadline::After),
+        };
+        match (hours.parse(), minutes.parse()) {
+            (Ok(hour @ 0..=23), Ok(minute @ 0..=59)) if minutes.len() == 2 => {
+                Ok(Deadline::At(hour, minute))
+            }
+            _ => Err(format!(
+                "expected a duration or a time like 06:30, got: {}",
+                s
+            )),
+        }
+    }
+}
+
+/// A named shell command that depends on other steps.
+#[derive(Clone, Debug)]
+pub struct StepSpec {
+    pub name: String,
+    pub deps: Vec<String>,
+    pub command: String,
+}
+
+impl std::str::FromStr for StepSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.splitn(2, '=');
+        let (name, command) = match (parts.next(), parts.next()) {
+            (Some(name), Some(command)) if !name.is_empty() => (name, command),
+            _ => return Err(format!("expected NAME[:DEP,...]=COMMAND, got: {}", s)),
+        };
+
+        let mut parts = name.splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        let deps = match parts.next() {
+            Some(deps) => deps.split(',').map(str::to_string).collect(),
+            None => Vec::new(),
+        };
+
+        Ok(StepSpec {
+            name: name.to_string(),
+            deps,
+            command: command.to_string(),
+        })
+    }
+}
+
+/// Parse a duration in seconds, or with a unit (`s`, `m`, `h` or `d`).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(index) => s.split_at(index),
+        None => (s, "s"),
+    };
+    let factor = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => return Err(format!("invalid duration unit: {}", unit)),
+    };
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("invalid duration: {}", s))?;
+    Duration::try_from_secs_f64(number * factor).map_err(|_| format!("invalid duration: {}", s))
+}
+
+/// Parse a size in bytes, or with a unit (`K`, `M` or `G`).
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => s.split_at(index),
+        None => (s, ""),
+    };
+    let factor = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(format!("invalid size unit: {}", unit)),
+    };
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size: {}", s))?;
+    number
+        .checked_mul(factor)
+        .ok_or_else(|| format!("size too large: {}", s))
+}
+
+/// Full command line interface, including subcommands, for completions and
+/// documentation.
+pub fn app() -> App<'static, 'static> {
+    let mut app = Options::clap().name("git-corun");
+    for subcommand in Cli::clap().p.subcommands {
+        if !subcommand.get_name().starts_with("__") {
+            app = app.subcommand(subcommand);
+        }
+    }
+    app
+}
+
+pub fn write_completions(shell: Shell, out: &mut dyn Write) -> io::Result<()> {
+    let mut app = app();
+    let mut script = Vec::new();
+    app.gen_completions_to("git-corun", shell, &mut script);
+    let mut script = String::from_utf8_lossy(&script).into_owned();
+
+    // complete revisions dynamically instead of file names
+    if let Shell::Zsh = shell {
+        for arg in &["::commits", ":revs"] {
+            script = script
+                .lines()
+                .map(|line| {
+                    if line.starts_with(&format!("'{} -- ", arg)) {
+                        line.replace(":_files'", ":_git_corun_revs'")
+                    } else {
+                        line.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        script.push('\n');
+        script.push_str(concat!(
+            "\n",
+            "(( $+functions[_git_corun_revs] )) ||\n",
+            "_git_corun_revs() {\n",
+            "    local -a revs\n",
+            "    revs=(${(f)\"$(git-corun __complete-revs \"$PREFIX\" 2>/dev/null)\"})\n",
+            "    compadd -Q -a revs\n",
+            "}\n",
+        ));
+    }
+    out.write_all(script.as_bytes())?;
+
+    // completions for `git corun`, which git's own completion scripts look up
+    match shell {
+        Shell::Bash => {
+            let flags = long_flags(&app).join(" ");
+            writeln!(out)?;
+            writeln!(out, "_git_corun ()")?;
+            writeln!(out, "{{")?;
+            writeln!(out, "\tcase \"$cur\" in")?;
+            writeln!(out, "\t-*) __gitcomp \"{}\" ;;", flags)?;
+            writeln!(
+                out,
+                "\t*) __gitcomp_nl \"$(git corun __complete-revs \"$cur\" 2>/dev/null)\" \"\" \"$cur\" ;;"
+            )?;
+            writeln!(out, "\tesac")?;
+            writeln!(out, "}}")?;
+        }
+        Shell::Fish => {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "complete -c git-corun -f -a '(git-corun __complete-revs (commandline -ct) 2>/dev/null)'"
+            )?;
+            writeln!(
+                out,
+                "complete -c git -n '__fish_git_using_command corun' -f -a '(git corun __complete-revs (commandline -ct) 2>/dev/null)'"
+            )?;
+            for flag in long_flags(&app) {
+                writeln!(
+                    out,
+                    "complete -c git -n '__fish_git_using_command corun' -l '{}'",
+                    flag.trim_start_matches('-').trim_end_matches('=')
+                )?;
+            }
+        }
+        // zsh completion for `git corun` uses the `_git-corun` function
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn long_flags(app: &App) -> Vec<String> {
+    let flags = app
+        .p
+        .flags
+        .iter()
+        .flat_map(|flag| flag.s.long)
+        .map(|long| format!("--{}", long));
+    let opts = app
+        .p
+        .opts
+        .iter()
+        .flat_map(|opt| opt.s.long)
+        .map(|long| format!("--{}=", long));
+    flags.chain(opts).collect()
+}
@@ -0,0 +1,53 @@
+//! Compressing logs with zstd once commands are done writing them, since runs
+//! on many commits with verbose output can take up a lot of space.
+//!
+//! This uses the `zstd` command, so that corun does not need to be built with
+//! a compression library. Compressed logs have a `.zst` suffix added, and can
+//! be read with e.g. `zstdcat` or `zstdless`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+pub const COMMAND: &str = "zstd";
+
+/// Compress a file in place, replacing it with a `.zst` file. Files that do not
+/// exist are ignored.
+pub fn compress(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    zstd(&["-q", "-f", "--rm"], path)
+}
+
+/// Decompress all `.zst` files in a directory in place, returning how many
+/// there were.
+pub fn decompress_dir(dir: &Path) -> io::Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "zst") {
+            zstd(&["-d", "-q", "-f", "--rm"], &path)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn zstd(args: &[&str], path: &Path) -> io::Result<()> {
+    let output = Command::new(COMMAND)
+        .args(args)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} failed on {}: {}",
+            COMMAND,
+            path.to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Running commands in a container, with `--dockerfile`, in an image that is
+//! built from the Dockerfile at each commit, so that each commit is tested in
+//! the environment it would ship with rather than the one of the host.
+//!
+//! The image is built in a step of its own before the other steps, which
+//! depend on it, so that a Dockerfile that does not build is told apart from
+//! commands that fail. docker caches the layers of the images, so only the
+//! layers that changed since the commit before are built again. The checkout
+//! is mounted in the container at the same path, and commands run as the user
+//! that runs corun, so that files they write can be cleaned up afterwards. The
+//! shared download caches are mounted the same way, so that `--prefetch` and
+//! the cache options work as they do outside of containers.
+
+use std::path::{Path, PathBuf};
+
+use crate::runner;
+use crate::steps::Step;
+
+pub const COMMAND: &str = "docker";
+
+/// Name of the step that builds the image.
+pub const STEP_NAME: &str = "image";
+
+/// Add a step that builds the image from a Dockerfile before the steps, which
+/// the steps depend on.
+pub fn add_build_step(dockerfile: &Path, steps: Vec<Step>) -> Vec<Step> {
+    let mut with_build = vec![Step {
+        name: STEP_NAME.to_string(),
+        deps: Vec::new(),
+        command: vec![
+            COMMAND.to_string(),
+            "build".to_string(),
+            "--file".to_string(),
+            dockerfile.to_string_lossy().into_owned(),
+            ".".to_string(),
+        ],
+        shell: false,
+        env: Vec::new(),
+    }];
+    with_build.extend(steps.into_iter().map(|mut step| {
+        step.deps = std::iter::once(0)
+            .chain(step.deps.iter().map(|dep| dep + 1))
+            .collect();
+        step
+    }));
+    with_build
+}
+
+/// Command to run a step with. The step that builds the image writes its ID
+/// to a file, and the other steps run in the image with that ID, with the
+/// directories given mounted at the same path, and with the environment
+/// variables given (by name, with the values from the environment of the
+/// command).
+pub fn command(
+    step: &Step,
+    shell: Option<&str>,
+    image_id_path: &Path,
+    work_tree: &Path,
+    mounts: &[PathBuf],
+    dir: &Path,
+    env: &[(String, String)],
+) -> Vec<String> {
+    if step.name == STEP_NAME {
+        let mut command = step.command.clone();
+        command.push("--iidfile".to_string());
+        command.push(image_id_path.to_string_lossy().into_owned());
+        return command;
+    }
+
+    let image = std::fs::read_to_string(image_id_path).unwrap_or_default();
+    let work_tree = work_tree.to_string_lossy();
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let mut command = vec![
+        COMMAND.to_string(),
+        "run".to_string(),
+        "--rm".to_string(),
+        "--init".to_string(),
+        "--volume".to_string(),
+        format!("{}:{}", work_tree, work_tree),
+    ];
+    for mount in mounts {
+        let mount = mount.to_string_lossy();
+        command.push("--volume".to_string());
+        command.push(format!("{}:{}", mount, mount));
+    }
+    command.extend(vec![
+        "--workdir".to_string(),
+        dir.to_string_lossy().into_owned(),
+        "--user".to_string(),
+        format!("{}:{}", uid, gid),
+    ]);
+    for (name, _) in env {
+        command.push("--env".to_string());
+        command.push(name.clone());
+    }
+    command.push(image.trim().to_string());
+    match shell {
+        Some(shell) => {
+            command.push(shell.to_string());
+            command.extend(
+                runner::shell_args(shell, &step.command)
+                    .into_iter()
+                    .map(str::to_string),
+            );
+        }
+        None => command.extend(step.command.iter().cloned()),
+    }
+    command
+}
@@ -0,0 +1,49 @@
+//! Stream of events as JSON lines, for other programs to follow a run.
+
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::report::RunResult;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event<'a> {
+    /// A command was run on a commit.
+    Result(&'a RunResult),
+    /// Progress of the run, after each commit.
+    Progress {
+        done: usize,
+        total: usize,
+        failed: usize,
+        /// Estimated time left, in seconds.
+        remaining: Option<f64>,
+        /// Estimated time the run will be done, in RFC 3339 format.
+        eta: Option<String>,
+    },
+}
+
+pub struct EventWriter {
+    out: LineWriter<Box<dyn Write>>,
+}
+
+impl EventWriter {
+    /// Write events to a file, or to stdout if the path is `-`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let out: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(path)?)
+        };
+        Ok(EventWriter {
+            out: LineWriter::new(out),
+        })
+    }
+
+    pub fn write(&mut self, event: &Event) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, event)?;
+        writeln!(self.out)
+    }
+}
@@ -0,0 +1,95 @@
+//! Failures that are expected, which are shown as XFAIL instead of as failures,
+//! e.g. on old commits that are known to fail in a way that does not matter.
+//! Commits that are expected to fail but pass are shown as XPASS, so that the
+//! expectations can be updated.
+//!
+//! These are listed in the `corun.expectedFailure` git config, either as a
+//! revision or range, optionally followed by a reason, or as `signature:`
+//! followed by a regular expression that matches error signatures (see
+//! `signature`):
+//!
+//! ```text
+//! [corun]
+//!     expectedFailure = v1.0..v1.1 tests need a network connection
+//!     expectedFailure = "signature:^test net::\\S+ \\.\\.\\. FAILED"
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+
+use regex::Regex;
+
+use crate::git;
+use crate::status::Status;
+use crate::vcs::Vcs;
+
+#[derive(Default)]
+pub struct ExpectedFailures {
+    /// Commits that are expected to fail, with the reason.
+    commits: HashMap<String, String>,
+    /// Patterns of error signatures of failures that are expected.
+    signatures: Vec<Regex>,
+}
+
+impl ExpectedFailures {
+    /// Get the expected failures from the git config of a repository.
+    pub fn load(vcs: &dyn Vcs) -> io::Result<Self> {
+        let git_dir = match vcs.git_dir() {
+            Some(git_dir) => git_dir,
+            None => return Ok(ExpectedFailures::default()),
+        };
+
+        let mut expected = ExpectedFailures::default();
+        for line in git::get_config_values(git_dir, "corun.expectedFailure")? {
+            let line = line.trim();
+            if let Some(pattern) = line.strip_prefix("signature:") {
+                let regex = Regex::new(pattern).map_err(|err| {
+                    io::Error::other(format!("corun.expectedFailure {}: {}", line, err))
+                })?;
+                expected.signatures.push(regex);
+                continue;
+            }
+
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let rev = parts.next().unwrap_or_default();
+            let reason = parts.next().unwrap_or_default().trim();
+            let commits = vcs.expand_commits(rev).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("corun.expectedFailure {}: {}", rev, err),
+                )
+            })?;
+            for commit in commits {
+                expected.commits.insert(commit, reason.to_string());
+            }
+        }
+        Ok(expected)
+    }
+
+    /// Status of a command with expected failures as XFAIL, and passes that
+    /// were expected to fail as XPASS, along with the reason if there is one.
+    pub fn classify(
+        &self,
+        commit: &str,
+        status: Status,
+        signature: Option<&str>,
+    ) -> (Status, Option<&str>) {
+        let reason = self.commits.get(commit).map(String::as_str);
+        match status {
+            Status::Success(code) if reason.is_some() => (Status::Xpass(code), reason),
+            _ if status.is_failure() => {
+                let signature_expected = signature.is_some_and(|signature| {
+                    self.signatures
+                        .iter()
+                        .any(|pattern| pattern.is_match(signature))
+                });
+                if reason.is_some() || signature_expected {
+                    (Status::Xfail(status.code()), reason)
+                } else {
+                    (status, None)
+                }
+            }
+            _ => (status, None),
+        }
+    }
+}
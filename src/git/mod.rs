@@ -1,15 +1,97 @@
-use std::io;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
 
 #[macro_use]
 pub mod run;
 
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::term;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StashEntry {
+    /// Name of the stash entry, e.g. `stash@{0}`.
+    pub name: String,
+    /// Commit hash of the stash entry.
+    pub commit: String,
+    /// Commit hash of the commit the stash entry was created on.
+    pub parent: String,
+    /// Patch with the changes of the stash entry, written to the directory of
+    /// the run that applied it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<PathBuf>,
+}
+
 pub fn get_git_dir() -> io::Result<PathBuf> {
     let git_dir = gitc!("rev-parse", "--git-dir")?;
     Ok(PathBuf::from(git_dir))
 }
 
+pub fn get_work_tree() -> io::Result<PathBuf> {
+    let work_tree = gitc!("rev-parse", "--show-toplevel")?;
+    Ok(PathBuf::from(work_tree))
+}
+
+/// Get a boolean config key, as git interprets it, or `None` if it is not set.
+pub fn get_config_bool(
+    git_dir: impl AsRef<Path>,
+    key: impl AsRef<str>,
+) -> io::Result<Option<bool>> {
+    let git_dir = git_dir.as_ref();
+    let key = key.as_ref();
+
+    let args = gitc_args!("--git-dir", git_dir, "config", "--bool", "--get", key);
+    run::trace("git", args);
+    let output = Command::new("git").args(args).output()?;
+
+    match output.status.code() {
+        Some(0) => Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim() == "true",
+        )),
+        Some(1) => Ok(None),
+        _ => {
+            let error = String::from_utf8_lossy(&output.stderr);
+            let error = error.trim_end_matches('\n');
+            Err(io::Error::other(format!("git error: {}", error)))
+        }
+    }
+}
+
+/// Get all values of a multi-valued config key, which is empty if it is not set.
+pub fn get_config_values(
+    git_dir: impl AsRef<Path>,
+    key: impl AsRef<str>,
+) -> io::Result<Vec<String>> {
+    let git_dir = git_dir.as_ref();
+    let key = key.as_ref();
+
+    let args = gitc_args!("--git-dir", git_dir, "config", "--get-all", key);
+    run::trace("git", args);
+    let output = Command::new("git").args(args).output()?;
+
+    // git config exits with 1 if the key is not set
+    match output.status.code() {
+        Some(0) => Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect()),
+        Some(1) => Ok(Vec::new()),
+        _ => {
+            let error = String::from_utf8_lossy(&output.stderr);
+            let error = error.trim_end_matches('\n');
+            Err(io::Error::other(format!("git error: {}", error)))
+        }
+    }
+}
+
 pub fn get_commit_hash(
     git_dir: impl AsRef<Path>,
     commit_ref: impl AsRef<str>,
@@ -21,6 +103,11 @@ pub fn get_commit_hash(
     Ok(git_hash)
 }
 
+/// Get the commits of a revision or range, oldest first.
+///
+/// Single revisions are peeled to the commit they point to, so that e.g.
+/// annotated tags give the tagged commit rather than the tag object, and
+/// abbreviated hashes that are ambiguous give an error listing the candidates.
 pub fn get_commit_hashes(
     git_dir: impl AsRef<Path>,
     ref_or_range: impl AsRef<str>,
@@ -28,8 +115,14 @@ pub fn get_commit_hashes(
     let git_dir = git_dir.as_ref();
     let ref_or_range = ref_or_range.as_ref();
 
-    if !ref_or_range.contains("..") {
-        let git_hash = get_commit_hash(git_dir, ref_or_range)?;
+    // `A..B` and `A...B`, and `A^!`, `A^@` and `A^-N`, which are also ranges
+    let is_range = ref_or_range.contains("..")
+        || ref_or_range.ends_with("^!")
+        || ref_or_range.ends_with("^@")
+        || ref_or_range.contains("^-");
+    if !is_range {
+        let commit = format!("{}^{{commit}}", ref_or_range);
+        let git_hash = gitc!("--git-dir", git_dir, "rev-parse", "--verify", commit)?;
         return Ok(vec![git_hash]);
     }
 
@@ -41,6 +134,454 @@ pub fn get_commit_hashes(
         .collect())
 }
 
+/// Commits that a ref or the ends of a range point to, as with `git rev-parse`
+/// (with `^` before the commits that are excluded), without walking the range.
+pub fn get_range_ends(
+    git_dir: impl AsRef<Path>,
+    ref_or_range: impl AsRef<str>,
+) -> io::Result<Vec<String>> {
+    let git_dir = git_dir.as_ref();
+    let ref_or_range = ref_or_range.as_ref();
+
+    let git_hashes = gitc!("--git-dir", git_dir, "rev-parse", ref_or_range)?;
+    Ok(git_hashes
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Walk the commits of a range in the background, newest first, so that they
+/// can be used before all of them are found.
+pub fn stream_commit_hashes(
+    git_dir: impl AsRef<Path>,
+    range: impl AsRef<str>,
+) -> io::Result<mpsc::Receiver<io::Result<String>>> {
+    let git_dir = git_dir.as_ref();
+    let range = range.as_ref();
+
+    let args = gitc_args!("--git-dir", git_dir, "rev-list", range);
+    run::trace("git", args);
+    let mut child = Command::new("git")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            // stop walking when the commits are no longer needed
+            if sender.send(line).is_err() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+        }
+        match child.wait_with_output() {
+            Ok(output) if !output.status.success() => {
+                let error = String::from_utf8_lossy(&output.stderr);
+                let error = error.trim_end_matches('\n');
+                let _ = sender.send(Err(io::Error::other(format!("git error: {}", error))));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                let _ = sender.send(Err(err));
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+/// Files changed by commits (compared to their first parent), with the number
+/// of lines added and removed in each, where binary files count as one line.
+#[allow(clippy::type_complexity)]
+pub fn changed_lines(
+    git_dir: impl AsRef<Path>,
+    commits: &[String],
+) -> io::Result<Vec<(String, Vec<(String, u32)>)>> {
+    let git_dir = git_dir.as_ref();
+
+    let input = commits.join("\n") + "\n";
+    let output = gitc_with_input!(
+        input;
+        "-c",
+        "core.quotePath=false",
+        "--git-dir",
+        git_dir,
+        "log",
+        "--no-walk=unsorted",
+        "--stdin",
+        "--format=%H",
+        "--numstat",
+        "--no-renames",
+        "--diff-merges=first-parent"
+    )?;
+
+    let mut changes: Vec<(String, Vec<(String, u32)>)> = Vec::new();
+    for line in output.lines().filter(|line| !line.is_empty()) {
+        let mut fields = line.splitn(3, '\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(added), Some(removed), Some(path)) => {
+                let lines = added.parse::<u32>().unwrap_or(1) + removed.parse::<u32>().unwrap_or(0);
+                if let Some((_, paths)) = changes.last_mut() {
+                    paths.push((path.to_string(), lines));
+                }
+            }
+            _ => changes.push((line.to_string(), Vec::new())),
+        }
+    }
+    Ok(changes)
+}
+
+/// Sort commits by commit date, newest first.
+pub fn sort_commits_by_date(
+    git_dir: impl AsRef<Path>,
+    commits: &[String],
+) -> io::Result<Vec<String>> {
+    let git_dir = git_dir.as_ref();
+
+    let input = commits.join("\n") + "\n";
+    let output = gitc_with_input!(input; "--git-dir", git_dir, "log", "--no-walk", "--stdin", "--format=%H")?;
+    Ok(output
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Get the commit date (as a Unix timestamp) and the parents of commits.
+pub fn get_commit_dates_and_parents(
+    git_dir: impl AsRef<Path>,
+    commits: &[String],
+) -> io::Result<HashMap<String, (i64, Vec<String>)>> {
+    let git_dir = git_dir.as_ref();
+
+    let input = commits.join("\n") + "\n";
+    let output = gitc_with_input!(
+        input;
+        "--git-dir",
+        git_dir,
+        "log",
+        "--no-walk=unsorted",
+        "--stdin",
+        "--format=%H %ct %P"
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let commit = fields.next()?.to_string();
+            let date = fields.next()?.parse().ok()?;
+            Some((commit, (date, fields.map(str::to_string).collect())))
+        })
+        .collect())
+}
+
+/// Get the commits that change any files matching a pathspec.
+pub fn get_commits_touching(
+    git_dir: impl AsRef<Path>,
+    commits: &[String],
+    pathspec: impl AsRef<str>,
+) -> io::Result<HashSet<String>> {
+    let git_dir = git_dir.as_ref();
+    let pathspec = pathspec.as_ref();
+
+    let input = commits.join("\n") + "\n";
+    let output = gitc_with_input!(
+        input;
+        "--git-dir",
+        git_dir,
+        "log",
+        "--no-walk",
+        "--stdin",
+        "--format=%H",
+        "--",
+        pathspec
+    )?;
+    Ok(output
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Get the commits that are not reachable from a revision, i.e. are not it or
+/// one of its ancestors.
+pub fn get_commits_not_reachable_from(
+    git_dir: impl AsRef<Path>,
+    commits: &[String],
+    rev: impl AsRef<str>,
+) -> io::Result<HashSet<String>> {
+    let git_dir = git_dir.as_ref();
+    let commit = get_commit_hash(git_dir, format!("{}^{{commit}}", rev.as_ref()))
+        .map_err(|_| io::Error::other(format!("not a commit: {}", rev.as_ref())))?;
+
+    let input = commits.join("\n") + "\n";
+    let exclude = format!("^{}", commit);
+    let output = gitc_with_input!(
+        input;
+        "--git-dir",
+        git_dir,
+        "rev-list",
+        "--use-bitmap-index",
+        "--stdin",
+        exclude
+    )?;
+    Ok(output
+        .split('\n')
+        .filter(|line| commits.iter().any(|commit| commit == line))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Check whether any files in some paths differ between two commits.
+pub fn paths_differ(
+    git_dir: impl AsRef<Path>,
+    from: impl AsRef<str>,
+    to: impl AsRef<str>,
+    paths: &[PathBuf],
+) -> io::Result<bool> {
+    let git_dir = git_dir.as_ref();
+    let (from, to) = (from.as_ref(), to.as_ref());
+
+    let output = gitc!(
+        "--git-dir",
+        git_dir,
+        "diff",
+        "--name-only",
+        from,
+        to,
+        "--",
+        ..paths
+    )?;
+    Ok(!output.trim().is_empty())
+}
+
+pub fn list_stashes(git_dir: impl AsRef<Path>) -> io::Result<Vec<StashEntry>> {
+    let git_dir = git_dir.as_ref();
+
+    let stashes = gitc!("--git-dir", git_dir, "stash", "list", "--format=%gd %H %P")?;
+    Ok(stashes
+        .split('\n')
+        .flat_map(|line| {
+            let mut parts = line.split(' ');
+            let name = parts.next()?.to_string();
+            let commit = parts.next()?.to_string();
+            let parent = parts.next()?.to_string();
+            Some(StashEntry {
+                name,
+                commit,
+                parent,
+                patch: None,
+            })
+        })
+        .collect())
+}
+
+/// Write the changes of a stash entry as a patch, including untracked files.
+/// The output is written as it is, since the changes need not be UTF-8.
+pub fn write_stash_patch(
+    git_dir: impl AsRef<Path>,
+    commit: impl AsRef<str>,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let args = [
+        OsStr::new("--git-dir"),
+        git_dir.as_ref().as_os_str(),
+        OsStr::new("stash"),
+        OsStr::new("show"),
+        OsStr::new("--patch"),
+        OsStr::new("--binary"),
+        OsStr::new("--include-untracked"),
+        OsStr::new(commit.as_ref()),
+    ];
+    run::trace("git", &args);
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "git error: {}",
+            error.trim_end_matches('\n')
+        )));
+    }
+    fs::write(path, output.stdout)
+}
+
+/// Save a set of commits as a ref, pointing at a commit whose parents are the
+/// commits of the set, so that they are kept from being garbage collected and
+/// are pushed and fetched along with the ref.
+pub fn save_commit_set(
+    git_dir: impl AsRef<Path>,
+    name: impl AsRef<str>,
+    commits: &[String],
+) -> io::Result<()> {
+    let git_dir = git_dir.as_ref();
+    let name = name.as_ref();
+    let ref_name = format!("refs/corun/sets/{}", name);
+
+    if gitc!("check-ref-format", ref_name).is_err() {
+        return Err(io::Error::other(format!(
+            "invalid commit set name: {}",
+            name
+        )));
+    }
+
+    // the parents are written in a commit object directly, since there can be
+    // more of them than fit on a command line
+    let tree = gitc_with_input!(""; "--git-dir", git_dir, "mktree")?;
+    let timestamp = Utc::now().timestamp();
+    let mut contents = format!("tree {}\n", tree);
+    for commit in commits {
+        contents += &format!("parent {}\n", commit);
+    }
+    contents += &format!(
+        "author git-corun <> {} +0000\ncommitter git-corun <> {} +0000\n\ncorun commit set {}\n",
+        timestamp, timestamp, name
+    );
+    let set_commit = gitc_with_input!(contents; "--git-dir", git_dir, "hash-object", "-t", "commit", "-w", "--stdin")?;
+    gitc!("--git-dir", git_dir, "update-ref", ref_name, set_commit)?;
+    Ok(())
+}
+
+pub fn get_commit_set(git_dir: impl AsRef<Path>, name: impl AsRef<str>) -> io::Result<Vec<String>> {
+    let git_dir = git_dir.as_ref();
+    let name = name.as_ref();
+    let ref_name = format!("refs/corun/sets/{}", name);
+
+    let not_found = || io::Error::other(format!("no such commit set: {}", name));
+    let kind = gitc!("--git-dir", git_dir, "cat-file", "-t", ref_name).map_err(|_| not_found())?;
+    let contents = if kind == "blob" {
+        // sets were saved as a list of commits before
+        gitc!("--git-dir", git_dir, "cat-file", "blob", ref_name)
+    } else {
+        gitc!(
+            "--git-dir",
+            git_dir,
+            "show",
+            "--no-patch",
+            "--format=%P",
+            ref_name
+        )
+    }
+    .map_err(|_| not_found())?;
+    Ok(contents.split_whitespace().map(str::to_string).collect())
+}
+
+pub fn list_commit_sets(git_dir: impl AsRef<Path>) -> io::Result<Vec<String>> {
+    let git_dir = git_dir.as_ref();
+
+    let names = gitc!(
+        "--git-dir",
+        git_dir,
+        "for-each-ref",
+        "--format=%(refname:lstrip=3)",
+        "refs/corun/sets/"
+    )?;
+    Ok(names
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Keep a commit from being garbage collected, e.g. a stash entry that a run
+/// used, even after it is dropped.
+pub fn keep_commit(git_dir: impl AsRef<Path>, commit: impl AsRef<str>) -> io::Result<()> {
+    let git_dir = git_dir.as_ref();
+    let commit = commit.as_ref();
+
+    let keep_ref = format!("refs/corun/keep/{}", commit);
+    gitc!("--git-dir", git_dir, "update-ref", keep_ref, commit)?;
+    Ok(())
+}
+
+/// Stop keeping commits with `keep_commit`, except the given ones.
+pub fn drop_kept_commits(git_dir: impl AsRef<Path>, keep: &HashSet<&str>) -> io::Result<usize> {
+    let git_dir = git_dir.as_ref();
+
+    let kept = gitc!(
+        "--git-dir",
+        git_dir,
+        "for-each-ref",
+        "--format=%(refname)",
+        "refs/corun/keep/"
+    )?;
+    let input = kept
+        .lines()
+        .filter(|keep_ref| !keep.contains(keep_ref.trim_start_matches("refs/corun/keep/")))
+        .map(|keep_ref| format!("delete {}\n", keep_ref))
+        .collect::<String>();
+    if !input.is_empty() {
+        gitc_with_input!(&input; "--git-dir", git_dir, "update-ref", "--stdin")?;
+    }
+    Ok(input.lines().count())
+}
+
+/// Mark a commit as passing or failing, replacing any previous mark.
+pub fn mark_commit(
+    git_dir: impl AsRef<Path>,
+    commit: impl AsRef<str>,
+    pass: bool,
+) -> io::Result<()> {
+    let git_dir = git_dir.as_ref();
+    let commit = commit.as_ref();
+
+    let (mark, other) = if pass {
+        ("pass", "fail")
+    } else {
+        ("fail", "pass")
+    };
+    let mark_ref = format!("refs/corun/{}/{}", mark, commit);
+    let other_ref = format!("refs/corun/{}/{}", other, commit);
+
+    gitc!("--git-dir", git_dir, "update-ref", mark_ref, commit)?;
+    gitc!("--git-dir", git_dir, "update-ref", "-d", other_ref)?;
+    Ok(())
+}
+
+/// Get the commits that are marked as passing.
+pub fn get_passing_commits(git_dir: impl AsRef<Path>) -> io::Result<HashSet<String>> {
+    let git_dir = git_dir.as_ref();
+
+    let commits = gitc!(
+        "--git-dir",
+        git_dir,
+        "for-each-ref",
+        "--format=%(objectname)",
+        "refs/corun/pass/"
+    )?;
+    Ok(commits
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// List branches, tags and recent commits, for completing revision arguments.
+pub fn list_completion_revs(git_dir: impl AsRef<Path>) -> io::Result<Vec<String>> {
+    let git_dir = git_dir.as_ref();
+
+    let refs = gitc!(
+        "--git-dir",
+        git_dir,
+        "for-each-ref",
+        "--format=%(refname:short)",
+        "refs/heads/",
+        "refs/tags/",
+        "refs/remotes/"
+    )?;
+    let commits = gitc!("--git-dir", git_dir, "log", "--max-count=20", "--format=%h")?;
+
+    Ok(std::iter::once("HEAD")
+        .chain(refs.split('\n'))
+        .chain(commits.split('\n'))
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
 pub fn clone_local(src_dir: impl AsRef<Path>, dst_dir: impl AsRef<Path>) -> io::Result<()> {
     let src_dir = src_dir.as_ref();
     let dst_dir = dst_dir.as_ref();
@@ -49,6 +590,44 @@ pub fn clone_local(src_dir: impl AsRef<Path>, dst_dir: impl AsRef<Path>) -> io::
     Ok(())
 }
 
+/// Check whether a repository has a commit-graph file, which makes walking
+/// the history much faster in large repositories.
+pub fn has_commit_graph(git_dir: impl AsRef<Path>) -> bool {
+    let info_dir = git_dir.as_ref().join("objects").join("info");
+    info_dir.join("commit-graph").is_file() || info_dir.join("commit-graphs").is_dir()
+}
+
+/// Write a commit-graph file for all commits in a repository.
+pub fn write_commit_graph(work_dir: impl AsRef<Path>) -> io::Result<()> {
+    let work_dir = work_dir.as_ref();
+
+    gitc!("-C", work_dir, "commit-graph", "write", "--reachable")?;
+    Ok(())
+}
+
+/// Fetch commits from the repository the work directory was cloned from.
+///
+/// Only the commit checked out in the work directory is offered as a commit
+/// both sides have, with protocol v2, so that fetching a few commits into a
+/// clone of a large repository does not first list every ref it has.
+pub fn fetch_commits(work_dir: impl AsRef<Path>, commits: &[String]) -> io::Result<()> {
+    let work_dir = work_dir.as_ref();
+
+    gitc!(
+        "-C",
+        work_dir,
+        "-c",
+        "protocol.version=2",
+        "fetch",
+        "--quiet",
+        "--no-tags",
+        "--negotiation-tip=HEAD",
+        "origin",
+        ..commits
+    )?;
+    Ok(())
+}
+
 pub fn clean_work_dir(work_dir: impl AsRef<Path>) -> io::Result<()> {
     let work_dir = work_dir.as_ref();
 
@@ -56,6 +635,29 @@ pub fn clean_work_dir(work_dir: impl AsRef<Path>) -> io::Result<()> {
     Ok(())
 }
 
+/// Get the commit checked out in a work directory.
+pub fn get_head_commit(work_dir: impl AsRef<Path>) -> io::Result<String> {
+    let work_dir = work_dir.as_ref();
+
+    gitc!("-C", work_dir, "rev-parse", "HEAD")
+}
+
+/// Check that a work directory has no local changes, and no untracked or
+/// ignored files.
+pub fn is_work_dir_clean(work_dir: impl AsRef<Path>) -> io::Result<bool> {
+    let work_dir = work_dir.as_ref();
+
+    let status = gitc!(
+        "-C",
+        work_dir,
+        "status",
+        "--porcelain",
+        "--ignored",
+        "--untracked-files=all"
+    )?;
+    Ok(status.trim().is_empty())
+}
+
 pub fn checkout_detached(work_dir: impl AsRef<Path>, commit: impl AsRef<str>) -> io::Result<()> {
     let work_dir = work_dir.as_ref();
     let commit = commit.as_ref();
@@ -64,6 +666,26 @@ pub fn checkout_detached(work_dir: impl AsRef<Path>, commit: impl AsRef<str>) ->
     Ok(())
 }
 
+/// Check out the commit of a submodule that the commit checked out in the work
+/// directory uses, cloning the submodule first if needed.
+pub fn update_submodule(work_dir: impl AsRef<Path>, path: impl AsRef<Path>) -> io::Result<()> {
+    let work_dir = work_dir.as_ref();
+    let path = path.as_ref();
+
+    gitc!(
+        "-C",
+        work_dir,
+        "submodule",
+        "update",
+        "--init",
+        "--force",
+        "--checkout",
+        "--",
+        path
+    )?;
+    Ok(())
+}
+
 pub fn apply_stash(work_dir: impl AsRef<Path>, commit: impl AsRef<str>) -> io::Result<()> {
     let work_dir = work_dir.as_ref();
     let commit = commit.as_ref();
@@ -72,39 +694,323 @@ pub fn apply_stash(work_dir: impl AsRef<Path>, commit: impl AsRef<str>) -> io::R
     Ok(())
 }
 
+pub fn reset_index(work_dir: impl AsRef<Path>) -> io::Result<()> {
+    let work_dir = work_dir.as_ref();
+
+    gitc!("-C", work_dir, "reset", "--quiet")?;
+    Ok(())
+}
+
+/// Add a value to the config of a repository.
+pub fn add_config(work_dir: impl AsRef<Path>, key: &str, value: &str) -> io::Result<()> {
+    let work_dir = work_dir.as_ref();
+
+    gitc!("-C", work_dir, "config", "--add", key, value)?;
+    Ok(())
+}
+
+/// Identity used for commits made by corun when the user has none configured.
+const DEFAULT_IDENTITY: &[(&str, &str)] = &[
+    ("user.name", "git-corun"),
+    ("user.email", "git-corun@localhost"),
+];
+
+/// Make sure commits can be made in a clone, by configuring the `git-corun`
+/// identity in it for what the user has not configured. The user's identity, or
+/// one given with `--git-config`, is used otherwise.
+pub fn ensure_identity(work_dir: impl AsRef<Path>) -> io::Result<()> {
+    let work_dir = work_dir.as_ref();
+
+    for (key, value) in DEFAULT_IDENTITY {
+        if get_config_values(work_dir.join(".git"), key)?.is_empty() {
+            gitc!("-C", work_dir, "config", key, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether git refuses to use a repository because it is owned by another
+/// user, e.g. on a shared mount that reports another owner for all files.
+pub fn has_dubious_ownership(work_dir: impl AsRef<Path>) -> io::Result<bool> {
+    let work_dir = work_dir.as_ref();
+
+    // git translates its messages, so it is run in the C locale to match them
+    let args = gitc_args!("-C", work_dir, "rev-parse", "--git-dir");
+    run::trace("git", args);
+    let output = Command::new("git").args(args).env("LC_ALL", "C").output()?;
+
+    let error = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        Ok(false)
+    } else if error.contains("dubious ownership") {
+        Ok(true)
+    } else {
+        Err(io::Error::other(format!(
+            "git error: {}",
+            error.trim_end_matches('\n')
+        )))
+    }
+}
+
+/// Set git config for all git commands run from now on, by corun and by the
+/// commands it runs, as with `git -c KEY=VALUE` (which needs git 2.31).
+pub fn set_config_env(key: &str, value: &str) {
+    let count = env::var("GIT_CONFIG_COUNT")
+        .ok()
+        .and_then(|count| count.parse::<usize>().ok())
+        .unwrap_or(0);
+    env::set_var(format!("GIT_CONFIG_KEY_{}", count), key);
+    env::set_var(format!("GIT_CONFIG_VALUE_{}", count), value);
+    env::set_var("GIT_CONFIG_COUNT", (count + 1).to_string());
+}
+
+/// Arguments to use a work tree, e.g. so that its `.mailmap` is used for
+/// author names and emails, even when not run from its top-level directory.
+fn work_tree_args(work_tree: Option<&Path>) -> Vec<&OsStr> {
+    match work_tree {
+        Some(work_tree) => vec![OsStr::new("--work-tree"), work_tree.as_os_str()],
+        None => Vec::new(),
+    }
+}
+
+pub fn format_commit(
+    git_dir: impl AsRef<Path>,
+    work_tree: Option<&Path>,
+    commit: impl AsRef<str>,
+    format: impl AsRef<str>,
+) -> io::Result<String> {
+    let git_dir = git_dir.as_ref();
+    let commit = commit.as_ref();
+    let format = format.as_ref();
+
+    let pretty_format = format!("--pretty=format:{}", format);
+
+    gitc!(
+        "--git-dir",
+        git_dir,
+        ..work_tree_args(work_tree),
+        "show",
+        "--quiet",
+        "--no-patch",
+        pretty_format,
+        commit
+    )
+}
+
 pub fn show_commit(
     git_dir: impl AsRef<Path>,
+    work_tree: Option<&Path>,
     commit: impl AsRef<str>,
     format: impl AsRef<str>,
+    date: impl AsRef<str>,
 ) -> io::Result<()> {
     let git_dir = git_dir.as_ref();
     let commit = commit.as_ref();
     let format = format.as_ref();
 
-    let pretty_format = format!("--pretty=format:{}", format);
+    // terminated by a newline, like commits shown by other version control systems
+    let pretty_format = format!("--pretty=tformat:{}", format);
+    let date_format = format!("--date={}", date.as_ref());
+    let color = if term::color_enabled() {
+        "color.ui=always"
+    } else {
+        "color.ui=never"
+    };
 
     let output = Command::new("git")
         .args(gitc_args!(
+            "--no-pager",
+            "-c",
+            color,
             "--git-dir",
             git_dir,
+            ..work_tree_args(work_tree),
             "show",
             "--quiet",
             "--no-patch",
             pretty_format,
-            "--date=format:%e %b %Y %H:%M",
+            date_format,
             commit
         ))
-        .stdout(Stdio::inherit())
+        .stdout(term::status_output()?)
         .output()?;
 
     if !output.status.success() {
         let error = String::from_utf8_lossy(&output.stderr);
         let error = error.trim_end_matches('\n');
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("git error: {}", error),
-        ));
+        return Err(io::Error::other(format!("git error: {}", error)));
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A repository in a temporary directory, which is removed when dropped.
+    struct TestRepo {
+        dir: PathBuf,
+    }
+
+    impl TestRepo {
+        fn new() -> TestRepo {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let dir = env::temp_dir().join(format!(
+                "corun-test-{}-{}",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::SeqCst)
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let repo = TestRepo { dir };
+            repo.git(&["init", "--quiet"]);
+            repo
+        }
+
+        fn git_dir(&self) -> PathBuf {
+            self.dir.join(".git")
+        }
+
+        fn git(&self, args: &[&str]) -> String {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(&self.dir)
+                .args(args)
+                .env("GIT_AUTHOR_NAME", "A")
+                .env("GIT_AUTHOR_EMAIL", "a@example.com")
+                .env("GIT_COMMITTER_NAME", "A")
+                .env("GIT_COMMITTER_EMAIL", "a@example.com")
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "git {:?}: {}",
+                args,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+
+        fn commit(&self, message: &str) -> String {
+            self.git(&["commit", "--quiet", "--allow-empty", "-m", message]);
+            self.git(&["rev-parse", "HEAD"])
+        }
+
+        fn hashes(&self, rev: &str) -> io::Result<Vec<String>> {
+            get_commit_hashes(self.git_dir(), rev)
+        }
+    }
+
+    impl Drop for TestRepo {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn annotated_tag_is_peeled_to_commit() {
+        let repo = TestRepo::new();
+        let commit = repo.commit("first");
+        repo.git(&["tag", "-a", "-m", "release", "v1"]);
+        assert_ne!(repo.git(&["rev-parse", "v1"]), commit);
+
+        assert_eq!(repo.hashes("v1").unwrap(), vec![commit.clone()]);
+        assert_eq!(repo.hashes("v1^{commit}").unwrap(), vec![commit]);
+    }
+
+    #[test]
+    fn tags_of_tags_are_peeled_to_commit() {
+        let repo = TestRepo::new();
+        let commit = repo.commit("first");
+        repo.git(&["tag", "-a", "-m", "inner", "inner"]);
+        repo.git(&["tag", "-a", "-m", "outer", "outer", "inner"]);
+
+        assert_eq!(repo.hashes("outer").unwrap(), vec![commit]);
+    }
+
+    #[test]
+    fn ranges_with_tags_are_expanded() {
+        let repo = TestRepo::new();
+        repo.commit("first");
+        repo.git(&["tag", "-a", "-m", "release", "v1"]);
+        let second = repo.commit("second");
+        let third = repo.commit("third");
+
+        assert_eq!(
+            repo.hashes("v1..HEAD").unwrap(),
+            vec![second, third.clone()]
+        );
+        assert_eq!(repo.hashes("HEAD^!").unwrap(), vec![third]);
+    }
+
+    #[test]
+    fn special_refs_are_resolved() {
+        let repo = TestRepo::new();
+        let first = repo.commit("first");
+        let second = repo.commit("second");
+        fs::write(
+            repo.git_dir().join("FETCH_HEAD"),
+            format!("{}\t\tbranch 'main' of example.com\n", first),
+        )
+        .unwrap();
+        fs::write(repo.git_dir().join("MERGE_HEAD"), format!("{}\n", second)).unwrap();
+
+        assert_eq!(repo.hashes("FETCH_HEAD").unwrap(), vec![first]);
+        assert_eq!(repo.hashes("MERGE_HEAD").unwrap(), vec![second]);
+    }
+
+    #[test]
+    fn abbreviated_hash_is_resolved() {
+        let repo = TestRepo::new();
+        let commit = repo.commit("first");
+
+        assert_eq!(repo.hashes(&commit[..7]).unwrap(), vec![commit]);
+    }
+
+    #[test]
+    fn ambiguous_abbreviated_hash_is_an_error() {
+        let repo = TestRepo::new();
+        // enough commits for two of them to start with the same 4 characters
+        let mut script = String::new();
+        for index in 0..1000 {
+            script.push_str(&format!(
+                "commit refs/heads/main\ncommitter A <a@example.com> {} +0000\ndata 0\n\n",
+                index
+            ));
+        }
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&repo.dir)
+            .args(["fast-import", "--quiet"])
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), script.as_bytes()).unwrap();
+        assert!(child.wait().unwrap().success());
+
+        let commits = repo.git(&["rev-list", "main"]);
+        let mut seen = HashSet::new();
+        let prefix = commits
+            .lines()
+            .map(|commit| &commit[..4])
+            .find(|prefix| !seen.insert(*prefix))
+            .expect("two commits with the same prefix");
+
+        let err = repo.hashes(prefix).unwrap_err();
+        assert!(err.to_string().contains("ambiguous"), "{}", err);
+    }
+
+    #[test]
+    fn invalid_revision_is_an_error() {
+        let repo = TestRepo::new();
+        repo.commit("first");
+
+        assert!(repo.hashes("does-not-exist").is_err());
+        assert!(repo.hashes("does-not-exist..HEAD").is_err());
+    }
+}
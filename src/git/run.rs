@@ -1,6 +1,13 @@
 use std::ffi::OsStr;
-use std::io;
-use std::process::Command;
+use std::io::{self, Write};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::runner::shell_quote;
+use crate::term;
+
+/// Whether to print commands run by corun itself, for `-vv`.
+static TRACE: AtomicBool = AtomicBool::new(false);
 
 macro_rules! __gitc_args {
     ($out:ident;) => {};
@@ -31,8 +38,12 @@ macro_rules! __gitc_args {
 macro_rules! gitc_args {
     ($($tt:tt)*) => {
         &{
-            let mut args = Vec::new();
-            __gitc_args!(args; $($tt)*);
+            #[allow(clippy::vec_init_then_push)]
+            let args = {
+                let mut args = Vec::new();
+                __gitc_args!(args; $($tt)*);
+                args
+            };
             args
         } as &[&::std::ffi::OsStr]
     };
@@ -42,19 +53,71 @@ macro_rules! gitc {
     ($($tt:tt)*) => { $crate::git::run::run_gitc(gitc_args!($($tt)*)) };
 }
 
+macro_rules! gitc_with_input {
+    ($input:expr; $($tt:tt)*) => {
+        $crate::git::run::run_gitc_with_input(gitc_args!($($tt)*), $input)
+    };
+}
+
+/// Print git (and other version control) commands before running them.
+pub fn set_trace(enabled: bool) {
+    TRACE.store(enabled, Ordering::SeqCst);
+}
+
+/// Print a command that corun runs itself, if enabled with `set_trace`.
+pub fn trace<S>(program: &str, args: &[S])
+where
+    S: AsRef<OsStr>,
+{
+    if !TRACE.load(Ordering::SeqCst) {
+        return;
+    }
+    let args = args
+        .iter()
+        .map(|arg| shell_quote(&arg.as_ref().to_string_lossy()))
+        .collect::<Vec<_>>();
+    eprintln!(
+        "{}",
+        term::paint("2", format!("+ {} {}", program, args.join(" ")))
+    );
+}
+
 pub fn run_gitc<S>(args: &[S]) -> io::Result<String>
 where
     S: AsRef<OsStr>,
 {
+    trace("git", args);
     let output = Command::new("git").args(args).output()?;
+    into_result(output)
+}
+
+pub fn run_gitc_with_input<S>(args: &[S], input: impl AsRef<[u8]>) -> io::Result<String>
+where
+    S: AsRef<OsStr>,
+{
+    trace("git", args);
+    let mut child = Command::new("git")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_ref())?;
+
+    let output = child.wait_with_output()?;
+    into_result(output)
+}
 
+fn into_result(output: Output) -> io::Result<String> {
     if !output.status.success() {
         let error = String::from_utf8_lossy(&output.stderr);
         let error = error.trim_end_matches('\n');
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("git error: {}", error),
-        ));
+        return Err(io::Error::other(format!("git error: {}", error)));
     }
 
     let result = String::from_utf8_lossy(&output.stdout)
@@ -0,0 +1,57 @@
+//! A home directory of its own for commands, so that configuration and caches
+//! of tools in the user's home directory (e.g. pip, npm or gradle) do not
+//! affect the results, and commands behave the same on every machine.
+//!
+//! The home directory is kept in the clone, and is shared by the commits of a
+//! run. It starts out empty, or as a copy of a template directory with the
+//! configuration the commands should use.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Create the home directory, as a copy of a template if one is given.
+pub fn prepare(home: &Path, template: Option<&Path>) -> io::Result<()> {
+    if home.exists() {
+        fs::remove_dir_all(home)?;
+    }
+    match template {
+        Some(template) => copy_dir(template, home).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("{}: {}", template.to_string_lossy(), err),
+            )
+        }),
+        None => fs::create_dir_all(home),
+    }
+}
+
+/// Environment variables to set for the home directory, with the directories
+/// they are set to.
+pub fn variables(home: &Path) -> Vec<(&'static str, PathBuf)> {
+    vec![
+        ("HOME", home.to_path_buf()),
+        ("XDG_CONFIG_HOME", home.join(".config")),
+        ("XDG_CACHE_HOME", home.join(".cache")),
+        ("XDG_DATA_HOME", home.join(".local").join("share")),
+        ("XDG_STATE_HOME", home.join(".local").join("state")),
+    ]
+}
+
+/// Copy a directory recursively, keeping symbolic links as they are.
+fn copy_dir(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let dst = dst.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &dst)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &dst)?;
+        } else {
+            fs::copy(entry.path(), &dst)?;
+        }
+    }
+    Ok(())
+}
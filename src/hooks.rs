@@ -0,0 +1,115 @@
+//! Shell commands run at points in the lifecycle of a run, for notifications
+//! and other integrations.
+//!
+//! Hooks are set in git config, each of which can be given more than once:
+//!
+//! ```text
+//! [corun]
+//!     onRunStart = notify-send "corun started"
+//!     onCommitFinish = ./scripts/record-result
+//!     onRunFinish = jq -r .id >> ~/corun-runs
+//! ```
+//!
+//! Each hook gets a JSON object describing what happened on stdin, with a
+//! `hook` field of `run_start`, `commit_finish` or `run_finish`.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde::Serialize;
+
+use crate::git;
+use crate::report::RunResult;
+use crate::store::Run;
+
+#[derive(Serialize)]
+#[serde(tag = "hook", rename_all = "snake_case")]
+pub enum HookEvent<'a> {
+    /// Commits are about to be run on.
+    RunStart {
+        label: Option<&'a str>,
+        command: &'a [String],
+        dir: &'a Path,
+        commits: Vec<&'a str>,
+    },
+    /// All commands were run on a commit.
+    CommitFinish {
+        commit: &'a str,
+        results: &'a [RunResult],
+        done: usize,
+        total: usize,
+    },
+    /// The run is done, with the same contents as a `--json` report.
+    RunFinish(&'a Run),
+}
+
+#[derive(Debug, Default)]
+pub struct Hooks {
+    on_run_start: Vec<String>,
+    on_commit_finish: Vec<String>,
+    on_run_finish: Vec<String>,
+}
+
+impl Hooks {
+    /// Get the hooks set in the git config of a repository.
+    pub fn load(git_dir: Option<&Path>) -> io::Result<Self> {
+        let git_dir = match git_dir {
+            Some(git_dir) => git_dir,
+            None => return Ok(Hooks::default()),
+        };
+        Ok(Hooks {
+            on_run_start: git::get_config_values(git_dir, "corun.onRunStart")?,
+            on_commit_finish: git::get_config_values(git_dir, "corun.onCommitFinish")?,
+            on_run_finish: git::get_config_values(git_dir, "corun.onRunFinish")?,
+        })
+    }
+
+    /// Also run commands when the run is done, e.g. ones given with
+    /// `--on-finish`.
+    pub fn add_on_run_finish(&mut self, commands: &[String]) {
+        self.on_run_finish.extend(commands.iter().cloned());
+    }
+
+    /// Run the hooks for an event. Hooks that fail only cause a warning.
+    pub fn run(&self, event: &HookEvent) -> io::Result<()> {
+        let commands = match event {
+            HookEvent::RunStart { .. } => &self.on_run_start,
+            HookEvent::CommitFinish { .. } => &self.on_commit_finish,
+            HookEvent::RunFinish(_) => &self.on_run_finish,
+        };
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        let mut input = serde_json::to_vec(event)?;
+        input.push(b'\n');
+        for command in commands {
+            if let Err(err) = run_hook(command, &input) {
+                eprintln!("Warning: hook {:?} failed: {}", command, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn run_hook(command: &str, input: &[u8]) -> io::Result<()> {
+    let mut child = Command::new("/bin/sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    // hooks do not have to read their input
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    match stdin.write_all(input) {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+        _ => {}
+    }
+    drop(stdin);
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("exited with {}", status)));
+    }
+    Ok(())
+}
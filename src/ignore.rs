@@ -0,0 +1,56 @@
+//! Commits that are known to be broken, which are skipped.
+//!
+//! These are listed in `.git-corun-ignore` at the root of the repository, and
+//! in the `corun.ignore` git config, one revision or range per line, optionally
+//! followed by a reason:
+//!
+//! ```text
+//! # comments start with #
+//! 1a2b3c4 does not build with current compilers
+//! v1.0..v1.1 tests need a network connection
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use crate::git;
+use crate::vcs::Vcs;
+
+pub const FILE_NAME: &str = ".git-corun-ignore";
+
+/// Get known broken commits, with the reason they are broken.
+pub fn known_broken(vcs: &dyn Vcs) -> io::Result<HashMap<String, String>> {
+    let mut lines = Vec::new();
+    if let Ok(root) = vcs.root() {
+        match fs::read_to_string(root.join(FILE_NAME)) {
+            Ok(contents) => lines.extend(contents.lines().map(str::to_string)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    if let Some(git_dir) = vcs.git_dir() {
+        lines.extend(git::get_config_values(git_dir, "corun.ignore")?);
+    }
+
+    let mut commits = HashMap::new();
+    for line in &lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let rev = parts.next().unwrap_or_default();
+        let reason = parts.next().unwrap_or_default().trim();
+        let reason = reason.trim_start_matches('#').trim();
+
+        let expanded = vcs.expand_commits(rev).map_err(|err| {
+            io::Error::new(err.kind(), format!("{}: {}: {}", FILE_NAME, rev, err))
+        })?;
+        for commit in expanded {
+            commits.insert(commit, reason.to_string());
+        }
+    }
+    Ok(commits)
+}
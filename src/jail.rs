@@ -0,0 +1,195 @@
+//! Restricting where commands can write files, for running build scripts of
+//! repositories that are not trusted.
+//!
+//! This uses Landlock, which is available on Linux 5.13 and later, and needs
+//! neither root nor containers. Commands can still read any file that the user
+//! can read, and use the network.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+/// A set of directories that commands may write in, and nowhere else.
+pub struct Jail {
+    ruleset: File,
+}
+
+impl Jail {
+    /// Create a jail in which only the given directories (and everything
+    /// beneath them) are writable.
+    pub fn new(writable: &[PathBuf]) -> io::Result<Self> {
+        let ruleset = imp::create_ruleset(writable)
+            .map_err(|err| io::Error::new(err.kind(), format!("could not set up jail: {}", err)))?;
+        Ok(Jail { ruleset })
+    }
+
+    /// File descriptor to enter the jail with, using `enter`.
+    pub fn fd(&self) -> RawFd {
+        self.ruleset.as_raw_fd()
+    }
+}
+
+/// Enter a jail in the current process, which cannot be undone. This is meant
+/// for use between fork and exec, so it does not allocate.
+pub fn enter(fd: RawFd) -> io::Result<()> {
+    imp::restrict_self(fd)
+}
+
+/// Directories that are writable in a jail by default, in addition to the
+/// checkout: the temporary directory, and devices such as `/dev/null`.
+pub fn default_writable() -> Vec<PathBuf> {
+    vec![std::env::temp_dir(), Path::new("/dev").to_path_buf()]
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    use std::path::PathBuf;
+
+    // not in the version of libc used
+    const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+    const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+    const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+    const PR_SET_NO_NEW_PRIVS: libc::c_int = 38;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    /// Linking and renaming files between directories, since ABI version 2.
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// Truncating files, since ABI version 3.
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    pub fn create_ruleset(writable: &[PathBuf]) -> io::Result<File> {
+        let abi = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 1 {
+            return Err(io::Error::other(
+                "Landlock is not supported or not enabled by the kernel",
+            ));
+        }
+
+        let mut access = ACCESS_FS_WRITE_FILE
+            | ACCESS_FS_REMOVE_DIR
+            | ACCESS_FS_REMOVE_FILE
+            | ACCESS_FS_MAKE_CHAR
+            | ACCESS_FS_MAKE_DIR
+            | ACCESS_FS_MAKE_REG
+            | ACCESS_FS_MAKE_SOCK
+            | ACCESS_FS_MAKE_FIFO
+            | ACCESS_FS_MAKE_BLOCK
+            | ACCESS_FS_MAKE_SYM;
+        if abi >= 2 {
+            access |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            access |= ACCESS_FS_TRUNCATE;
+        }
+
+        let attr = RulesetAttr {
+            handled_access_fs: access,
+        };
+        let fd = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                &attr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ruleset = unsafe { File::from_raw_fd(fd as RawFd) };
+
+        for path in writable {
+            let name = CString::new(path.as_os_str().as_bytes())?;
+            let dir = unsafe { libc::open(name.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if dir < 0 {
+                let err = io::Error::last_os_error();
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("{}: {}", path.to_string_lossy(), err),
+                ));
+            }
+            let dir = unsafe { File::from_raw_fd(dir) };
+
+            let rule = PathBeneathAttr {
+                allowed_access: access,
+                parent_fd: dir.as_raw_fd(),
+            };
+            let result = unsafe {
+                libc::syscall(
+                    SYS_LANDLOCK_ADD_RULE,
+                    fd,
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule,
+                    0,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(ruleset)
+    }
+
+    pub fn restrict_self(fd: RawFd) -> io::Result<()> {
+        unsafe {
+            if libc::prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == -1
+                || libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, fd, 0) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::RawFd;
+    use std::path::PathBuf;
+
+    pub fn create_ruleset(_writable: &[PathBuf]) -> io::Result<File> {
+        Err(io::Error::other("only supported on Linux"))
+    }
+
+    pub fn restrict_self(_fd: RawFd) -> io::Result<()> {
+        Err(io::Error::other("only supported on Linux"))
+    }
+}
@@ -1,203 +1,2539 @@
+mod auto;
+mod bench;
+mod caches;
+mod candidates;
+mod cli;
+mod compress;
+mod container;
+mod events;
+mod expected;
+#[macro_use]
 mod git;
+mod home;
+mod hooks;
+mod ignore;
+mod jail;
+mod matrix;
+mod metric;
+mod order;
+mod pager;
+mod plan;
+mod plot;
+mod plugins;
+mod prefetch;
+mod preflight;
+mod procs;
+mod progress;
+mod query;
+mod range_diff;
+mod regressions;
+mod remote;
+mod report;
+mod reproducible;
+mod runner;
+mod signature;
+mod split;
+mod stack;
+mod status;
+mod steps;
+mod store;
+mod synthetic;
+mod term;
+#[cfg(feature = "self-update")]
+mod update;
+mod vcs;
 
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
-use std::fmt::Display;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{self, Command, ExitStatus, Stdio};
+use std::process;
+use std::sync::mpsc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use chrono::{prelude::*, Duration, Local};
-use structopt::{clap, StructOpt};
+use cli::{
+    AnnotateCommand, CargoTarget, Cli, ColorWhen, CompleteRevsCommand, CompletionsCommand,
+    DateFormat, Deadline, DiffRunsCommand, DirCommand, ExecLinesCommand, GcCommand, GitConfig,
+    GroupBy, ImportCommand, ListCommand, OnFailure, OnRefChange, Options, Prefetch, Priority,
+    QueryCommand, RangeDiffCommand, RegressionsCommand, RemoteJobCommand, ReplayCommand,
+    SelfUpdateCommand, SetCommand,
+};
+use report::RunResult;
+use status::Status;
+use structopt::StructOpt;
+use vcs::{GitVcs, Vcs};
 
-#[derive(Clone, Debug, StructOpt)]
-struct Options {
-    #[structopt(
-        help = "Directory to check out and run code in",
-        short = "d",
-        long = "dir"
-    )]
-    dir: Option<PathBuf>,
+/// Number of commits to run on without asking for confirmation, unless set
+/// with `corun.confirmThreshold`.
+const DEFAULT_CONFIRM_THRESHOLD: usize = 100;
 
-    #[structopt(
-        help = "Apply latest stash before running",
-        short = "s",
-        long = "stash"
-    )]
-    apply_stash: bool,
+/// Time that finding the commits to run on can take before suggesting a
+/// commit-graph to make it faster.
+const SLOW_EXPAND_TIME: std::time::Duration = std::time::Duration::from_secs(10);
 
-    #[structopt(skip)]
-    apply_index: bool,
+fn main() {
+    let args = std::env::args_os().collect::<Vec<_>>();
+    if let Err(err) = plugins::exec_if_found(&args, &default_base_dir()) {
+        eprintln!("Error: {}", err);
+        process::exit(128);
+    }
+
+    let cli = Cli::from_args_safe().unwrap_or_else(|err| {
+        if err.use_stderr() {
+            eprintln!("{}", err.message);
+            process::exit(128);
+        }
+        let out = io::stdout();
+        writeln!(&mut out.lock(), "{}", err.message).expect("Error writing error to stdout");
+        process::exit(128);
+    });
+
+    let result = match cli {
+        Cli::Run(opts) => app(opts, run_args(), None),
+        Cli::Set(cmd) => set_app(cmd).map(|()| 0),
+        Cli::List(cmd) => list_app(cmd).map(|()| 0),
+        Cli::Regressions(cmd) => regressions_app(cmd).map(|()| 0),
+        Cli::DiffRuns(cmd) => diff_runs_app(cmd).map(|()| 0),
+        Cli::RangeDiff(cmd) => range_diff_app(cmd),
+        Cli::ExecLines(cmd) => exec_lines_app(cmd).map(|()| 0),
+        Cli::Annotate(cmd) => annotate_app(cmd).map(|()| 0),
+        Cli::Import(cmd) => import_app(cmd).map(|()| 0),
+        Cli::Dir(cmd) => dir_app(cmd).map(|()| 0),
+        Cli::Replay(cmd) => replay_app(cmd),
+        Cli::Gc(cmd) => gc_app(cmd).map(|()| 0),
+        Cli::Query(cmd) => query_app(cmd).map(|()| 0),
+        Cli::Completions(cmd) => completions_app(cmd).map(|()| 0),
+        Cli::SelfUpdate(cmd) => self_update_app(cmd).map(|()| 0),
+        Cli::CompleteRevs(cmd) => complete_revs_app(cmd).map(|()| 0),
+        Cli::RemoteJob(cmd) => remote_job_app(cmd),
+    };
+
+    match result {
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(128);
+        }
+        Ok(exit_code) => process::exit(exit_code),
+    }
+}
+
+/// Run on commits, with the arguments that are recorded for `replay`, and the
+/// run to replay if any.
+fn app(
+    mut opts: Options,
+    args: Vec<String>,
+    replay: Option<&store::Run>,
+) -> Result<i32, Box<dyn Error>> {
+    for config in &opts.git_config {
+        git::set_config_env(&config.key, &config.value);
+    }
+    git::run::set_trace(opts.verbose >= 2);
+
+    // open repository
+    if let Some(repo) = &opts.repo {
+        std::env::set_current_dir(repo)
+            .map_err(|err| format!("cannot open repository {}: {}", repo.to_string_lossy(), err))?;
+    }
+    let vcs = vcs::open(opts.vcs).map_err(|err| {
+        if opts.repo.is_none() && err.to_string().starts_with("not a git repository") {
+            format!(
+                "{}\nhint: run in a git repository, or use --repo DIR to run on one elsewhere",
+                err
+            )
+        } else {
+            err.to_string()
+        }
+    })?;
+    let vcs = vcs.as_ref();
+    let git_dir = vcs.git_dir();
+    if let Ok(root) = vcs.root() {
+        // directories that corun checks out commits in are clones, and would
+        // be run on instead of the repository they were cloned from
+        if vcs.metadata_dir(&root).join("logs").is_dir() {
+            return Err(format!(
+                "{} is a directory that corun checked out commits in, not a repository to run on\nhint: run in the repository it was cloned from, or use --repo DIR",
+                root.to_string_lossy()
+            )
+            .into());
+        }
+    }
+    term::set_status_fd(opts.status_fd)?;
+    if opts.no_color || opts.screen_reader {
+        opts.color = Some(ColorWhen::Never);
+    }
+    set_color(vcs, opts.color)?;
+    term::set_words(opts.no_unicode || opts.screen_reader);
+
+    // validate everything before cloning, so all problems are reported at once
+    let mut problems = preflight::Problems::default();
+
+    // git config from `corun.gitConfig`, unless given on the command line
+    let mut git_config = opts.git_config.clone();
+    if let Some(git_dir) = git_dir {
+        let configured = problems
+            .check("corun.gitConfig", config_git_config(git_dir))
+            .unwrap_or_default();
+        for config in configured {
+            if !opts.git_config.iter().any(|other| other.key == config.key) {
+                git::set_config_env(&config.key, &config.value);
+                git_config.push(config);
+            }
+        }
+    }
+
+    if opts.auto {
+        if let Some(command) = problems.check("--auto", auto::command(git_dir)) {
+            opts.command = command;
+        }
+    }
 
-    #[structopt(help = "Run as shell command", short = "c")]
-    shell_command: bool,
+    // check that features that need git are only used with git
+    let uses_stash = opts.apply_stash > 0 || opts.apply_all_stashes || opts.stash_as_commit;
+    if git_dir.is_none() {
+        for (used, option) in &[
+            (uses_stash, "--stash"),
+            (opts.set.is_some(), "--set"),
+            (opts.plan.is_some(), "--plan"),
+            (opts.split_by_path.is_some(), "--split-by-path"),
+            (opts.stack || opts.stack_independent, "--stack"),
+            (opts.mark || opts.skip_marked, "--mark"),
+            (opts.suggest_trailers, "--suggest-trailers"),
+            (!opts.relevant_paths.is_empty(), "--relevant-paths"),
+            (
+                opts.reachable_from.is_some() || opts.ours,
+                "--reachable-from",
+            ),
+            (opts.only_breaking_candidates, "--only-breaking-candidates"),
+            (opts.stream, "--stream"),
+            (opts.topo_order || opts.date_order, "--topo-order"),
+            (opts.submodule.is_some(), "--submodule"),
+            (
+                opts.prioritize == Some(Priority::Candidates),
+                "--prioritize candidates",
+            ),
+        ] {
+            if *used {
+                problems.push(format!("{} can only be used in git repositories", option));
+            }
+        }
+    }
+
+    // commands are run more than once to check that their output is the same
+    let repeat = opts.repeat.unwrap_or(if opts.check_reproducible.is_some() {
+        2
+    } else {
+        1
+    });
+    if repeat == 0 {
+        problems.push("--repeat must be at least 1");
+    } else if repeat < 2 && opts.check_reproducible.is_some() {
+        problems.push("--check-reproducible needs --repeat of at least 2");
+    }
+
+    // settings of benchmarking mode, where commands are also pinned to CPUs
+    let extract = opts.extract.as_ref().and_then(|pattern| {
+        problems.check(
+            "--extract",
+            metric::Extract::new(
+                pattern,
+                opts.threshold,
+                opts.max_regression_pct,
+                opts.higher_is_better,
+            ),
+        )
+    });
+
+    let bench = if opts.bench {
+        Some(bench::Settings {
+            cooldown: opts.cooldown.map_or(0.0, |cooldown| cooldown.as_secs_f64()),
+            cpus: opts
+                .pin_cpus
+                .as_ref()
+                .map(|cpus| cpus.0.clone())
+                .unwrap_or_default(),
+            warmup: opts.warmup.unwrap_or(1),
+        })
+    } else {
+        None
+    };
+    if let Some(cpus) = &opts.pin_cpus {
+        problems.check("--pin-cpus", bench::pin_cpus(&cpus.0));
+    }
+
+    if opts.merge_parent == Some(0) {
+        problems.push("--merge-parent must be at least 1");
+    }
+
+    // get steps to run, in order
+    let steps = if opts.steps.is_empty() {
+        vec![steps::Step {
+            name: String::new(),
+            deps: Vec::new(),
+            command: opts.command.clone(),
+            shell: opts.shell_command,
+            env: Vec::new(),
+        }]
+    } else {
+        problems
+            .check("steps", steps::from_specs(&opts.steps))
+            .unwrap_or_default()
+    };
+    let steps = match opts.prefetch {
+        Some(_) if steps.iter().any(|step| step.name == prefetch::STEP_NAME) => {
+            problems.push(format!(
+                "--prefetch cannot be used with a step named {}",
+                prefetch::STEP_NAME
+            ));
+            steps
+        }
+        Some(prefetch) => prefetch::add_step(prefetch, steps, &default_base_dir().join("cache")),
+        None => steps,
+    };
+    let steps = match &opts.dockerfile {
+        Some(_) if steps.iter().any(|step| step.name == container::STEP_NAME) => {
+            problems.push(format!(
+                "--dockerfile cannot be used with a step named {}",
+                container::STEP_NAME
+            ));
+            steps
+        }
+        Some(dockerfile) => container::add_build_step(dockerfile, steps),
+        None => steps,
+    };
+
+    // check that commands can be executed, which jobs on a job runner are
+    // only sent with curl
+    if opts.remote.is_some() {
+        if let Err(err) = preflight::check_executable(remote::COMMAND) {
+            problems.push(format!("--remote: {}", err));
+        }
+    }
+    for step in steps.iter().filter(|_| opts.remote.is_none()) {
+        let executable = if step.shell {
+            &opts.shell
+        } else {
+            &step.command[0]
+        };
+        if let Err(err) = preflight::check_executable(executable) {
+            problems.push(err);
+        }
+    }
+    if opts.compress_logs {
+        if let Err(err) = preflight::check_executable(compress::COMMAND) {
+            problems.push(format!("--compress-logs: {}", err));
+        }
+    }
 
-    #[structopt(help = "Show output from commands", short = "v", long = "verbose")]
-    verbose: bool,
+    // get stash entries, newest first
+    let stashes = if let (true, Some(git_dir)) = (uses_stash, git_dir) {
+        let stashes = problems
+            .check("stash", git::list_stashes(git_dir))
+            .unwrap_or_default();
+        if stashes.is_empty() {
+            problems.push("no stash entries found");
+        } else if stashes.len() < opts.apply_stash {
+            problems.push(format!(
+                "cannot apply {} stash entries, only {} found",
+                opts.apply_stash,
+                stashes.len()
+            ));
+        }
+        stashes
+    } else {
+        Vec::new()
+    };
+    let mut stash_as_commit = match replay {
+        Some(replay) => replay.stash_as_commit.clone(),
+        None if opts.stash_as_commit => stashes.first().cloned(),
+        None => None,
+    };
 
-    #[structopt(help = "List of commits to run on", default_value = "HEAD")]
-    commits: Vec<String>,
+    // stash entries to apply, oldest first
+    let stash_entries = if opts.apply_all_stashes {
+        stashes.iter().rev().cloned().collect()
+    } else if opts.apply_stash > 0 {
+        stashes
+            .iter()
+            .take(opts.apply_stash)
+            .rev()
+            .cloned()
+            .collect()
+    } else if opts.apply_index {
+        unimplemented!()
+    } else {
+        Vec::new()
+    };
+    let mut stash_entries = match replay {
+        Some(replay) => replay.stashes.clone(),
+        None => stash_entries,
+    };
+
+    // expand list of commits
+    let revs = if replay.is_some() {
+        Vec::new()
+    } else if opts.commits.is_empty()
+        && opts.set.is_none()
+        && opts.from_report.is_none()
+        && opts.plan.is_none()
+        && opts.split_by_path.is_none()
+        && !opts.stash_as_commit
+    {
+        vec![vcs.default_rev().to_string()]
+    } else {
+        opts.commits.clone()
+    };
+    let expand_start = Instant::now();
+    let mut commits_by_rev = Vec::new();
+    let mut stream = None;
+    match (opts.stream, git_dir, revs.as_slice()) {
+        (true, Some(git_dir), [rev]) if rev.contains("..") => {
+            stream = problems.check(rev, git::stream_commit_hashes(git_dir, rev));
+        }
+        (true, _, _) => problems.push("--stream needs a single range of commits"),
+        (false, _, _) => {
+            // say which of the revisions is wrong, and run on the others unless
+            // that is not good enough
+            let strict = opts.strict_revs || revs.len() == 1;
+            let mut invalid = 0;
+            for rev in &revs {
+                let problem = match vcs.expand_commits(rev) {
+                    Ok(commits) if commits.is_empty() => format!("{}: matches no commits", rev),
+                    Ok(commits) => {
+                        commits_by_rev.push((rev.as_str(), commits));
+                        continue;
+                    }
+                    Err(err) => {
+                        let err = err.to_string();
+                        format!("{}: {}", rev, err.lines().next().unwrap_or_default())
+                    }
+                };
+                invalid += 1;
+                if strict {
+                    problems.push(problem);
+                } else {
+                    eprintln!("Warning: {}", problem);
+                }
+            }
+            if invalid > 0 && !strict {
+                if commits_by_rev.is_empty() {
+                    problems.push("none of the revisions matched any commits");
+                } else {
+                    eprintln!(
+                        "Running on the commits of the other {} revisions (use --strict-revs to fail instead)",
+                        commits_by_rev.len()
+                    );
+                }
+            }
+        }
+    }
+    // revisions that resolved, and their ends, which are resolved again to
+    // check if they changed, unless these are replaced with synthetic commits
+    // or are still being walked
+    let resolved_revs = commits_by_rev
+        .iter()
+        .map(|(rev, _)| rev.to_string())
+        .collect::<Vec<_>>();
+    let check_ref_change =
+        opts.on_ref_change != OnRefChange::Ignore && !opts.stack_independent && !opts.stream;
+    let mut rev_ends = if check_ref_change {
+        range_ends(vcs, &resolved_revs)?
+    } else {
+        Vec::new()
+    };
+    let (mut rev_commits, provenance) = dedup_commits(commits_by_rev);
+    if let Some(git_dir) = git_dir {
+        if expand_start.elapsed() > SLOW_EXPAND_TIME && !git::has_commit_graph(git_dir) {
+            eprintln!(
+                "Hint: finding the commits took {}, which is faster with a commit-graph (git commit-graph write --reachable)",
+                progress::format_duration(expand_start.elapsed())
+            );
+        }
+    }
+    let overlapping = provenance.values().filter(|revs| revs.len() > 1).count();
+    if overlapping > 0 {
+        eprintln!(
+            "Running once on {} commits selected by more than one revision",
+            overlapping
+        );
+    }
+
+    // check that commits are on the expected branch
+    let reachable_from = match (&opts.reachable_from, opts.ours) {
+        (Some(rev), _) => Some(rev.as_str()),
+        (None, true) => Some("HEAD"),
+        (None, false) => None,
+    };
+    if let (Some(rev), Some(git_dir)) = (reachable_from, git_dir) {
+        let unreachable = problems.check(
+            rev,
+            git::get_commits_not_reachable_from(git_dir, &rev_commits, rev),
+        );
+        if let Some(unreachable) = unreachable {
+            if let Some(first) = rev_commits
+                .iter()
+                .find(|commit| unreachable.contains(*commit))
+            {
+                problems.push(format!(
+                    "{} commits are not reachable from {}, e.g. {:.7}",
+                    unreachable.len(),
+                    rev,
+                    first
+                ));
+            }
+        }
+    }
+
+    // check that commits are a stack, and make each of them by itself if asked
+    if let (true, Some(git_dir)) = (opts.stack || opts.stack_independent, git_dir) {
+        let base = problems.check("stack", stack::base(git_dir, &rev_commits));
+        if let (Some(base), true) = (base, opts.stack_independent) {
+            let commits = stack::independent(git_dir, &base, &rev_commits);
+            if let Some(commits) = problems.check("stack", commits) {
+                rev_commits = commits;
+            }
+        }
+    }
+
+    // commits that do not come from revisions
+    let mut extra_targets = Vec::new();
+    if let (Some(set), Some(git_dir)) = (&opts.set, git_dir) {
+        let commits = problems.check(set, git::get_commit_set(git_dir, set));
+        extra_targets.extend(commits.into_iter().flatten().map(Target::Commit));
+    }
+    if let (Some(plan), Some(git_dir)) = (&opts.plan, git_dir) {
+        let commits = problems.check("plan", plan::from_file(git_dir, plan));
+        extra_targets.extend(commits.into_iter().flatten().map(Target::Commit));
+    }
+    if let (Some(commit), Some(git_dir)) = (&opts.split_by_path, git_dir) {
+        let commits = problems.check(commit, split::by_path(git_dir, commit));
+        extra_targets.extend(commits.into_iter().flatten().map(Target::Commit));
+    }
+    match replay {
+        Some(replay) => {
+            extra_targets.extend(replay.commits.iter().map(|commit| match &stash_as_commit {
+                Some(stash) if &stash.commit == commit => Target::Stash(stash.clone()),
+                _ => Target::Commit(commit.clone()),
+            }))
+        }
+        None => extra_targets.extend(stash_as_commit.clone().map(Target::Stash)),
+    }
+
+    // results with local changes applied say nothing about the commits themselves
+    if opts.mark && (!stash_entries.is_empty() || stash_as_commit.is_some()) {
+        problems.push("--mark cannot be used together with stashes");
+    }
+
+    // results to compare with, to only fail on new failures
+    let store = store::Store::open(vcs);
+    let baseline = opts
+        .baseline
+        .as_ref()
+        .and_then(|baseline| problems.check(baseline, store.load_or_read(baseline)));
+
+    // commits from a past run, e.g. to run again on the ones that failed
+    if let Some(report) = &opts.from_report {
+        if let Some(run) = problems.check(report, store.load_or_read(report)) {
+            let mut commits = Vec::new();
+            for result in &run.results {
+                let selected = opts.status.is_empty()
+                    || opts.status.iter().any(|status| match status.as_str() {
+                        "failed" => result.status.is_failure(),
+                        "passed" => matches!(result.status, Status::Success(_)),
+                        name => result.status.name() == name,
+                    });
+                if selected && !commits.contains(&result.info.commit) {
+                    commits.push(result.info.commit.clone());
+                }
+            }
+            if commits.is_empty() {
+                eprintln!("No commits selected from {}", report);
+            }
+            extra_targets.extend(commits.into_iter().map(Target::Commit));
+        }
+    }
+
+    problems.into_result()?;
+
+    // skip commits that are already known to pass
+    let passing = match git_dir {
+        Some(git_dir) if opts.skip_marked => git::get_passing_commits(git_dir)?,
+        _ => HashSet::new(),
+    };
+    let mut targets = select_targets(&rev_commits, &extra_targets, &passing);
+    let skipped = rev_commits.len() + extra_targets.len() - targets.len();
+    if skipped > 0 {
+        eprintln!("Skipping {} commits marked as passing", skipped);
+    }
+
+    let date_format = match (&opts.date, git_dir) {
+        (Some(date_format), _) => date_format.clone(),
+        (None, Some(git_dir)) => config_date_format(git_dir)?,
+        (None, None) => DateFormat::default(),
+    };
+
+    // skip commits that are known to be broken, except when replaying, which
+    // runs on exactly the same commits
+    let known_broken = match replay {
+        Some(_) => HashMap::new(),
+        None => ignore::known_broken(vcs)?,
+    };
+    for target in &targets {
+        if let Some(reason) = known_broken.get(target.commit()) {
+            let label = if reason.is_empty() {
+                "known broken".to_string()
+            } else {
+                format!("known broken: {}", reason)
+            };
+            vcs.show_commit(target.commit(), Status::Skipped, &label, &date_format)?;
+        }
+    }
+    targets.retain(|target| !known_broken.contains_key(target.commit()));
+    let mut skip = passing;
+    skip.extend(known_broken.into_keys());
+
+    // skip commits that are unlikely to break anything
+    if let (true, Some(git_dir)) = (opts.only_breaking_candidates, git_dir) {
+        let heuristics = candidates::Heuristics::load(git_dir, &opts.command)?;
+        let commits = targets
+            .iter()
+            .map(|target| target.commit().to_string())
+            .collect::<Vec<_>>();
+        let scores = candidates::scores(git_dir, &commits, &heuristics)?;
+        let (kept, skipped): (Vec<_>, Vec<_>) = targets
+            .into_iter()
+            .partition(|target| scores.get(target.commit()).copied().unwrap_or(1) > 0);
+        if !skipped.is_empty() {
+            eprintln!(
+                "Skipping {} commits that are unlikely to break anything",
+                skipped.len()
+            );
+        }
+        skip.extend(skipped.iter().map(|target| target.commit().to_string()));
+        targets = kept;
+    }
+
+    // parents before children, before prioritizing, which keeps this order
+    // for commits with the same priority
+    let commit_order = if opts.topo_order {
+        Some(order::Order::Topo)
+    } else if opts.date_order {
+        Some(order::Order::Date)
+    } else {
+        None
+    };
+    if let (Some(commit_order), Some(git_dir)) = (commit_order, git_dir) {
+        order_targets(git_dir, &mut targets, commit_order)?;
+    }
+    if let Some(priority) = &opts.prioritize {
+        prioritize(vcs, &mut targets, priority, &opts.command)?;
+    }
+
+    // guard against accidentally running on a lot of commits, e.g. a mistyped range
+    let threshold = match git_dir {
+        Some(git_dir) => git::get_config_values(git_dir, "corun.confirmThreshold")?
+            .last()
+            .map(|value| value.parse::<usize>())
+            .transpose()
+            .map_err(|err| format!("invalid corun.confirmThreshold: {}", err))?,
+        None => None,
+    };
+    let threshold = threshold.unwrap_or(DEFAULT_CONFIRM_THRESHOLD);
+    if stream.is_some() {
+        eprintln!("About to run on commits of {} while finding them", revs[0]);
+    } else {
+        eprintln!("About to run on {} commits", targets.len());
+    }
+    if targets.len() > threshold
+        && !opts.yes
+        && !term::confirm(format!("Run on more than {} commits?", threshold))?
+    {
+        return Err(format!(
+            "not running on more than {} commits without confirmation (use --yes)",
+            threshold
+        )
+        .into());
+    }
+
+    // create temporary directory (and possibly clean up old ones)
+    let started = Local::now();
+    let deadline = opts
+        .deadline
+        .as_ref()
+        .map(|deadline| deadline_time(deadline, started));
+    let tmpdir = create_directory(&opts)?;
+    eprintln!("Running in directory: {}", tmpdir.to_string_lossy());
+
+    // clone into temporary directory
+    vcs.clone_to(&tmpdir)?;
+    if git_dir.is_some() {
+        // newer versions of git refuse to use repositories owned by another
+        // user, which files on shared mounts can look like they are, so this
+        // is checked before anything else uses the clone
+        if git::has_dubious_ownership(&tmpdir)? {
+            eprintln!(
+                "Note: {} is owned by another user, so it is added to safe.directory",
+                tmpdir.to_string_lossy()
+            );
+            git::set_config_env("safe.directory", &tmpdir.to_string_lossy());
+        }
+        // also for git commands run in the clone after corun is done
+        for config in &git_config {
+            git::add_config(&tmpdir, &config.key, &config.value)?;
+        }
+        git::ensure_identity(&tmpdir)?;
+        // the clone shares the commit-graph of the repository if it has one
+        if !git::has_commit_graph(tmpdir.join(".git")) {
+            git::write_commit_graph(&tmpdir)?;
+        }
+    }
+
+    // shared download caches, given to commands in environment variables
+    let mut cache_dirs = Vec::new();
+    let mut cache_env = Vec::new();
+    for (used, cache) in &[
+        (
+            opts.cargo_home || opts.prefetch == Some(Prefetch::Cargo),
+            caches::Cache::Cargo,
+        ),
+        (
+            opts.npm_cache || opts.prefetch == Some(Prefetch::Npm),
+            caches::Cache::Npm,
+        ),
+        (opts.go_cache, caches::Cache::Go),
+        (opts.prefetch == Some(Prefetch::Pip), caches::Cache::Pip),
+    ] {
+        if *used {
+            for (name, dir) in cache.variables(&default_base_dir().join("cache")) {
+                fs::create_dir_all(&dir)?;
+                std::env::set_var(name, &dir);
+                cache_env.push((name.to_string(), dir.to_string_lossy().into_owned()));
+                cache_dirs.push(dir);
+            }
+        }
+    }
+    if let Some(dir) = opts
+        .prefetch
+        .and_then(|prefetch| prefetch.download_dir(&default_base_dir().join("cache")))
+    {
+        fs::create_dir_all(&dir)?;
+        cache_dirs.push(dir);
+    }
+
+    // keep logs in the clone, where cleaning the work directory does not remove them
+    let log_dir = vcs.metadata_dir(&tmpdir).join("logs");
+
+    // home directory for commands, only given to them and not to git commands
+    // run by corun itself, which should still use the user's configuration
+    let mut home_env = Vec::new();
+    if opts.isolate_home {
+        let home = vcs.metadata_dir(&tmpdir).join("home");
+        home::prepare(&home, opts.home_template.as_deref())?;
+        for (name, dir) in home::variables(&home) {
+            home_env.push((name.to_string(), dir.to_string_lossy().into_owned()));
+        }
+    }
+
+    let jail = if opts.jail {
+        let mut writable = vec![tmpdir.clone()];
+        writable.extend(jail::default_writable());
+        writable.extend(cache_dirs.iter().cloned());
+        writable.extend(opts.jail_allow.iter().cloned());
+        Some(jail::Jail::new(&writable)?)
+    } else {
+        None
+    };
+    fs::create_dir_all(&log_dir)?;
+    eprintln!("Writing logs to: {}", log_dir.to_string_lossy());
+
+    // keep the changes of stash entries next to the logs, since results on
+    // top of them mean little later without knowing what the changes were
+    if let Some(git_dir) = git_dir {
+        let patch_dir = vcs.metadata_dir(&tmpdir).join("patches");
+        for (i, stash) in stash_entries
+            .iter_mut()
+            .chain(&mut stash_as_commit)
+            .enumerate()
+        {
+            fs::create_dir_all(&patch_dir)?;
+            let path = patch_dir.join(format!("{}-{:.7}.patch", i + 1, stash.commit));
+            git::write_stash_patch(git_dir, &stash.commit, &path)?;
+            eprintln!(
+                "Writing changes of {} to: {}",
+                stash.name,
+                path.to_string_lossy()
+            );
+            stash.patch = Some(path);
+        }
+    }
+
+    let signatures = signature::Signatures::load(git_dir)?;
+    let expected = expected::ExpectedFailures::load(vcs)?;
+    let ctx = RunContext {
+        opts: &opts,
+        vcs,
+        date_format: &date_format,
+        work_tree: &tmpdir,
+        log_dir: &log_dir,
+        scratch_dir: &vcs.metadata_dir(&tmpdir).join("tmp"),
+        home_env: &home_env,
+        cache_dirs: &cache_dirs,
+        cache_env: &cache_env,
+        jail: jail.as_ref(),
+        stash_entries: &stash_entries,
+        steps: &steps,
+        extract: extract.as_ref(),
+        signatures: &signatures,
+        expected: &expected,
+    };
+
+    // combinations of environment variables to run each commit with, repeated
+    // to compare the output of each time
+    let variants = matrix::repeat(matrix::variants(&opts.matrix), repeat);
+    let mut not_reproducible = Vec::new();
+    let mut first_command = true;
+    runner::handle_interrupts();
+    let mut grid = Vec::new();
+    let mut results = Vec::new();
+
+    let mut events = opts
+        .json_lines
+        .as_ref()
+        .map(|path| events::EventWriter::create(path))
+        .transpose()?;
+    let mut estimator = progress::Estimator::default();
+
+    let mut hooks = hooks::Hooks::load(git_dir)?;
+    hooks.add_on_run_finish(&opts.on_finish);
+    hooks.run(&hooks::HookEvent::RunStart {
+        label: opts.label.as_deref(),
+        command: &report_command(&opts, &steps),
+        dir: &tmpdir,
+        commits: targets.iter().map(Target::commit).collect(),
+    })?;
+
+    let mut last_exit_code = 0;
+    let mut failed = 0;
+    let mut done = HashSet::new();
+    let mut ref_change_seen = false;
+    let mut index = 0;
+    // last commit that was run on, and its results
+    let mut previous: Option<(String, std::ops::Range<usize>)> = None;
+    while index < targets.len() || stream.is_some() {
+        // take the commits found so far, or wait for the next one if all of
+        // them were run on
+        if let Some(receiver) = &stream {
+            let walked = loop {
+                let received = if index == targets.len() {
+                    receiver
+                        .recv()
+                        .map_err(|_| mpsc::TryRecvError::Disconnected)
+                } else {
+                    receiver.try_recv()
+                };
+                match received {
+                    Ok(commit) => {
+                        let commit = commit?;
+                        if !skip.contains(&commit) {
+                            targets.push(Target::Commit(commit));
+                        }
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break false,
+                    // the sender is only dropped when the walk is done
+                    Err(mpsc::TryRecvError::Disconnected) => break true,
+                }
+            };
+            if walked {
+                eprintln!(
+                    "Found all {} commits to run on",
+                    done.len() + targets.len() - index
+                );
+                stream = None;
+                continue;
+            }
+        }
+
+        // check if the revisions still point to the same commits, and only walk
+        // them again when their ends changed
+        if check_ref_change && !ref_change_seen {
+            let current_ends = range_ends(vcs, &resolved_revs)?;
+            if current_ends != rev_ends {
+                rev_ends = current_ends;
+                let current_commits = expand_commits(vcs, &resolved_revs)?;
+                if current_commits != rev_commits {
+                    match opts.on_ref_change {
+                        OnRefChange::Ignore => {}
+                        OnRefChange::Warn => {
+                            eprintln!(
+                                "Warning: commits changed during run, continuing with original commits"
+                            );
+                            ref_change_seen = true;
+                        }
+                        OnRefChange::Abort => return Err("commits changed during run".into()),
+                        OnRefChange::Restart => {
+                            eprintln!("Commits changed during run, continuing with new commits");
+                            vcs.fetch_commits(&tmpdir, &current_commits)?;
+                            rev_commits = current_commits;
+                            let skip = skip.union(&done).cloned().collect();
+                            targets = select_targets(&rev_commits, &extra_targets, &skip);
+                            if let (Some(commit_order), Some(git_dir)) = (commit_order, git_dir) {
+                                order_targets(git_dir, &mut targets, commit_order)?;
+                            }
+                            if let Some(priority) = &opts.prioritize {
+                                prioritize(vcs, &mut targets, priority, &opts.command)?;
+                            }
+                            index = 0;
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+
+        if deadline.is_some_and(|deadline| Local::now() >= deadline) {
+            eprintln!(
+                "Deadline reached, not running on the remaining {} commits",
+                targets.len() - index
+            );
+            break;
+        }
+        if runner::is_interrupted() {
+            eprintln!(
+                "Interrupted, not running on the remaining {} commits",
+                targets.len() - index
+            );
+            break;
+        }
+
+        let target = &targets[index];
+        index += 1;
+        let total = done.len() + targets.len() - index + 1;
+
+        if opts.title {
+            let finish_time = estimator.finish_time(total - done.len());
+            term::set_title(progress_title(done.len(), total, failed, finish_time))?;
+        }
+
+        let target_start = Instant::now();
+
+        let info = vcs.commit_info(target.commit())?;
+
+        // reuse the results of the previous commit if nothing relevant changed
+        let unchanged_since = match (git_dir, target, &previous) {
+            (Some(git_dir), Target::Commit(commit), Some((previous_commit, previous_results)))
+                if !opts.relevant_paths.is_empty()
+                    && !git::paths_differ(
+                        git_dir,
+                        previous_commit,
+                        commit,
+                        &opts.relevant_paths,
+                    )? =>
+            {
+                Some(results[previous_results.clone()].to_vec())
+            }
+            _ => None,
+        };
+
+        let first_result = results.len();
+        let mut statuses = Vec::new();
+        let mut output_hashes = HashMap::new();
+        for variant in &variants {
+            let reused = match &unchanged_since {
+                Some(previous_results) => reuse_results(&ctx, &info, variant, previous_results)?,
+                None => None,
+            };
+            let was_reused = reused.is_some();
+            let mut variant_results = match reused {
+                Some(reused) => reused,
+                None => {
+                    if let (Some(bench), None | Some(1)) = (&bench, variant.repetition) {
+                        for warmup in 1..=bench.warmup {
+                            cool_down(bench, &mut first_command);
+                            let warmup_variant = matrix::Variant {
+                                warmup: Some(warmup),
+                                ..variant.clone()
+                            };
+                            run_app_for(&ctx, target, &info, &warmup_variant)?;
+                        }
+                    }
+                    if let Some(bench) = &bench {
+                        cool_down(bench, &mut first_command);
+                    }
+                    run_app_for(&ctx, target, &info, variant)?
+                }
+            };
+
+            // compare the output with the first time the variant was run
+            if let (Some(path), false) = (&opts.check_reproducible, was_reused) {
+                let hash = reproducible::hash(&tmpdir.join(path))?;
+                for result in &mut variant_results {
+                    result.output_hash = Some(hash.clone());
+                }
+                let first_hash = output_hashes
+                    .entry(variant.env.clone())
+                    .or_insert_with(|| hash.clone());
+                if *first_hash != hash {
+                    eprintln!(
+                        "Not reproducible: {} differs between runs on {:.7} ({:.7} and {:.7})",
+                        path.to_string_lossy(),
+                        target.commit(),
+                        first_hash,
+                        hash
+                    );
+                    not_reproducible.push((target.commit().to_string(), variant.label()));
+                }
+            }
+
+            for mut result in variant_results {
+                // only worth recording when there was more than one revision
+                if revs.len() > 1 {
+                    if let Some(selected_by) = provenance.get(&result.info.commit) {
+                        result.revs = selected_by.clone();
+                    }
+                }
+                let status = result.status;
+                if status.is_failure() {
+                    failed += 1;
+                }
+                match status {
+                    Status::Skipped | Status::NotRun => {}
+                    Status::Xfail(_) => last_exit_code = 0,
+                    _ => last_exit_code = status.code().unwrap_or(255),
+                }
+                if let Some(events) = &mut events {
+                    events.write(&events::Event::Result(&result))?;
+                }
+                if let Some(template) = &opts.result_file {
+                    report::write_result_file(template, &result)?;
+                }
+                statuses.push(status);
+                results.push(result);
+            }
+        }
+
+        // a commit only passes if it passes for all variants
+        if let (true, Some(git_dir), Target::Commit(commit)) = (opts.mark, git_dir, target) {
+            if statuses.iter().any(|status| status.is_failure()) {
+                git::mark_commit(git_dir, commit, false)?;
+            } else if statuses
+                .iter()
+                .all(|status| matches!(status, Status::Success(_)))
+            {
+                git::mark_commit(git_dir, commit, true)?;
+            }
+        }
+        let commit_failed = statuses.iter().any(|status| status.is_failure());
+        if opts.bell && commit_failed {
+            term::bell()?;
+        }
+        done.insert(target.commit().to_string());
+        previous = Some((target.commit().to_string(), first_result..results.len()));
+        grid.push((target.commit().to_string(), statuses));
+
+        hooks.run(&hooks::HookEvent::CommitFinish {
+            commit: target.commit(),
+            results: &results[first_result..],
+            done: done.len(),
+            total,
+        })?;
+
+        // estimate when the run is done from how long commits took so far
+        estimator.add(target_start.elapsed());
+        let commits_left = total - done.len();
+        let remaining = estimator.remaining(commits_left);
+        let finish_time = estimator.finish_time(commits_left);
+        if let (Some(remaining), Some(finish_time)) = (remaining, finish_time) {
+            if commits_left > 0 && estimator.should_show(finish_time) {
+                eprintln!(
+                    "Estimated to be done at {} (in {})",
+                    finish_time.format("%H:%M"),
+                    progress::format_duration(remaining)
+                );
+            }
+        }
+        if let Some(events) = &mut events {
+            events.write(&events::Event::Progress {
+                done: done.len(),
+                total,
+                failed,
+                remaining: remaining.map(|remaining| remaining.as_secs_f64()),
+                eta: finish_time.map(|finish_time| finish_time.to_rfc3339()),
+            })?;
+        }
+
+        if opts.fail_fast && commit_failed {
+            eprintln!("Stopping after first failure");
+            break;
+        }
+    }
+
+    // record the commits that the run was cut short before, so that reports
+    // tell them apart from commits that passed or failed
+    for target in &targets[index..] {
+        let info = vcs.commit_info(target.commit())?;
+        for variant in &variants {
+            for step in steps.iter() {
+                let label = run_label(step, variant);
+                results.push(placeholder_result(&info, label, Status::NotRun));
+            }
+        }
+    }
+
+    if opts.check_reproducible.is_some() {
+        if not_reproducible.is_empty() {
+            eprintln!("Output was the same every time on all commits");
+        } else {
+            eprintln!("Output was not reproducible on:");
+            for (commit, label) in &not_reproducible {
+                eprintln!("  {:.7} ({})", commit, label);
+            }
+            if last_exit_code == 0 {
+                last_exit_code = 1;
+            }
+        }
+    }
+
+    // passes of commits that are expected to fail, so that the expectations can
+    // be updated
+    let xpassed = results
+        .iter()
+        .filter(|result| matches!(result.status, Status::Xpass(_)))
+        .collect::<Vec<_>>();
+    if !xpassed.is_empty() {
+        eprintln!("Passed, but expected to fail (XPASS):");
+        for result in xpassed {
+            eprintln!("  {:.7} {}", result.info.commit, result.info.subject);
+        }
+    }
+
+    // page summaries, which can be long when running on many commits
+    let has_summary = variants.len() * steps.len() > 1
+        || opts.group_by != GroupBy::None
+        || opts.group_by_trailer.is_some()
+        || opts.plot
+        || failed > 1;
+    let pager = start_pager(vcs, opts.no_pager || opts.screen_reader || !has_summary)?;
+
+    if variants.len() * steps.len() > 1 {
+        let labels = variants
+            .iter()
+            .flat_map(|variant| steps.iter().map(move |step| run_label(step, variant)))
+            .collect::<Vec<_>>();
+        matrix::print_summary(&labels, &grid)?;
+    }
+
+    match opts.group_by {
+        GroupBy::None => {}
+        GroupBy::Author => {
+            report::print_grouped_summary("author", &results, |info| vec![info.author.clone()])?
+        }
+        GroupBy::Day => report::print_grouped_summary("day", &results, |info| {
+            vec![info.date.chars().take(10).collect()]
+        })?,
+    }
+    if let Some(key) = &opts.group_by_trailer {
+        report::print_grouped_summary(key, &results, |info| {
+            let values = info.trailer_values(key);
+            if values.is_empty() {
+                vec!["(none)".to_string()]
+            } else {
+                values.into_iter().map(str::to_string).collect()
+            }
+        })?;
+    }
+
+    if opts.plot {
+        plot::print(&results)?;
+    }
+    if failed > 1 {
+        signature::print_clusters(&results)?;
+    }
+
+    drop(pager);
+
+    if opts.suggest_trailers {
+        let commits = rev_commits
+            .iter()
+            .map(|commit| vcs.commit_info(commit))
+            .collect::<io::Result<Vec<_>>>()?;
+        if plan::is_linear(&commits) {
+            report::print_trailer_todo(&commits, &results)?;
+        } else {
+            eprintln!("Not suggesting trailers, since the commits are not a linear history");
+        }
+    }
+
+    check_leftover_processes(&tmpdir, opts.kill_daemons);
+
+    // keep results, so that they can be compared with later runs
+    let mut run = store::Run {
+        id: started.format("%Y%m%d-%H%M%S").to_string(),
+        started: started.to_rfc3339(),
+        label: opts.label.clone(),
+        command: report_command(&opts, &steps),
+        dir: Some(tmpdir.clone()),
+        results,
+        args,
+        commits: grid.iter().map(|(commit, _)| commit.clone()).collect(),
+        stashes: stash_entries.clone(),
+        stash_as_commit,
+        env: store::env_snapshot(),
+        bench: bench.clone(),
+    };
+    if let Some(git_dir) = git_dir {
+        // keep applied stash entries, so the run can be replayed after they are dropped
+        for stash in run.stashes.iter().chain(&run.stash_as_commit) {
+            git::keep_commit(git_dir, &stash.commit)?;
+        }
+    }
+    store.save(&mut run)?;
+    hooks.run(&hooks::HookEvent::RunFinish(&run))?;
+    if let Some(git_dir) = git_dir {
+        let retention = config_retention(git_dir)?;
+        if retention.is_set() {
+            prune_runs(vcs, &store, &retention)?;
+        }
+    }
+
+    if let Some(baseline) = &baseline {
+        let known_failures = baseline
+            .results
+            .iter()
+            .filter(|result| result.status.is_failure())
+            .map(|result| (&result.info.commit, &result.label))
+            .collect::<HashSet<_>>();
+        let (known, new): (Vec<_>, Vec<_>) = run
+            .results
+            .iter()
+            .filter(|result| result.status.is_failure())
+            .partition(|result| known_failures.contains(&(&result.info.commit, &result.label)));
+
+        if !known.is_empty() {
+            eprintln!(
+                "Ignoring {} failures that also failed in baseline run {}",
+                known.len(),
+                baseline.id
+            );
+        }
+        last_exit_code = new
+            .last()
+            .map(|result| result.status.code().unwrap_or(255))
+            .unwrap_or(0);
+    }
+    if runner::is_interrupted() {
+        last_exit_code = 130;
+    }
+
+    if let Some(path) = &opts.json {
+        run.write(path)?;
+    }
+    if let Some(path) = &opts.html {
+        report::write_html(path, &run, &date_format)?;
+    }
+    if let Some(path) = &opts.csv {
+        report::write_csv(path, &run)?;
+    }
+
+    if opts.title {
+        term::set_title(progress_title(done.len(), done.len(), failed, None))?;
+    }
+    if opts.bell {
+        term::bell()?;
+    }
+
+    #[cfg(feature = "self-update")]
+    update::notify_if_outdated(git_dir);
+
+    Ok(last_exit_code)
+}
+
+/// Use colors as given with `--color`, or as set with `color.ui` in git config.
+fn set_color(vcs: &dyn Vcs, color: Option<ColorWhen>) -> io::Result<()> {
+    let setting = match (color, vcs.git_dir()) {
+        (Some(ColorWhen::Auto), _) => Some("auto".to_string()),
+        (Some(ColorWhen::Always), _) => Some("always".to_string()),
+        (Some(ColorWhen::Never), _) => Some("never".to_string()),
+        (None, Some(git_dir)) => git::get_config_values(git_dir, "color.ui")?.pop(),
+        (None, None) => None,
+    };
+    term::set_color(setting.as_deref());
+    Ok(())
+}
+
+/// Show stdout in a pager until the returned pager is dropped, unless disabled.
+fn start_pager(vcs: &dyn Vcs, no_pager: bool) -> io::Result<Option<pager::Pager>> {
+    if no_pager {
+        return Ok(None);
+    }
+    pager::Pager::start(vcs.git_dir())
+}
+
+/// Format of dates as set with `log.date` in git config, or the default if it
+/// is not set or is not a format that is supported.
+fn config_date_format(git_dir: &Path) -> io::Result<DateFormat> {
+    Ok(git::get_config_values(git_dir, "log.date")?
+        .pop()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default())
+}
+
+/// How long and how many runs are kept for, as set with `corun.resultsMaxAge`
+/// and `corun.resultsMaxEntries` in git config.
+fn config_retention(git_dir: &Path) -> Result<store::Retention, Box<dyn Error>> {
+    let max_age = git::get_config_values(git_dir, "corun.resultsMaxAge")?.pop();
+    let max_entries = git::get_config_values(git_dir, "corun.resultsMaxEntries")?.pop();
+    Ok(store::Retention {
+        max_age: max_age
+            .map(|value| cli::parse_duration(&value))
+            .transpose()
+            .map_err(|err| format!("corun.resultsMaxAge: {}", err))?,
+        max_entries: max_entries
+            .map(|value| value.parse())
+            .transpose()
+            .map_err(|err| format!("corun.resultsMaxEntries: {}", err))?,
+    })
+}
+
+/// Remove runs as allowed by a retention, and stop keeping stash entries that
+/// only removed runs used. Returns the number of runs removed.
+fn prune_runs(
+    vcs: &dyn vcs::Vcs,
+    store: &store::Store,
+    retention: &store::Retention,
+) -> io::Result<usize> {
+    let count = store.list()?.len();
+    let kept = store.prune(retention)?;
+    if let Some(git_dir) = vcs.git_dir() {
+        let stashes = kept
+            .iter()
+            .flat_map(|run| run.stashes.iter().chain(&run.stash_as_commit))
+            .map(|stash| stash.commit.as_str())
+            .collect::<HashSet<_>>();
+        git::drop_kept_commits(git_dir, &stashes)?;
+    }
+    Ok(count - kept.len())
+}
+
+/// Git config for the clone and commands, as set with `corun.gitConfig` in git
+/// config.
+fn config_git_config(git_dir: &Path) -> Result<Vec<GitConfig>, Box<dyn Error>> {
+    Ok(git::get_config_values(git_dir, "corun.gitConfig")?
+        .iter()
+        .map(|value| value.parse())
+        .collect::<Result<_, _>>()?)
+}
+
+/// Warn about processes that are still using a directory after running, which
+/// are not killed if they left their process group, and stop known daemons if
+/// `kill_daemons` is set.
+fn check_leftover_processes(dir: &Path, kill_daemons: bool) {
+    let processes = procs::using_dir(dir);
+    if processes.is_empty() {
+        return;
+    }
+
+    let (daemons, others): (Vec<_>, Vec<_>) = processes
+        .into_iter()
+        .partition(|process| procs::daemon_name(process).is_some());
+    if kill_daemons && !daemons.is_empty() {
+        eprintln!("Stopping daemons using {}:", dir.to_string_lossy());
+        for process in &daemons {
+            eprintln!(
+                "  {:>7} {}",
+                process.pid,
+                procs::daemon_name(process).unwrap()
+            );
+        }
+        procs::terminate(&daemons);
+    }
+
+    let remaining = if kill_daemons {
+        others
+    } else {
+        daemons.into_iter().chain(others).collect()
+    };
+    if remaining.is_empty() {
+        return;
+    }
+    eprintln!(
+        "Warning: processes are still using {}:",
+        dir.to_string_lossy()
+    );
+    for process in &remaining {
+        match procs::daemon_name(process) {
+            Some(name) => eprintln!(
+                "  {:>7} {} (use --kill-daemons to stop it)",
+                process.pid, name
+            ),
+            None => eprintln!("  {:>7} {}", process.pid, process.command),
+        }
+    }
+}
+
+/// Build the list of targets to run on, leaving out the given commits.
+fn select_targets(
+    rev_commits: &[String],
+    extra_targets: &[Target],
+    skip: &HashSet<String>,
+) -> Vec<Target> {
+    rev_commits
+        .iter()
+        .cloned()
+        .map(Target::Commit)
+        .chain(extra_targets.iter().cloned())
+        .filter(|target| !skip.contains(target.commit()))
+        .collect()
+}
+
+/// Arguments corun was run with, without the `run` subcommand.
+fn run_args() -> Vec<String> {
+    let mut args = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("run") {
+        args.remove(0);
+    }
+    args
+}
+
+/// Command line to show in reports, with steps given as `NAME=COMMAND`.
+fn report_command(opts: &Options, steps: &[steps::Step]) -> Vec<String> {
+    if opts.steps.is_empty() {
+        opts.command.clone()
+    } else {
+        steps
+            .iter()
+            .map(|step| format!("{}={}", step.name, step.command.join(" ")))
+            .collect()
+    }
+}
+
+/// Sort commits to run on in an order where parents come before children.
+fn order_targets(git_dir: &Path, targets: &mut [Target], order: order::Order) -> io::Result<()> {
+    let commits = targets
+        .iter()
+        .map(|target| target.commit().to_string())
+        .collect::<Vec<_>>();
+    let ranks = order::sort(git_dir, &commits, order)?
+        .into_iter()
+        .enumerate()
+        .map(|(rank, commit)| (commit, rank))
+        .collect::<HashMap<_, _>>();
+    targets.sort_by_key(|target| ranks.get(target.commit()).copied().unwrap_or(usize::MAX));
+    Ok(())
+}
+
+/// Reorder targets so that the commits to run on first come first.
+fn prioritize(
+    vcs: &dyn Vcs,
+    targets: &mut [Target],
+    priority: &Priority,
+    command: &[String],
+) -> io::Result<()> {
+    let commits = targets
+        .iter()
+        .map(|target| target.commit().to_string())
+        .collect::<Vec<_>>();
 
-    #[structopt(help = "Command to execute", required = true, last = true)]
-    command: Vec<String>,
+    match priority {
+        Priority::Recent => {
+            let order = vcs.newest_first(&commits)?;
+            let ranks = order
+                .iter()
+                .enumerate()
+                .map(|(rank, commit)| (commit.as_str(), rank))
+                .collect::<HashMap<_, _>>();
+            targets.sort_by_key(|target| ranks.get(target.commit()).copied().unwrap_or(usize::MAX));
+        }
+        Priority::Touching(pathspec) => {
+            let touching = vcs.commits_touching(&commits, pathspec)?;
+            targets.sort_by_key(|target| !touching.contains(target.commit()));
+        }
+        Priority::Candidates => {
+            let git_dir = vcs.git_dir().expect("only used with git");
+            let heuristics = candidates::Heuristics::load(git_dir, command)?;
+            let scores = candidates::scores(git_dir, &commits, &heuristics)?;
+            // stable, so commits with the same score keep their order
+            targets.sort_by_key(|target| {
+                std::cmp::Reverse(scores.get(target.commit()).copied().unwrap_or(0))
+            });
+        }
+        Priority::Random => {
+            // xorshift, seeded by the current time, is random enough for this
+            let mut state = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or_default()
+                | 1;
+            for i in (1..targets.len()).rev() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                targets.swap(i, (state % (i as u64 + 1)) as usize);
+            }
+        }
+    }
+    Ok(())
 }
 
-impl Options {
-    fn from_args_safe() -> clap::Result<Self> {
-        Ok(Self::from_clap(&Self::clap().get_matches_safe()?))
+fn progress_title(
+    done: usize,
+    total: usize,
+    failed: usize,
+    finish_time: Option<DateTime<Local>>,
+) -> String {
+    let mut title = format!("corun {}/{}", done, total);
+    if failed > 0 {
+        title += &format!(" ✘{}", failed);
+    }
+    if let Some(finish_time) = finish_time {
+        title += &format!(" ~{}", finish_time.format("%H:%M"));
     }
+    title
 }
 
-fn main() {
-    let opts = Options::from_args_safe().unwrap_or_else(|err| {
-        if err.use_stderr() {
-            eprintln!("{}", err.message);
-            process::exit(128);
+fn set_app(cmd: SetCommand) -> Result<(), Box<dyn Error>> {
+    let git_dir = git::get_git_dir()?;
+
+    match cmd {
+        SetCommand::Save { name, revs } => {
+            let commits = expand_commits(&GitVcs::with_git_dir(git_dir.clone()), &revs)?;
+            git::save_commit_set(&git_dir, &name, &commits)?;
+            eprintln!("Saved {} commits to set {}", commits.len(), name);
         }
-        let out = io::stdout();
-        writeln!(&mut out.lock(), "{}", err.message).expect("Error writing error to stdout");
-        process::exit(128);
-    });
+        SetCommand::List => {
+            for name in git::list_commit_sets(&git_dir)? {
+                let commits = git::get_commit_set(&git_dir, &name)?;
+                println!("{} ({} commits)", name, commits.len());
+            }
+        }
+    }
 
-    match app(opts) {
-        Err(err) => {
-            eprintln!("Error: {}", err);
-            process::exit(128);
+    Ok(())
+}
+
+fn list_app(cmd: ListCommand) -> Result<(), Box<dyn Error>> {
+    let vcs = vcs::open(cmd.vcs)?;
+    set_color(vcs.as_ref(), cmd.color)?;
+    let _pager = start_pager(vcs.as_ref(), cmd.no_pager)?;
+    let runs = store::Store::open(vcs.as_ref()).list()?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for run in &runs {
+        let commits = run
+            .results
+            .iter()
+            .map(|result| &result.info.commit)
+            .collect::<HashSet<_>>();
+        let failed = run
+            .results
+            .iter()
+            .filter(|result| result.status.is_failure())
+            .map(|result| &result.info.commit)
+            .collect::<HashSet<_>>();
+
+        write!(stdout, "{}", term::paint("33", &run.id))?;
+        if let Some(label) = &run.label {
+            write!(stdout, " {}", term::paint("35", format!("({})", label)))?;
         }
-        Ok(exit_code) => process::exit(exit_code),
+        write!(stdout, " {} commits", commits.len())?;
+        if !failed.is_empty() {
+            write!(
+                stdout,
+                ", {}",
+                term::paint("31", format!("{} failed", failed.len()))
+            )?;
+        }
+        let notes = run
+            .results
+            .iter()
+            .map(|result| result.notes.len())
+            .sum::<usize>();
+        let not_run = run
+            .results
+            .iter()
+            .filter(|result| !result.status.was_run())
+            .map(|result| &result.info.commit)
+            .collect::<HashSet<_>>();
+        if !not_run.is_empty() {
+            write!(stdout, ", {} not run", not_run.len())?;
+        }
+        if notes > 0 {
+            write!(stdout, ", {} notes", notes)?;
+        }
+        writeln!(stdout, ": {}", run.command.join(" "))?;
     }
+    Ok(())
 }
 
-fn app(opts: Options) -> Result<i32, Box<dyn Error>> {
-    // get git directory
+fn regressions_app(cmd: RegressionsCommand) -> Result<(), Box<dyn Error>> {
+    let vcs = vcs::open(cmd.vcs)?;
+    set_color(vcs.as_ref(), cmd.color)?;
+    let _pager = start_pager(vcs.as_ref(), cmd.no_pager)?;
+    let runs = store::Store::open(vcs.as_ref()).list()?;
+
+    // compare the latest run with the one before it that ran the same command
+    let (latest, earlier) = runs.split_last().ok_or("no runs found")?;
+    let previous = earlier
+        .iter()
+        .rev()
+        .find(|run| run.command == latest.command)
+        .ok_or_else(|| format!("no earlier run of: {}", latest.command.join(" ")))?;
+
+    let regressions = regressions::find(previous, latest);
+    regressions::print(previous, latest, &regressions)?;
+    Ok(())
+}
+
+fn diff_runs_app(cmd: DiffRunsCommand) -> Result<(), Box<dyn Error>> {
+    let vcs = vcs::open(cmd.vcs)?;
+    set_color(vcs.as_ref(), cmd.color)?;
+    let _pager = start_pager(vcs.as_ref(), cmd.no_pager)?;
+    let store = store::Store::open(vcs.as_ref());
+
+    let run_a = store.load_or_read(&cmd.run_a)?;
+    let run_b = store.load_or_read(&cmd.run_b)?;
+    let changes = regressions::diff(&run_a, &run_b);
+    regressions::print_diff(&run_a, &run_b, &changes)?;
+    Ok(())
+}
+
+fn range_diff_app(cmd: RangeDiffCommand) -> Result<i32, Box<dyn Error>> {
     let git_dir = git::get_git_dir()?;
+    let range_diff = range_diff::pairs(&git_dir, &cmd.old, &cmd.new)?;
+    if range_diff.pairs.is_empty() {
+        return Err(format!("no commits in {} and {} could be paired", cmd.old, cmd.new).into());
+    }
 
-    // get latest stash commit
-    let stash_commit = if opts.apply_stash {
-        Some(git::get_commit_hash(&git_dir, "refs/stash")?)
-    } else if opts.apply_index {
-        unimplemented!()
+    // run on the commits of both ranges in one run, as if given on the command line
+    let label = format!("range-diff {} {}", cmd.old, cmd.new);
+    let mut args = vec!["--label".to_string(), label];
+    args.extend(range_diff.commits());
+    args.push("--".to_string());
+    args.extend(cmd.command.iter().cloned());
+    let mut opts = Options::from_iter_safe(
+        std::iter::once("git-corun").chain(args.iter().map(String::as_str)),
+    )?;
+
+    // read the results back from a report, since the run may also end early
+    let report = create_temp_file("corun-range-diff", ".json")?;
+    opts.json = Some(report.clone());
+    let run = app(opts, args, None).and_then(|_| {
+        store::Run::read(&report).map_err(|err| {
+            format!(
+                "could not read the results of the run from {}: {}",
+                report.to_string_lossy(),
+                err
+            )
+            .into()
+        })
+    });
+    let removed = fs::remove_file(&report);
+    let run = run?;
+    removed?;
+
+    let changes = range_diff::changes(&range_diff, &run);
+    range_diff::print(&range_diff, &changes)?;
+    Ok(if changes.is_empty() { 0 } else { 1 })
+}
+
+fn exec_lines_app(cmd: ExecLinesCommand) -> Result<(), Box<dyn Error>> {
+    let vcs = vcs::open(cli::VcsKind::Git)?;
+    let commits = vcs
+        .expand_commits(&cmd.range)?
+        .iter()
+        .map(|commit| vcs.commit_info(commit))
+        .collect::<io::Result<Vec<_>>>()?;
+    if !plan::is_linear(&commits) {
+        return Err(format!("{} is not a linear history", cmd.range).into());
+    }
+
+    let steps = if cmd.steps.is_empty() {
+        vec![steps::Step {
+            name: String::new(),
+            deps: Vec::new(),
+            command: cmd.command.clone(),
+            shell: cmd.shell_command,
+            env: Vec::new(),
+        }]
     } else {
-        None
+        steps::from_specs(&cmd.steps)?
     };
 
-    // expand list of commits
-    let commits = opts
-        .commits
+    // the rebase stops at the first command that fails, so steps only run
+    // after the steps they depend on succeeded
+    let execs = matrix::variants(&cmd.matrix)
         .iter()
-        .map(|commit| git::get_commit_hashes(&git_dir, commit))
-        .collect::<Result<Vec<_>, _>>()?
-        .into_iter()
-        .flatten()
+        .flat_map(|variant| {
+            steps
+                .iter()
+                .map(|step| step.shell_line(&cmd.shell, &variant.env))
+                .collect::<Vec<_>>()
+        })
         .collect::<Vec<_>>();
+    plan::print_exec_todo(&commits, &execs)?;
+    Ok(())
+}
 
-    // create temporary directory (and possibly clean up old ones)
-    let tmpdir = create_directory(&opts)?;
-    eprintln!("Running in directory: {}", tmpdir.to_string_lossy());
+fn annotate_app(cmd: AnnotateCommand) -> Result<(), Box<dyn Error>> {
+    let vcs = vcs::open(cmd.vcs)?;
+    let store = store::Store::open(vcs.as_ref());
+    let mut run = store.load(&cmd.run)?;
 
-    // git clone into temporary directory
-    git::clone_local(&git_dir, &tmpdir)?;
+    let commits = vcs
+        .expand_commits(&cmd.commit)
+        .map_err(|_| format!("not a commit: {}", cmd.commit))?;
+    let mut count = 0;
+    for result in &mut run.results {
+        if commits.contains(&result.info.commit)
+            && cmd
+                .label
+                .as_ref()
+                .is_none_or(|label| &result.label == label)
+        {
+            result.notes.push(cmd.message.clone());
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return Err(format!("run {} has no results on {}", run.id, cmd.commit).into());
+    }
+    store.update(&run)?;
 
-    let mut last_exit_code = 0;
-    for commit in commits {
-        last_exit_code = run_app_for(
-            &opts,
-            &git_dir,
-            tmpdir.as_ref(),
-            &commit,
-            stash_commit.as_ref().map(String::as_str),
-        )?;
+    // the query database only adds runs it does not have yet, so let it be
+    // created again with the notes
+    let db = vcs.store_dir().join("results.sqlite");
+    if db.exists() {
+        fs::remove_file(db)?;
     }
 
-    Ok(last_exit_code)
+    eprintln!("Added note to {} results of run {}", count, run.id);
+    Ok(())
+}
+
+fn import_app(cmd: ImportCommand) -> Result<(), Box<dyn Error>> {
+    let vcs = vcs::open(cmd.vcs)?;
+    let store = store::Store::open(vcs.as_ref());
+
+    for path in &cmd.reports {
+        let mut run =
+            store::Run::read(path).map_err(|err| format!("{}: {}", path.to_string_lossy(), err))?;
+        if !store::is_valid_id(&run.id) {
+            if !run.id.is_empty() {
+                eprintln!(
+                    "Warning: {} has an invalid run identifier {:?}, using a new one",
+                    path.to_string_lossy(),
+                    run.id
+                );
+            }
+            run.id = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        }
+        store.save(&mut run)?;
+        eprintln!("Imported {} as run {}", path.to_string_lossy(), run.id);
+    }
+
+    Ok(())
+}
+
+fn gc_app(cmd: GcCommand) -> Result<(), Box<dyn Error>> {
+    if !cmd.results {
+        return Err("nothing to remove (use --results to remove old results of past runs)".into());
+    }
+
+    let vcs = vcs::open(cmd.vcs)?;
+    let mut retention = match vcs.git_dir() {
+        Some(git_dir) => config_retention(git_dir)?,
+        None => store::Retention::default(),
+    };
+    retention.max_age = cmd.results_max_age.or(retention.max_age);
+    retention.max_entries = cmd.results_max_entries.or(retention.max_entries);
+    if !retention.is_set() {
+        return Err("no retention given (use --results-max-age or --results-max-entries, or set corun.resultsMaxAge or corun.resultsMaxEntries)".into());
+    }
+
+    let store = store::Store::open(vcs.as_ref());
+    let count = prune_runs(vcs.as_ref(), &store, &retention)?;
+    eprintln!("Removed {} runs", count);
+    Ok(())
+}
+
+fn query_app(cmd: QueryCommand) -> Result<(), Box<dyn Error>> {
+    preflight::check_executable(query::COMMAND)?;
+
+    let vcs = vcs::open(cmd.vcs)?;
+    let _pager = start_pager(vcs.as_ref(), cmd.no_pager)?;
+    let store = store::Store::open(vcs.as_ref());
+    let db = vcs.store_dir().join("results.sqlite");
+    query::run(&store, &db, &cmd.sql, cmd.format)?;
+    Ok(())
+}
+
+fn dir_app(cmd: DirCommand) -> Result<(), Box<dyn Error>> {
+    let vcs = vcs::open(cmd.vcs)?;
+    let runs = store::Store::open(vcs.as_ref()).list()?;
+
+    let run = match (cmd.last, &cmd.run) {
+        (true, _) | (false, None) => runs.last().ok_or("no runs found")?,
+        (false, Some(id)) if runs.iter().any(|run| &run.id == id) => {
+            runs.iter().find(|run| &run.id == id).unwrap()
+        }
+        (false, Some(rev)) => {
+            let commits = vcs
+                .expand_commits(rev)
+                .map_err(|_| format!("not a run id or commit: {}", rev))?;
+            runs.iter()
+                .rev()
+                .find(|run| {
+                    run.results
+                        .iter()
+                        .any(|result| commits.contains(&result.info.commit))
+                })
+                .ok_or_else(|| format!("no runs found on {}", rev))?
+        }
+    };
+
+    let dir = run
+        .dir
+        .as_ref()
+        .ok_or_else(|| format!("run {} has no directory", run.id))?;
+    if !dir.is_dir() {
+        return Err(format!(
+            "directory of run {} no longer exists: {}",
+            run.id,
+            dir.to_string_lossy()
+        )
+        .into());
+    }
+
+    if cmd.decompress {
+        let log_dir = vcs.metadata_dir(dir).join("logs");
+        if log_dir.is_dir() {
+            let count = compress::decompress_dir(&log_dir)?;
+            eprintln!("Decompressed {} logs", count);
+        }
+    }
+
+    println!("{}", dir.to_string_lossy());
+    Ok(())
+}
+
+fn replay_app(cmd: ReplayCommand) -> Result<i32, Box<dyn Error>> {
+    let vcs = vcs::open(cmd.vcs)?;
+    let run = store::Store::open(vcs.as_ref()).load_or_read(&cmd.run)?;
+    if run.args.is_empty() {
+        return Err(format!(
+            "run {} was not recorded in a way that can be replayed",
+            run.id
+        )
+        .into());
+    }
+
+    let mut opts = Options::from_iter_safe(
+        std::iter::once("git-corun").chain(run.args.iter().map(String::as_str)),
+    )?;
+    eprintln!("Replaying run {}: git corun {}", run.id, run.args.join(" "));
+
+    // the commits and stash entries are the recorded ones instead
+    opts.commits.clear();
+    opts.set = None;
+    opts.plan = None;
+    opts.split_by_path = None;
+    opts.apply_stash = 0;
+    opts.apply_all_stashes = false;
+    opts.stash_as_commit = false;
+    opts.skip_marked = false;
+    opts.prioritize = None;
+    opts.label = Some(format!("replay of {}", run.id));
+
+    // runs recorded before the environment was limited have all of it, which
+    // should not replace the current session's
+    for (name, value) in &run.env {
+        if store::is_recorded_env(name) {
+            std::env::set_var(name, value);
+        }
+    }
+
+    app(opts, run.args.clone(), Some(&run))
+}
+
+#[cfg(feature = "self-update")]
+fn self_update_app(cmd: SelfUpdateCommand) -> Result<(), Box<dyn Error>> {
+    update::self_update(cmd.check)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "self-update"))]
+fn self_update_app(_cmd: SelfUpdateCommand) -> Result<(), Box<dyn Error>> {
+    Err("this build does not support updating itself (build with --features self-update, or update with cargo)".into())
+}
+
+fn completions_app(cmd: CompletionsCommand) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    cli::write_completions(cmd.shell, &mut stdout.lock())?;
+    Ok(())
+}
+
+fn complete_revs_app(cmd: CompleteRevsCommand) -> Result<(), Box<dyn Error>> {
+    let git_dir = git::get_git_dir()?;
+
+    // keep the start of a range, so that e.g. `main..` completes to `main..topic`
+    let current = cmd.current.unwrap_or_default();
+    let prefix = match current.rfind("..") {
+        Some(index) => &current[..index + 2],
+        None => "",
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for rev in git::list_completion_revs(&git_dir)? {
+        writeln!(stdout, "{}{}", prefix, rev)?;
+    }
+
+    Ok(())
+}
+
+fn remote_job_app(cmd: RemoteJobCommand) -> Result<i32, Box<dyn Error>> {
+    Ok(remote::run_job(&cmd.url, &cmd.env, &cmd.command)?)
+}
+
+fn expand_commits(vcs: &dyn Vcs, revs: &[impl AsRef<str>]) -> io::Result<Vec<String>> {
+    let commits_by_rev = revs
+        .iter()
+        .map(|rev| Ok((rev.as_ref(), vcs.expand_commits(rev.as_ref())?)))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(dedup_commits(commits_by_rev).0)
+}
+
+/// Commits at the ends of revisions, in order.
+fn range_ends(vcs: &dyn Vcs, revs: &[impl AsRef<str>]) -> io::Result<Vec<String>> {
+    let mut ends = Vec::new();
+    for rev in revs {
+        ends.extend(vcs.range_ends(rev.as_ref())?);
+    }
+    Ok(ends)
+}
+
+/// Commits of revisions without duplicates, in the order they are first
+/// selected in, and the revisions that selected each commit.
+fn dedup_commits(
+    commits_by_rev: Vec<(&str, Vec<String>)>,
+) -> (Vec<String>, HashMap<String, Vec<String>>) {
+    let mut commits = Vec::new();
+    let mut provenance: HashMap<String, Vec<String>> = HashMap::new();
+    for (rev, rev_commits) in commits_by_rev {
+        for commit in rev_commits {
+            let revs = provenance.entry(commit.clone()).or_insert_with(|| {
+                commits.push(commit);
+                Vec::new()
+            });
+            if !revs.iter().any(|selected_by| selected_by == rev) {
+                revs.push(rev.to_string());
+            }
+        }
+    }
+    (commits, provenance)
+}
+
+/// Everything that stays the same for all commits in a run.
+struct RunContext<'a> {
+    opts: &'a Options,
+    vcs: &'a dyn Vcs,
+    date_format: &'a DateFormat,
+    work_tree: &'a Path,
+    log_dir: &'a Path,
+    /// Directory for temporary files of commands, which is emptied for each
+    /// commit.
+    scratch_dir: &'a Path,
+    /// Environment variables for `--isolate-home`.
+    home_env: &'a [(String, String)],
+    /// Directories of the shared download caches, and the environment
+    /// variables that point to them.
+    cache_dirs: &'a [PathBuf],
+    cache_env: &'a [(String, String)],
+    jail: Option<&'a jail::Jail>,
+    stash_entries: &'a [git::StashEntry],
+    steps: &'a [steps::Step],
+    extract: Option<&'a metric::Extract>,
+    signatures: &'a signature::Signatures,
+    expected: &'a expected::ExpectedFailures,
+}
+
+/// Status of a command by whether it produced a file, ignoring its exit code
+/// unless it was killed.
+fn expected_file_status(status: Status, path: &Path, non_empty: bool) -> Status {
+    let code = match status {
+        Status::Success(code) | Status::Failure(code) | Status::Inconclusive(code) => code,
+        _ => return status,
+    };
+    let produced = match fs::metadata(path) {
+        Ok(metadata) => !non_empty || metadata.len() > 0,
+        Err(_) => false,
+    };
+    if produced {
+        Status::Success(code)
+    } else {
+        Status::Failure(code)
+    }
 }
 
 fn run_app_for(
-    opts: &Options,
-    git_dir: &Path,
-    work_tree: &Path,
-    commit: &str,
-    stash_commit: Option<&str>,
-) -> Result<i32, Box<dyn Error>> {
-    // get commit hash
-    let commit = git::get_commit_hash(&git_dir, commit)?;
+    ctx: &RunContext,
+    target: &Target,
+    info: &vcs::CommitInfo,
+    variant: &matrix::Variant,
+) -> Result<Vec<RunResult>, Box<dyn Error>> {
+    let RunContext {
+        opts,
+        vcs,
+        date_format,
+        work_tree,
+        log_dir,
+        scratch_dir,
+        home_env,
+        cache_dirs,
+        cache_env,
+        jail,
+        stash_entries,
+        steps,
+        extract,
+        signatures,
+        expected,
+    } = *ctx;
+
+    // get commit to check out, which for stashes is the parent commit
+    let (commit, checkout_commit, stash_entries) = match target {
+        Target::Commit(commit) => (commit, commit, stash_entries),
+        Target::Stash(stash) => (&stash.commit, &stash.parent, std::slice::from_ref(stash)),
+    };
 
     // check out directory
-    git::checkout_detached(&work_tree, &commit)?;
+    vcs.checkout(work_tree, checkout_commit)?;
 
     // clean directory
-    git::clean_work_dir(&work_tree)?;
+    vcs.clean(work_tree)?;
+    if let Some(path) = &opts.submodule {
+        checkout_submodule(work_tree, path)?;
+    }
+
+    // check that nothing else changed the checkout, e.g. another process using
+    // the same directory, and check it out again once if so
+    if !vcs.is_pristine(work_tree, checkout_commit)? {
+        eprintln!(
+            "Warning: {} was changed by something else, checking out {:.7} again",
+            work_tree.to_string_lossy(),
+            checkout_commit
+        );
+        vcs.checkout(work_tree, checkout_commit)?;
+        vcs.clean(work_tree)?;
+        if let Some(path) = &opts.submodule {
+            checkout_submodule(work_tree, path)?;
+        }
+        if !vcs.is_pristine(work_tree, checkout_commit)? {
+            eprintln!(
+                "Warning: {} still has changes after checking out {:.7} again",
+                work_tree.to_string_lossy(),
+                checkout_commit
+            );
+        }
+    }
+
+    // apply stashes
+    for (i, stash) in stash_entries.iter().enumerate() {
+        if i > 0 {
+            // git refuses to apply a stash on top of staged changes, so only the
+            // index of the last stash entry is kept
+            git::reset_index(work_tree)?;
+        }
+
+        git::apply_stash(work_tree, &stash.commit).map_err(|err| {
+            format!(
+                "could not apply {} ({:.7}) on {:.7}: {}",
+                stash.name, stash.commit, checkout_commit, err
+            )
+        })?;
+    }
+
+    // the parent to run against, which for merge commits is the chosen one
+    let parent = if info.parents.len() > 1 {
+        info.parents.get(opts.merge_parent.unwrap_or(1) - 1)
+    } else {
+        info.parents.first()
+    };
+    let mut env = variant.env.clone();
+    if let (Target::Commit(_), Some(parent)) = (target, parent) {
+        env.push(("CORUN_PARENT".to_string(), parent.clone()));
+    }
+    for name in &opts.param_from_trailer {
+        if let Some(value) = info.trailer_values(name).last() {
+            env.push((param_env_name(name), value.to_string()));
+        }
+    }
+
+    // directory to run in, which is the submodule with `--submodule`
+    let run_dir = match &opts.submodule {
+        Some(path) => work_tree.join(path),
+        None => work_tree.to_path_buf(),
+    };
+
+    // temporary files of one commit should not affect the next, and should be
+    // in the directory of the run rather than shared with everything else
+    if scratch_dir.exists() {
+        fs::remove_dir_all(scratch_dir)?;
+    }
+    fs::create_dir_all(scratch_dir)?;
+    let mut command_env = env.clone();
+    command_env.extend(home_env.iter().cloned());
+    if let Some(cargo_target) = opts.cargo_target {
+        let target_dir = vcs.metadata_dir(work_tree).join("cargo-target");
+        let target_dir = match cargo_target {
+            CargoTarget::Shared => target_dir.join("shared"),
+            CargoTarget::PerCommit => target_dir.join(commit),
+        };
+        command_env.push((
+            "CARGO_TARGET_DIR".to_string(),
+            target_dir.to_string_lossy().into_owned(),
+        ));
+    }
+    command_env.push((
+        "TMPDIR".to_string(),
+        scratch_dir.to_string_lossy().into_owned(),
+    ));
+
+    // run steps in order, skipping steps whose dependencies did not succeed
+    let mut results: Vec<RunResult> = Vec::new();
+    for (index, step) in steps.iter().enumerate() {
+        let label = run_label(step, variant);
+        let shown_label = merge_label(&label, info, opts.merge_parent);
+
+        let deps_succeeded = step
+            .deps
+            .iter()
+            .all(|&dep| matches!(results[dep].status, Status::Success(_)));
+        if !deps_succeeded {
+            vcs.show_commit(commit, Status::Skipped, &shown_label, date_format)?;
+            results.push(placeholder_result(info, label, Status::Skipped));
+            continue;
+        }
+        if runner::is_interrupted() {
+            results.push(placeholder_result(info, label, Status::NotRun));
+            continue;
+        }
+
+        // print commit
+        vcs.show_commit(commit, Status::Pending, &shown_label, date_format)?;
+
+        let shell = if step.shell {
+            Some(opts.shell.as_str())
+        } else {
+            None
+        };
+        let mut command_env = command_env.clone();
+        command_env.extend(step.env.iter().cloned());
+        let (command, shell) = match (&opts.dockerfile, &opts.remote) {
+            (Some(_), _) => {
+                let image_id_path = vcs.metadata_dir(work_tree).join("image-id");
+                // the caches are only in the environment of corun itself
+                command_env.extend(cache_env.iter().cloned());
+                let command = container::command(
+                    step,
+                    shell,
+                    &image_id_path,
+                    work_tree,
+                    cache_dirs,
+                    &run_dir,
+                    &command_env,
+                );
+                (command, None)
+            }
+            (None, Some(url)) => (remote::command(url, step, shell, &command_env)?, None),
+            (None, None) => (step.command.clone(), shell),
+        };
+        if opts.show_command {
+            eprintln!(
+                "{}",
+                runner::command_line(&command, shell, &run_dir, &command_env)
+            );
+        }
+        if opts.verbose >= 3 {
+            print_environment(&command_env);
+        }
+
+        // run command in repo
+        let log_path = log_dir.join(log_file_name(commit, &label));
+        let start = Instant::now();
+        let run_options = runner::RunOptions {
+            verbose: opts.verbose > 0,
+            timeout: opts.timeout,
+            pty: opts.pty,
+            memory_limit: opts.memory_limit,
+            jail: jail.map(jail::Jail::fd),
+            timestamps: opts.timestamps,
+        };
+        let (status, usage) = match runner::run_in(
+            &command,
+            shell,
+            &run_dir,
+            &command_env,
+            &log_path,
+            run_options,
+        ) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                (Status::Cancelled, runner::Usage::default())
+            }
+            result => result?,
+        };
+        let status = match &opts.expect_file {
+            Some(path) if index == steps.len() - 1 => {
+                expected_file_status(status, &run_dir.join(path), opts.expect_non_empty)
+            }
+            _ => status,
+        };
+        let (status, metric) = match extract {
+            Some(extract) if index == steps.len() - 1 => extract.classify(
+                commit,
+                parent.map(String::as_str),
+                &label,
+                status,
+                &log_path,
+            )?,
+            _ => (status, None),
+        };
+        let signature = if status.is_failure() {
+            signatures.extract(&log_path)?
+        } else {
+            None
+        };
+        let (status, expected_reason) = expected.classify(commit, status, signature.as_deref());
+
+        // print status, replacing the pending line if it is on a terminal, where
+        // it is the last line since there was no output
+        if opts.verbose == 0
+            && !opts.show_command
+            && !opts.screen_reader
+            && term::status_is_terminal()
+        {
+            let mut output = term::status_output()?;
+            write!(output, "\x1b[1F\x1b[K")?;
+            output.flush()?;
+        }
+        let shown_label = match metric {
+            Some(metric) => append_label(&shown_label, &metric.to_string()),
+            None => shown_label,
+        };
+        let shown_label = match (status, expected_reason) {
+            (Status::Xfail(_) | Status::Xpass(_), Some(reason)) if !reason.is_empty() => {
+                append_label(&shown_label, &format!("expected to fail: {}", reason))
+            }
+            (Status::Xfail(_) | Status::Xpass(_), _) => {
+                append_label(&shown_label, "expected to fail")
+            }
+            _ => shown_label,
+        };
+        vcs.show_commit(commit, status, &shown_label, date_format)?;
+        if opts.verbose >= 3 {
+            eprintln!(
+                "Took {:.3}s ({:.3}s user, {:.3}s system, {} KB max RSS)",
+                start.elapsed().as_secs_f64(),
+                usage.user_time,
+                usage.system_time,
+                usage.max_rss
+            );
+        }
+        let reproduce = if status.is_failure() && stash_entries.is_empty() {
+            reproduce_command(
+                vcs,
+                commit,
+                opts.submodule.as_deref(),
+                &command,
+                shell,
+                &[&env[..], &step.env[..]].concat(),
+            )
+        } else {
+            None
+        };
+        if let Some(reproduce) = &reproduce {
+            eprintln!("To reproduce: {}", reproduce);
+        }
+        if let (Status::Oom, None) = (status, opts.memory_limit) {
+            eprintln!("Warning: command was killed for using too much memory (use --memory-limit to limit it)");
+        }
+
+        // show output of failed commands
+        if opts.verbose_failed && status.is_failure() {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            io::copy(&mut fs::File::open(&log_path)?, &mut stdout)?;
+            stdout.flush()?;
+        }
+
+        // let the user look at the failure before the checkout is cleaned
+        if opts.on_failure == OnFailure::Shell && status.is_failure() {
+            if term::is_interactive() {
+                eprintln!(
+                    "Starting a shell in {} (exit it to continue)",
+                    run_dir.to_string_lossy()
+                );
+                runner::run_shell(&run_dir, &command_env)?;
+            } else {
+                eprintln!("Not starting a shell, since stdin is not a terminal");
+            }
+        }
+
+        if opts.compress_logs {
+            compress::compress(&log_path)?;
+            compress::compress(&runner::stream_log_path(&log_path, "stdout"))?;
+            compress::compress(&runner::stream_log_path(&log_path, "stderr"))?;
+        }
 
-    if let Some(stash_commit) = stash_commit {
-        // apply stash
-        git::apply_stash(&work_tree, stash_commit)?;
+        results.push(RunResult {
+            info: info.clone(),
+            label,
+            status,
+            duration: start.elapsed().as_secs_f64(),
+            usage: Some(usage),
+            output_hash: None,
+            metric,
+            revs: Vec::new(),
+            unchanged_since: None,
+            signature,
+            reproduce,
+            notes: Vec::new(),
+        });
     }
 
-    // print commit
-    print_commit(&git_dir, &commit, Status::Pending)?;
+    fs::remove_dir_all(scratch_dir)?;
+
+    Ok(results)
+}
+
+/// Shell command that checks out a commit in a new worktree of the repository
+/// and runs a command in it, like corun does, or `None` if the repository is
+/// not a git repository with a work tree.
+fn reproduce_command(
+    vcs: &dyn vcs::Vcs,
+    commit: &str,
+    submodule: Option<&Path>,
+    command: &[String],
+    shell: Option<&str>,
+    env: &[(String, String)],
+) -> Option<String> {
+    vcs.git_dir()?;
+    let root = vcs.root().ok()?;
+    let worktree = std::env::temp_dir().join(format!("corun-{:.7}", commit));
+    let worktree_quoted = runner::shell_quote(&worktree.to_string_lossy());
+    let mut reproduce = format!(
+        "git -C {} worktree add --detach {} {} && ",
+        runner::shell_quote(&root.to_string_lossy()),
+        worktree_quoted,
+        commit
+    );
+    let dir = match submodule {
+        Some(path) => {
+            reproduce.push_str(&format!(
+                "git -C {} submodule update --init -- {} && ",
+                worktree_quoted,
+                runner::shell_quote(&path.to_string_lossy())
+            ));
+            worktree.join(path)
+        }
+        None => worktree,
+    };
+    reproduce.push_str(&runner::command_line(command, shell, &dir, env));
+    Some(reproduce)
+}
+
+/// Check out the commit of a submodule that the checked out commit uses, for
+/// `--submodule`, without anything left from other commits.
+fn checkout_submodule(work_tree: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+    git::update_submodule(work_tree, path)
+        .map_err(|err| format!("could not check out submodule {}: {}", path.display(), err))?;
+    git::clean_work_dir(work_tree.join(path))?;
+    Ok(())
+}
+
+/// Print the environment that a command runs with, for `-vvv`.
+fn print_environment(env: &[(String, String)]) {
+    let mut vars = std::env::vars().collect::<BTreeMap<_, _>>();
+    vars.extend(env.iter().cloned());
+    eprintln!("Environment:");
+    for (name, value) in vars {
+        eprintln!("  {}={}", name, value);
+    }
+}
 
-    // run command in repo
-    let exit_status = run_in(&opts, opts.command.iter().map(String::as_str), &work_tree)?;
-    let status = exit_status.into();
+/// Result for a command that was not run, e.g. because a step it depends on
+/// did not succeed.
+fn placeholder_result(info: &vcs::CommitInfo, label: String, status: Status) -> RunResult {
+    RunResult {
+        info: info.clone(),
+        label,
+        status,
+        duration: 0.0,
+        usage: None,
+        output_hash: None,
+        metric: None,
+        revs: Vec::new(),
+        unchanged_since: None,
+        signature: None,
+        reproduce: None,
+        notes: Vec::new(),
+    }
+}
 
-    // print status
-    if !opts.verbose {
-        let stdout = io::stdout();
-        let mut stdout = stdout.lock();
-        write!(stdout, "\x1b[1F\x1b[K")?;
-        stdout.flush()?;
+/// Results for a commit copied from the results of a previous commit, when
+/// nothing in `--relevant-paths` changed since it, or `None` if the previous
+/// commit has no results for some of the commands.
+fn reuse_results(
+    ctx: &RunContext,
+    info: &vcs::CommitInfo,
+    variant: &matrix::Variant,
+    previous_results: &[RunResult],
+) -> Result<Option<Vec<RunResult>>, Box<dyn Error>> {
+    let mut results = Vec::new();
+    for step in ctx.steps {
+        let label = run_label(step, variant);
+        let previous = match previous_results.iter().find(|result| result.label == label) {
+            Some(previous) => previous,
+            None => return Ok(None),
+        };
+        let unchanged_since = previous
+            .unchanged_since
+            .clone()
+            .unwrap_or_else(|| previous.info.commit.clone());
+        results.push(RunResult {
+            info: info.clone(),
+            duration: 0.0,
+            usage: None,
+            output_hash: None,
+            revs: Vec::new(),
+            notes: Vec::new(),
+            reproduce: None,
+            unchanged_since: Some(unchanged_since),
+            ..previous.clone()
+        });
     }
-    print_commit(&git_dir, &commit, status)?;
 
-    Ok(exit_status.code().unwrap_or(255))
+    for result in &results {
+        let label = merge_label(&result.label, info, ctx.opts.merge_parent);
+        let shown_label = match &result.unchanged_since {
+            Some(commit) => append_label(&label, &format!("unchanged since {:.7}", commit)),
+            None => label,
+        };
+        ctx.vcs
+            .show_commit(&info.commit, result.status, &shown_label, ctx.date_format)?;
+    }
+    Ok(Some(results))
 }
 
-fn print_commit(
-    git_dir: impl AsRef<Path>,
-    commit: impl AsRef<str>,
-    status: Status,
-) -> io::Result<()> {
-    let base_format = "%C(yellow)%h %C(bold)%G? %Creset%C(cyan)[%Cgreen%ad%C(cyan) by %Cred%an%C(cyan)]%Creset %s";
-    let format = format!("{} {}", status.get_format(), base_format);
+/// Pause before running a command when benchmarking, except before the first.
+fn cool_down(bench: &bench::Settings, first_command: &mut bool) {
+    if !*first_command && bench.cooldown > 0.0 {
+        std::thread::sleep(std::time::Duration::from_secs_f64(bench.cooldown));
+    }
+    *first_command = false;
+}
 
-    git::show_commit(git_dir, commit, &format)
+/// Label to show for a commit, which for merge commits also shows the parent
+/// they are run against, when it is chosen with `--merge-parent`.
+fn merge_label(label: &str, info: &vcs::CommitInfo, merge_parent: Option<usize>) -> String {
+    let merge = match (info.parents.len(), merge_parent) {
+        (0..=1, _) => return label.to_string(),
+        (_, None) => "merge".to_string(),
+        (_, Some(n)) => match info.parents.get(n - 1) {
+            Some(parent) => format!("merge, against parent {} {:.7}", n, parent),
+            None => format!("merge without parent {}", n),
+        },
+    };
+    append_label(label, &merge)
 }
 
-fn run_in<'a, I>(opts: &Options, command: I, dir: impl AsRef<Path>) -> io::Result<ExitStatus>
-where
-    I: IntoIterator<Item = &'a str>,
-{
-    let mut command = command.into_iter();
-    let dir = dir.as_ref();
+/// Name of the environment variable a trailer is given to commands in with
+/// `--param-from-trailer`, e.g. `CORUN_PARAM_BENCH_SCENARIO` for
+/// `Bench-Scenario`.
+fn param_env_name(trailer: &str) -> String {
+    let name = trailer
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("CORUN_PARAM_{}", name)
+}
 
-    let cmd_first = command.next().unwrap();
-    let cmd_rest = command.collect::<Vec<_>>();
+/// Name of the log file for running a commit, e.g. `0123abc-build.log`.
+fn log_file_name(commit: &str, label: &str) -> String {
+    let label = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
 
-    let (exec_name, cmd_args) = if opts.shell_command {
-        let exec_name = "/bin/bash";
-        let mut args = vec!["-c", cmd_first, "--"];
-        args.extend(cmd_rest);
-        (exec_name, args)
+    if label.is_empty() {
+        format!("{:.7}.log", commit)
     } else {
-        (cmd_first, cmd_rest)
-    };
+        format!("{:.7}-{}.log", commit, label)
+    }
+}
+
+/// Label shown after a commit, identifying the step and variant.
+fn run_label(step: &steps::Step, variant: &matrix::Variant) -> String {
+    let variant_label = variant.label();
+    match (step.name.is_empty(), variant_label.is_empty()) {
+        (true, _) => variant_label,
+        (false, true) => step.name.clone(),
+        (false, false) => format!("{} {}", step.name, variant_label),
+    }
+}
+
+/// Time to stop starting to run on more commits, for a run started at a time.
+fn deadline_time(deadline: &Deadline, started: DateTime<Local>) -> DateTime<Local> {
+    match *deadline {
+        Deadline::After(duration) => Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| started.checked_add_signed(duration))
+            // durations too long to add are as good as no deadline
+            .unwrap_or_else(|| started + Duration::weeks(52 * 100)),
+        Deadline::At(hour, minute) => {
+            // the next time it is that time of day, which may be tomorrow, and
+            // is after the gap if daylight saving time skips it
+            let at = |date: NaiveDate| {
+                let time = date.and_hms_opt(hour, minute, 0)?;
+                Local.from_local_datetime(&time).earliest().or_else(|| {
+                    Local
+                        .from_local_datetime(&(time + Duration::hours(1)))
+                        .earliest()
+                })
+            };
+            let today = started.naive_local().date();
+            match at(today) {
+                Some(time) if time > started => time,
+                _ => today
+                    .succ_opt()
+                    .and_then(at)
+                    .unwrap_or_else(|| started + Duration::days(1)),
+            }
+        }
+    }
+}
 
-    if opts.verbose {
-        Command::new(&exec_name)
-            .args(&cmd_args)
-            .current_dir(&dir)
-            .stdin(Stdio::null())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .status()
+/// Add something to a label to show, e.g. `build, unchanged since 0123abc`.
+fn append_label(label: &str, extra: &str) -> String {
+    if label.is_empty() {
+        extra.to_string()
     } else {
-        Command::new(&exec_name)
-            .args(&cmd_args)
-            .current_dir(&dir)
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
+        format!("{}, {}", label, extra)
     }
 }
 
@@ -205,36 +2541,100 @@ fn default_base_dir() -> PathBuf {
     dirs::home_dir().expect("no home dir").join(".git-corun")
 }
 
-fn create_directory(opts: &Options) -> io::Result<PathBuf> {
-    const DATE_FORMAT_STR: &str = "%Y%m%d-%H%M%S-%f";
+/// Format of the start of names of run directories, in UTC, e.g.
+/// `20240131T235959Z`, so that they sort by time and do not depend on the time
+/// zone or daylight saving time.
+const DIR_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Format of names of run directories before they were in UTC, in local time.
+const LEGACY_DIR_DATE_FORMAT: &str = "%Y%m%d-%H%M%S-%f";
+
+/// Time a run directory was created, from its name.
+fn parse_dir_date(name: &str) -> Option<DateTime<Utc>> {
+    if let Some(date) = name
+        .get(..16)
+        .and_then(|date| NaiveDateTime::parse_from_str(date, DIR_DATE_FORMAT).ok())
+    {
+        return Some(Utc.from_utc_datetime(&date));
+    }
+
+    // local times can be ambiguous or skipped when daylight saving time changes
+    let date = NaiveDateTime::parse_from_str(name, LEGACY_DIR_DATE_FORMAT).ok()?;
+    match Local.from_local_datetime(&date).earliest() {
+        Some(date) => Some(date.with_timezone(&Utc)),
+        None => Some(Utc.from_utc_datetime(&date)),
+    }
+}
+
+/// Random hexadecimal suffix for names that should not collide.
+fn random_suffix() -> String {
+    let mut bytes = [0; 4];
+    if fs::File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .is_err()
+    {
+        let nanos = Utc::now().timestamp_subsec_nanos();
+        bytes = (nanos ^ std::process::id()).to_le_bytes();
+    }
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Create a new empty file in the temporary directory, which no other process
+/// uses, and which is not a link that was put there before.
+fn create_temp_file(prefix: &str, suffix: &str) -> io::Result<PathBuf> {
+    const ATTEMPTS: usize = 100;
+
+    let dir = std::env::temp_dir();
+    for _ in 0..ATTEMPTS {
+        let path = dir.join(format!("{}-{}{}", prefix, random_suffix(), suffix));
+        // fails if anything already exists at the path, even a dangling link
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(_) => return Ok(path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("could not create a new file in {}", dir.to_string_lossy()),
+    ))
+}
 
+fn create_directory(opts: &Options) -> io::Result<PathBuf> {
     let path = match &opts.dir {
         Some(dir) => dir.clone(),
         None => {
             let base_dir = default_base_dir();
             // clean up existing build directories
             if base_dir.exists() {
-                let now = Local::now();
+                let now = Utc::now();
 
                 fs::read_dir(&base_dir)?
                     .flat_map(Result::ok)
                     .map(|entry| entry.path())
                     .flat_map(|path| {
                         let name = path.file_name()?.to_string_lossy().to_string();
-                        let date = Local.datetime_from_str(&name, DATE_FORMAT_STR).ok()?;
-                        Some((path, date))
+                        Some((path, parse_dir_date(&name)?))
                     })
                     .filter(|(_, date)| now.signed_duration_since(*date) > Duration::weeks(7))
-                    .for_each(|(path, _)| {
-                        eprintln!("Removing old directory: {:?}", path);
+                    .for_each(|(path, date)| {
+                        eprintln!(
+                            "Removing old directory from {}: {:?}",
+                            date.with_timezone(&Local).format("%e %b %Y %H:%M"),
+                            path
+                        );
+                        check_leftover_processes(&path, opts.kill_daemons);
                         if let Err(err) = fs::remove_dir_all(path) {
                             eprintln!("  Failed to remove directory: {}", err);
                         }
                     });
             }
 
-            let name = Local::now().format(DATE_FORMAT_STR).to_string();
-            base_dir.join(name)
+            return create_unique_directory(&base_dir);
         }
     };
 
@@ -243,55 +2643,44 @@ fn create_directory(opts: &Options) -> io::Result<PathBuf> {
     Ok(path)
 }
 
-#[derive(Copy, Clone, Debug)]
-enum Status {
-    /// Process is still running.
-    Pending,
-    /// Process exited with exit code 0.
-    Success(i32),
-    /// Process exited with exit code 1-124, 126 or 127.
-    Failure(i32),
-    /// Process exited with exit code 125.
-    Inconclusive(i32),
-    /// Process exited with any other exit code.
-    Abort(Option<i32>),
-}
-
-impl Status {
-    fn get_format(self) -> impl Display {
-        let prefix = match self {
-            Status::Pending => "%C(bold)%C(yellow)●",
-            Status::Success(_) => "%C(bold)%C(green)✔",
-            Status::Failure(_) => "%C(bold)%C(red)✘",
-            Status::Inconclusive(_) => "%C(bold)%C(blue)?",
-            Status::Abort(_) => "%C(bold)%C(red)!",
-        };
+/// Create a new directory named after the current time, which no other run
+/// uses, even if started at the same time.
+fn create_unique_directory(base_dir: &Path) -> io::Result<PathBuf> {
+    const ATTEMPTS: usize = 100;
 
-        if let Some(code) = self.code() {
-            format!("{}{:>3}%Creset", prefix, (code & 0xff) as u8)
-        } else {
-            format!("{}   %Creset", prefix)
+    fs::create_dir_all(base_dir)?;
+    for _ in 0..ATTEMPTS {
+        let name = format!("{}-{}", Utc::now().format(DIR_DATE_FORMAT), random_suffix());
+        let path = base_dir.join(name);
+        // fails if the directory already exists, unlike `create_dir_all`
+        match fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
         }
     }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!(
+            "could not create a new directory in {}",
+            base_dir.to_string_lossy()
+        ),
+    ))
+}
 
-    fn code(&self) -> Option<i32> {
-        match *self {
-            Status::Pending => None,
-            Status::Success(code) => Some(code),
-            Status::Failure(code) => Some(code),
-            Status::Inconclusive(code) => Some(code),
-            Status::Abort(code) => code,
-        }
-    }
+#[derive(Clone, Debug)]
+enum Target {
+    /// A regular commit.
+    Commit(String),
+    /// A stash entry, applied on top of its parent commit.
+    Stash(git::StashEntry),
 }
 
-impl From<ExitStatus> for Status {
-    fn from(exit_status: ExitStatus) -> Status {
-        match exit_status.code() {
-            Some(code @ 0) => Status::Success(code),
-            Some(code @ 1..=124) | Some(code @ 126) | Some(code @ 127) => Status::Failure(code),
-            Some(code @ 125) => Status::Inconclusive(code),
-            code => Status::Abort(code),
+impl Target {
+    fn commit(&self) -> &str {
+        match self {
+            Target::Commit(commit) => commit,
+            Target::Stash(stash) => &stash.commit,
         }
     }
 }
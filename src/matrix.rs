@@ -0,0 +1,108 @@
+use std::io::{self, Write};
+
+use crate::cli::MatrixDimension;
+use crate::status::Status;
+
+/// A combination of environment variables to run the command with.
+#[derive(Clone, Debug, Default)]
+pub struct Variant {
+    pub env: Vec<(String, String)>,
+    /// Which time the command is run with these variables, with `--repeat`.
+    pub repetition: Option<usize>,
+    /// Which time the command is run to warm up before being measured, with
+    /// `--bench`.
+    pub warmup: Option<usize>,
+}
+
+impl Variant {
+    pub fn label(&self) -> String {
+        let mut parts = self
+            .env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>();
+        if let Some(repetition) = self.repetition {
+            parts.push(format!("run {}", repetition));
+        }
+        if let Some(warmup) = self.warmup {
+            parts.push(format!("warmup {}", warmup));
+        }
+        parts.join(" ")
+    }
+}
+
+/// Get all combinations of values in a matrix.
+pub fn variants(dimensions: &[MatrixDimension]) -> Vec<Variant> {
+    let mut variants = vec![Variant::default()];
+    for dimension in dimensions {
+        variants = variants
+            .iter()
+            .flat_map(|variant| {
+                dimension.values.iter().map(move |value| {
+                    let mut variant = variant.clone();
+                    variant.env.push((dimension.name.clone(), value.clone()));
+                    variant
+                })
+            })
+            .collect();
+    }
+    variants
+}
+
+/// Repeat each variant a number of times, one after another.
+pub fn repeat(variants: Vec<Variant>, times: usize) -> Vec<Variant> {
+    if times <= 1 {
+        return variants;
+    }
+    variants
+        .into_iter()
+        .flat_map(|variant| {
+            (1..=times).map(move |repetition| Variant {
+                repetition: Some(repetition),
+                ..variant.clone()
+            })
+        })
+        .collect()
+}
+
+/// Print a grid of results, with a row for each commit and a labeled column for
+/// each variant or step.
+pub fn print_summary(labels: &[String], results: &[(String, Vec<Status>)]) -> io::Result<()> {
+    // width of each column, which fits the label and statuses, e.g. "✔  0"
+    let widths = labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| {
+            results
+                .iter()
+                .filter_map(|(_, statuses)| statuses.get(index))
+                .map(|status| status.text().chars().count())
+                .fold(label.chars().count(), usize::max)
+        })
+        .collect::<Vec<_>>();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    write!(stdout, "\n{:7}", "")?;
+    for (label, width) in labels.iter().zip(&widths) {
+        write!(stdout, "  {:width$}", label, width = width)?;
+    }
+    writeln!(stdout)?;
+
+    for (commit, statuses) in results {
+        write!(stdout, "{:.7}", commit)?;
+        for (status, width) in statuses.iter().zip(&widths) {
+            write!(
+                stdout,
+                "  {}{:pad$}",
+                status.get_ansi_format(),
+                "",
+                pad = width - status.text().chars().count()
+            )?;
+        }
+        writeln!(stdout)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Numbers extracted from the output of commands, such as the time a benchmark
+//! took or the number of tests, to find the commits that made them worse
+//! rather than the commits that fail.
+//!
+//! The number is taken from the last match of a regular expression in the
+//! output, from its first capture group (or the whole match if it has none).
+//! Commits fail if the number is worse than a threshold, or worse than on their
+//! parent by more than a percentage, where lower numbers are better unless
+//! told otherwise.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::status::Status;
+
+pub struct Extract {
+    regex: Regex,
+    threshold: Option<f64>,
+    max_regression_pct: Option<f64>,
+    higher_is_better: bool,
+    /// Numbers of the commits that were run on so far, by commit and label.
+    seen: RefCell<HashMap<(String, String), f64>>,
+}
+
+impl Extract {
+    pub fn new(
+        pattern: &str,
+        threshold: Option<f64>,
+        max_regression_pct: Option<f64>,
+        higher_is_better: bool,
+    ) -> Result<Self, regex::Error> {
+        Ok(Extract {
+            regex: Regex::new(pattern)?,
+            threshold,
+            max_regression_pct,
+            higher_is_better,
+            seen: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Extract the number from the output of a command, and classify the
+    /// commit by it. Commands that did not succeed keep their status.
+    pub fn classify(
+        &self,
+        commit: &str,
+        parent: Option<&str>,
+        label: &str,
+        status: Status,
+        log_path: &Path,
+    ) -> io::Result<(Status, Option<f64>)> {
+        let output = String::from_utf8_lossy(&fs::read(log_path)?).into_owned();
+        let value = self.extract(&output);
+        let code = match status {
+            Status::Success(code) => code,
+            _ => return Ok((status, value)),
+        };
+        let value = match value {
+            Some(value) => value,
+            None if self.threshold.is_some() || self.max_regression_pct.is_some() => {
+                return Ok((Status::Inconclusive(code), None))
+            }
+            None => return Ok((status, None)),
+        };
+
+        let mut seen = self.seen.borrow_mut();
+        seen.insert((commit.to_string(), label.to_string()), value);
+        let parent_value =
+            parent.and_then(|parent| seen.get(&(parent.to_string(), label.to_string())));
+
+        let above_threshold = self
+            .threshold
+            .is_some_and(|threshold| self.worse(value, threshold));
+        let regressed = match (self.max_regression_pct, parent_value) {
+            (Some(pct), Some(&parent_value)) => {
+                let allowed = parent_value.abs() * pct / 100.0;
+                let limit = if self.higher_is_better {
+                    parent_value - allowed
+                } else {
+                    parent_value + allowed
+                };
+                self.worse(value, limit)
+            }
+            _ => false,
+        };
+        if above_threshold || regressed {
+            Ok((Status::Failure(code), Some(value)))
+        } else {
+            Ok((status, Some(value)))
+        }
+    }
+
+    fn extract(&self, output: &str) -> Option<f64> {
+        let captures = self.regex.captures_iter(output).last()?;
+        let text = captures.get(1).or_else(|| captures.get(0))?.as_str();
+        text.trim().replace(',', "").parse().ok()
+    }
+
+    fn worse(&self, value: f64, than: f64) -> bool {
+        if self.higher_is_better {
+            value < than
+        } else {
+            value > than
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Orders to run on commits in, where parents always come before their
+//! children, so that incremental builds of a child can reuse the build of its
+//! parent.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::path::Path;
+
+use crate::git;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// Like `git log --topo-order`: the commits of a line of history one after
+    /// another, instead of interleaving them with other lines by date.
+    Topo,
+    /// Like `git log --date-order`: by commit date, oldest first.
+    Date,
+}
+
+/// Sort commits so that parents come before their children, keeping the
+/// order they were given in otherwise as far as the order allows.
+pub fn sort(git_dir: &Path, commits: &[String], order: Order) -> io::Result<Vec<String>> {
+    let details = git::get_commit_dates_and_parents(git_dir, commits)?;
+    let index_of = commits
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(index, commit)| (commit.as_str(), index))
+        .collect::<HashMap<_, _>>();
+
+    // number of parents of each commit that are yet to come, and the children
+    // of each commit, among the commits to sort
+    let mut waiting_for = vec![0; commits.len()];
+    let mut children = vec![Vec::new(); commits.len()];
+    for (index, commit) in commits.iter().enumerate() {
+        if index_of[commit.as_str()] != index {
+            continue;
+        }
+        let parents = details.get(commit).map(|(_, parents)| parents.as_slice());
+        for parent in parents.unwrap_or_default() {
+            if let Some(&parent_index) = index_of.get(parent.as_str()) {
+                waiting_for[index] += 1;
+                children[parent_index].push(index);
+            }
+        }
+    }
+    let date = |index: usize| details.get(&commits[index]).map_or(0, |(date, _)| *date);
+    let ready = (0..commits.len())
+        .filter(|&index| index_of[commits[index].as_str()] == index && waiting_for[index] == 0);
+
+    let mut sorted = Vec::with_capacity(commits.len());
+    match order {
+        Order::Topo => {
+            // depth first, so that children come right after their parent
+            let mut stack = ready.rev().collect::<Vec<_>>();
+            while let Some(index) = stack.pop() {
+                sorted.push(index);
+                for &child in children[index].iter().rev() {
+                    waiting_for[child] -= 1;
+                    if waiting_for[child] == 0 {
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+        Order::Date => {
+            let mut heap = ready
+                .map(|index| Reverse((date(index), index)))
+                .collect::<BinaryHeap<_>>();
+            while let Some(Reverse((_, index))) = heap.pop() {
+                sorted.push(index);
+                for &child in &children[index] {
+                    waiting_for[child] -= 1;
+                    if waiting_for[child] == 0 {
+                        heap.push(Reverse((date(child), child)));
+                    }
+                }
+            }
+        }
+    }
+    Ok(sorted
+        .into_iter()
+        .map(|index| commits[index].clone())
+        .collect())
+}
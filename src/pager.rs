@@ -0,0 +1,75 @@
+//! Paging output through the user's pager, like git does for long output.
+//!
+//! The pager is the same one git uses (`GIT_PAGER`, `core.pager`, `PAGER`, or
+//! `less`), and like git, `less` is run with `LESS=FRX` unless `LESS` is set,
+//! so output that fits on one screen is printed as it is.
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+use crate::git;
+
+/// A running pager, which stdout is redirected to until it is dropped.
+pub struct Pager {
+    child: Child,
+    /// Copy of the original stdout, to restore it with.
+    stdout: libc::c_int,
+}
+
+impl Pager {
+    /// Start paging stdout, unless stdout is not a terminal, there is no pager,
+    /// or paging is disabled with `pager.corun`.
+    pub fn start(git_dir: Option<&Path>) -> io::Result<Option<Pager>> {
+        if !io::stdout().is_terminal() {
+            return Ok(None);
+        }
+
+        let command = match git_dir {
+            Some(git_dir) => {
+                if git::get_config_bool(git_dir, "pager.corun")? == Some(false) {
+                    return Ok(None);
+                }
+                gitc!("--git-dir", git_dir, "var", "GIT_PAGER")?
+            }
+            None => env::var("PAGER").unwrap_or_else(|_| "less".to_string()),
+        };
+        if command.is_empty() || command == "cat" {
+            return Ok(None);
+        }
+
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", &command]).stdin(Stdio::piped());
+        if env::var_os("LESS").is_none() {
+            cmd.env("LESS", "FRX");
+        }
+        if env::var_os("LV").is_none() {
+            cmd.env("LV", "-c");
+        }
+        let child = cmd.spawn()?;
+
+        io::stdout().flush()?;
+        let stdin = child.stdin.as_ref().expect("stdin is piped");
+        let stdout = unsafe { libc::dup(1) };
+        if stdout == -1 || unsafe { libc::dup2(stdin.as_raw_fd(), 1) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Some(Pager { child, stdout }))
+    }
+}
+
+impl Drop for Pager {
+    /// Restore stdout, and wait for the user to quit the pager.
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        unsafe {
+            libc::dup2(self.stdout, 1);
+            libc::close(self.stdout);
+        }
+        drop(self.child.stdin.take());
+        let _ = self.child.wait();
+    }
+}
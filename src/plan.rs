@@ -0,0 +1,187 @@
+//! Proposed rewrites of history, given as a todo list like the one of
+//! `git rebase --interactive`, which are made as synthetic commits so that each
+//! step can be run on before actually rebasing.
+//!
+//! ```text
+//! pick 1a2b3c4 Add parser
+//! fixup 5d6e7f8 Fix typo in parser
+//! drop 9a8b7c6 Debugging code
+//! pick 3c4d5e6 Use parser
+//! ```
+//!
+//! The rewritten commits start from the parent of the oldest commit in the
+//! list, so only linear histories are supported.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::git;
+use crate::synthetic::Scratch;
+use crate::vcs::CommitInfo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Pick,
+    Drop,
+    /// Meld into the previous commit, keeping both messages.
+    Squash,
+    /// Meld into the previous commit, keeping only its message.
+    Fixup,
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    action: Action,
+    commit: String,
+}
+
+fn parse(contents: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let action = match command {
+            // there is nowhere to stop to edit or reword, so these are picked as they are
+            "pick" | "p" | "reword" | "r" | "edit" | "e" => Action::Pick,
+            "drop" | "d" => Action::Drop,
+            "squash" | "s" => Action::Squash,
+            "fixup" | "f" => Action::Fixup,
+            _ => {
+                return Err(format!(
+                    "line {}: unsupported command: {}",
+                    index + 1,
+                    command
+                ))
+            }
+        };
+        let commit = words
+            .next()
+            .ok_or_else(|| format!("line {}: missing commit", index + 1))?;
+
+        if matches!(action, Action::Squash | Action::Fixup)
+            && !steps.iter().any(|step: &Step| step.action != Action::Drop)
+        {
+            return Err(format!(
+                "line {}: cannot {} without a previous commit",
+                index + 1,
+                command
+            ));
+        }
+        steps.push(Step {
+            action,
+            commit: commit.to_string(),
+        });
+    }
+    Ok(steps)
+}
+
+/// Make the commits of a plan read from a file, returning the commit after each
+/// step, in order.
+pub fn from_file(git_dir: &Path, path: &Path) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    let mut steps = parse(&contents).map_err(io::Error::other)?;
+    for step in &mut steps {
+        step.commit = git::get_commit_hash(git_dir, format!("{}^{{commit}}", step.commit))
+            .map_err(|_| io::Error::other(format!("not a commit: {}", step.commit)))?;
+    }
+
+    let base = base_commit(git_dir, &steps)?;
+    let scratch = Scratch::create(git_dir, &base)?;
+
+    let mut commits: Vec<String> = Vec::new();
+    for step in &steps {
+        match step.action {
+            Action::Pick => commits.push(scratch.cherry_pick(&step.commit)?),
+            Action::Drop => {}
+            Action::Squash | Action::Fixup => {
+                let previous = commits.pop().expect("plan starts with a pick");
+                scratch.cherry_pick(&step.commit)?;
+
+                // replace both commits with one, with the author of the first
+                let mut message = scratch.git(&["log", "-1", "--format=%B", &previous])?;
+                if step.action == Action::Squash {
+                    message += "\n\n";
+                    message += &scratch.git(&["log", "-1", "--format=%B", &step.commit])?;
+                }
+                scratch.git(&["reset", "--soft", "HEAD~"])?;
+                scratch.git(&[
+                    "commit",
+                    "--quiet",
+                    "--amend",
+                    "--allow-empty",
+                    "-m",
+                    &message,
+                ])?;
+                commits.push(scratch.head()?);
+            }
+        }
+    }
+
+    if !commits.is_empty() {
+        scratch.keep("plan", &commits)?;
+    }
+    Ok(commits)
+}
+
+/// Commit to start the rewritten history from, which is the parent of the
+/// oldest commit in the plan.
+fn base_commit(git_dir: &Path, steps: &[Step]) -> io::Result<String> {
+    let commits = steps
+        .iter()
+        .map(|step| step.commit.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut bases = HashSet::new();
+    for commit in &commits {
+        let rev = format!("{}^", commit);
+        let parent = gitc!(
+            "--git-dir",
+            git_dir,
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            rev
+        )
+        .map_err(|_| io::Error::other(format!("{:.7} has no parent", commit)))?;
+        if !commits.contains(parent.as_str()) {
+            bases.insert(parent);
+        }
+    }
+
+    match bases.len() {
+        1 => Ok(bases.into_iter().next().unwrap()),
+        0 => Err(io::Error::other("plan has no commits")),
+        _ => Err(io::Error::other(
+            "commits in plan are not a linear history of commits",
+        )),
+    }
+}
+
+/// Check that commits are a linear history, oldest first, where each commit is
+/// the only parent of the next.
+pub fn is_linear(commits: &[CommitInfo]) -> bool {
+    commits
+        .windows(2)
+        .all(|pair| pair[1].parents == [pair[0].commit.clone()])
+}
+
+/// Print a todo list that runs commands after picking each commit, like
+/// `git rebase --exec` does.
+pub fn print_exec_todo(commits: &[CommitInfo], execs: &[String]) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for info in commits {
+        writeln!(stdout, "pick {:.7} {}", info.commit, info.subject)?;
+        for exec in execs {
+            writeln!(stdout, "exec {}", exec)?;
+        }
+    }
+    Ok(())
+}
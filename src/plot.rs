@@ -0,0 +1,177 @@
+//! Charts of the numbers extracted with `--extract`, or of durations, across
+//! the commits that were run on, to see trends at a glance.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::report::{escape_html, RunResult};
+use crate::status::Status;
+use crate::term;
+
+/// Width of the longest bar in the terminal, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Size of the bars in SVG charts, in pixels.
+const SVG_BAR_WIDTH: usize = 12;
+const SVG_HEIGHT: usize = 120;
+
+/// Values of the results to plot, with what they are, grouped by label in
+/// order of first appearance.
+struct Series<'a> {
+    label: &'a str,
+    points: Vec<(&'a RunResult, f64)>,
+}
+
+fn series(results: &[RunResult]) -> (&'static str, Vec<Series<'_>>) {
+    let has_metric = results.iter().any(|result| result.metric.is_some());
+    let (what, value): (_, fn(&RunResult) -> Option<f64>) = if has_metric {
+        ("metric", |result| result.metric)
+    } else {
+        ("duration (s)", |result| Some(result.duration))
+    };
+
+    let mut series: Vec<Series> = Vec::new();
+    for result in results {
+        if matches!(result.status, Status::Skipped) || !result.status.was_run() {
+            continue;
+        }
+        let value = match value(result) {
+            Some(value) => value,
+            None => continue,
+        };
+        match series
+            .iter_mut()
+            .find(|series| series.label == result.label)
+        {
+            Some(series) => series.points.push((result, value)),
+            None => series.push(Series {
+                label: &result.label,
+                points: vec![(result, value)],
+            }),
+        }
+    }
+    (what, series)
+}
+
+/// Print a bar chart for each label, with a sparkline above it.
+pub fn print(results: &[RunResult]) -> io::Result<()> {
+    const SPARKS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    const EIGHTHS: &[char] = &[' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    let (what, series) = series(results);
+    for series in series {
+        let (min, max) = range(&series.points);
+
+        let sparkline = series
+            .points
+            .iter()
+            .map(|(_, value)| {
+                let level = if max > min {
+                    ((value - min) / (max - min) * (SPARKS.len() - 1) as f64).round() as usize
+                } else {
+                    0
+                };
+                SPARKS[level]
+            })
+            .collect::<String>();
+        write!(stdout, "\n{}", what)?;
+        if !series.label.is_empty() {
+            write!(
+                stdout,
+                " {}",
+                term::paint("35", format!("({})", series.label))
+            )?;
+        }
+        writeln!(stdout, ": {}", sparkline)?;
+
+        let width = series
+            .points
+            .iter()
+            .map(|(_, value)| format_value(*value).len())
+            .max()
+            .unwrap_or_default();
+        for (result, value) in &series.points {
+            let eighths = if max > 0.0 {
+                (value.max(0.0) / max * (BAR_WIDTH * 8) as f64).round() as usize
+            } else {
+                0
+            };
+            let mut bar = "█".repeat(eighths / 8);
+            if eighths % 8 > 0 {
+                bar.push(EIGHTHS[eighths % 8]);
+            }
+            let (_, color, _) = result.status.style();
+            writeln!(
+                stdout,
+                "{} {:>width$} {}",
+                term::paint("33", format!("{:.7}", result.info.commit)),
+                format_value(*value),
+                term::paint(&color.to_string(), bar),
+                width = width
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// SVG bar charts for the HTML report, one for each label.
+pub fn svg(results: &[RunResult]) -> String {
+    let mut svg = String::new();
+    let (what, series) = series(results);
+    for series in series {
+        let (_, max) = range(&series.points);
+        let title = if series.label.is_empty() {
+            what.to_string()
+        } else {
+            format!("{} ({})", what, series.label)
+        };
+        let width = series.points.len() * SVG_BAR_WIDTH;
+
+        let _ = writeln!(svg, "<h2>{}</h2>", escape_html(&title));
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+            width, SVG_HEIGHT
+        );
+        for (index, (result, value)) in series.points.iter().enumerate() {
+            let height = if max > 0.0 {
+                (value.max(0.0) / max * SVG_HEIGHT as f64).round() as usize
+            } else {
+                0
+            };
+            let (color, _, _) = result.status.style();
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>{:.7} {}: {}</title></rect>",
+                index * SVG_BAR_WIDTH,
+                SVG_HEIGHT - height,
+                SVG_BAR_WIDTH - 2,
+                height,
+                color,
+                result.info.commit,
+                escape_html(&result.info.subject),
+                format_value(*value)
+            );
+        }
+        let _ = writeln!(svg, "</svg>");
+    }
+    svg
+}
+
+fn range(points: &[(&RunResult, f64)]) -> (f64, f64) {
+    points.iter().fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(min, max), (_, value)| (min.min(*value), max.max(*value)),
+    )
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.3}", value)
+    }
+}
@@ -0,0 +1,64 @@
+//! Subcommands provided by other executables, like git does for `git-<name>`.
+//!
+//! `git corun NAME ARGS...` runs `git-corun-NAME ARGS...` if it is on `PATH`
+//! and `NAME` is not a built-in subcommand, with these environment variables:
+//!
+//! * `CORUN`: Path to the corun executable.
+//! * `CORUN_VERSION`: Version of corun.
+//! * `CORUN_BASE_DIR`: Directory commits are checked out in by default.
+//! * `CORUN_RUNS_DIR`: Directory results of past runs are stored in, as JSON
+//!   files in the same format as `--json` reports, if in a git repository.
+
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::cli::Cli;
+use crate::preflight;
+use crate::store::Store;
+use crate::vcs::GitVcs;
+
+const PREFIX: &str = "git-corun-";
+
+/// Replace the process with a plugin if the first argument names one, and
+/// return otherwise.
+pub fn exec_if_found(args: &[OsString], base_dir: &Path) -> io::Result<()> {
+    let name = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(name) if is_plugin_name(name) && !Cli::SUBCOMMANDS.contains(&name) => name,
+        _ => return Ok(()),
+    };
+    let path = match preflight::find_in_path(&format!("{}{}", PREFIX, name)) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let mut cmd = Command::new(path);
+    cmd.args(&args[2..])
+        .env("CORUN_VERSION", env!("CARGO_PKG_VERSION"))
+        .env("CORUN_BASE_DIR", base_dir);
+    if let Ok(exe) = env::current_exe() {
+        cmd.env("CORUN", exe);
+    }
+    if let Ok(vcs) = GitVcs::open() {
+        // the git dir may be relative, and plugins may change directory
+        cmd.env(
+            "CORUN_RUNS_DIR",
+            env::current_dir()?.join(Store::open(&vcs).dir()),
+        );
+    }
+
+    // only returns if the plugin could not be run
+    Err(cmd.exec())
+}
+
+/// Whether an argument could name a plugin, rather than being an option or a
+/// revision (such as `HEAD~2` or `main..topic`).
+fn is_plugin_name(arg: &str) -> bool {
+    !arg.starts_with('-')
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
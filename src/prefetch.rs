@@ -0,0 +1,89 @@
+//! Fetching the dependencies of each commit in a step of its own, before the
+//! other steps, which then run offline. Downloads are not part of the time of
+//! the other steps then, and a flaky network only makes the prefetch step
+//! fail, rather than the builds and tests.
+//!
+//! The dependencies are fetched as pinned by the lockfile of each commit, into
+//! the download cache of the package manager that is shared between runs (see
+//! `caches`), so that each version is only downloaded once.
+
+use std::path::{Path, PathBuf};
+
+use crate::cli::Prefetch;
+use crate::steps::Step;
+
+/// Name of the step that fetches dependencies.
+pub const STEP_NAME: &str = "prefetch";
+
+impl Prefetch {
+    /// Directory the packages are downloaded to, if they are installed from a
+    /// directory rather than from the download cache.
+    pub fn download_dir(self, cache_dir: &Path) -> Option<PathBuf> {
+        match self {
+            Prefetch::Pip => Some(cache_dir.join("pip-downloads")),
+            _ => None,
+        }
+    }
+
+    fn command(self, cache_dir: &Path) -> Vec<String> {
+        let words: &[&str] = match self {
+            Prefetch::Cargo => &["cargo", "fetch"],
+            Prefetch::Npm => &["npm", "ci", "--ignore-scripts", "--no-audit", "--no-fund"],
+            Prefetch::Pip => &[
+                "pip",
+                "download",
+                "--requirement",
+                "requirements.txt",
+                "--dest",
+            ],
+        };
+        let mut command = words
+            .iter()
+            .map(|word| word.to_string())
+            .collect::<Vec<_>>();
+        if let Some(dir) = self.download_dir(cache_dir) {
+            command.push(dir.to_string_lossy().into_owned());
+        }
+        command
+    }
+
+    /// Environment variables that make the package manager work offline.
+    fn offline_env(self, cache_dir: &Path) -> Vec<(String, String)> {
+        match self {
+            Prefetch::Cargo => vec![("CARGO_NET_OFFLINE".to_string(), "true".to_string())],
+            Prefetch::Npm => vec![("npm_config_offline".to_string(), "true".to_string())],
+            Prefetch::Pip => vec![
+                ("PIP_NO_INDEX".to_string(), "1".to_string()),
+                (
+                    "PIP_FIND_LINKS".to_string(),
+                    self.download_dir(cache_dir)
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                ),
+            ],
+        }
+    }
+}
+
+/// Add a step that fetches dependencies before the steps, which the steps
+/// depend on and which run offline.
+pub fn add_step(prefetch: Prefetch, steps: Vec<Step>, cache_dir: &Path) -> Vec<Step> {
+    let offline_env = prefetch.offline_env(cache_dir);
+
+    let mut with_prefetch = vec![Step {
+        name: STEP_NAME.to_string(),
+        deps: Vec::new(),
+        command: prefetch.command(cache_dir),
+        shell: false,
+        env: Vec::new(),
+    }];
+    with_prefetch.extend(steps.into_iter().map(|mut step| {
+        step.deps = std::iter::once(0)
+            .chain(step.deps.iter().map(|dep| dep + 1))
+            .collect();
+        step.env.extend(offline_env.iter().cloned());
+        step
+    }));
+    with_prefetch
+}
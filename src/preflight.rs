@@ -0,0 +1,87 @@
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Problems found while validating a run before anything is checked out.
+#[derive(Debug, Default)]
+pub struct Problems(Vec<String>);
+
+impl Problems {
+    pub fn push(&mut self, problem: impl Into<String>) {
+        self.0.push(problem.into());
+    }
+
+    /// Record the error of a failed result, if any, and return its value otherwise.
+    ///
+    /// Only the first line of the error is kept, to keep the report readable.
+    pub fn check<T, E: Display>(
+        &mut self,
+        context: impl Display,
+        result: Result<T, E>,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                let err = err.to_string();
+                let err = err.lines().next().unwrap_or_default();
+                self.push(format!("{}: {}", context, err));
+                None
+            }
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Display for Problems {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "preflight checks failed:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Problems {}
+
+/// Check that a command can be executed.
+///
+/// Relative paths are not checked, since they are resolved inside each checkout.
+pub fn check_executable(name: &str) -> Result<(), String> {
+    let path = Path::new(name);
+
+    if path.is_absolute() {
+        if is_executable(path) {
+            Ok(())
+        } else {
+            Err(format!("command not executable: {}", name))
+        }
+    } else if name.contains('/') || find_in_path(name).is_some() {
+        Ok(())
+    } else {
+        Err(format!("command not found: {}", name))
+    }
+}
+
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
@@ -0,0 +1,105 @@
+//! Finding processes that are still using a directory, e.g. daemons started by
+//! build systems that left their process group.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A running process.
+#[derive(Clone, Debug)]
+pub struct Process {
+    pub pid: u32,
+    pub command: String,
+}
+
+/// Processes whose working directory or open files are in a directory.
+///
+/// Only supported on Linux, where this is read from `/proc`; elsewhere this is
+/// always empty.
+pub fn using_dir(dir: &Path) -> Vec<Process> {
+    if !cfg!(target_os = "linux") {
+        return Vec::new();
+    }
+
+    let dir = match dir.canonicalize() {
+        Ok(dir) => dir,
+        Err(_) => return Vec::new(),
+    };
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut processes = entries
+        .flat_map(Result::ok)
+        .flat_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| pid != std::process::id())
+        .filter(|&pid| {
+            let proc_dir = Path::new("/proc").join(pid.to_string());
+
+            // processes of other users cannot be inspected, and are ignored
+            let in_dir = |link: &Path| fs::read_link(link).is_ok_and(|path| path.starts_with(&dir));
+            in_dir(&proc_dir.join("cwd"))
+                || fs::read_dir(proc_dir.join("fd"))
+                    .map(|fds| fds.flat_map(Result::ok).any(|fd| in_dir(&fd.path())))
+                    .unwrap_or(false)
+        })
+        .map(|pid| Process {
+            pid,
+            command: command_line(pid),
+        })
+        .collect::<Vec<_>>();
+    processes.sort_by_key(|process| process.pid);
+    processes
+}
+
+fn command_line(pid: u32) -> String {
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    match fs::read(proc_dir.join("cmdline")) {
+        Ok(cmdline) if !cmdline.is_empty() => cmdline
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => fs::read_to_string(proc_dir.join("comm"))
+            .map(|comm| comm.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Long-lived helpers started by build tools, by name and a part of their
+/// command line to recognize them by.
+const DAEMONS: &[(&str, &str)] = &[
+    ("gradle daemon", "GradleDaemon"),
+    ("kotlin daemon", "KotlinCompileDaemon"),
+    ("cargo-watch", "cargo-watch"),
+    ("watchman", "watchman"),
+    ("bazel server", "(bazel)"),
+];
+
+/// Name of a known daemon, if the process is one.
+pub fn daemon_name(process: &Process) -> Option<&'static str> {
+    DAEMONS
+        .iter()
+        .find(|(_, pattern)| process.command.contains(pattern))
+        .map(|&(name, _)| name)
+}
+
+/// Terminate processes, and kill them if they have not exited after a while.
+pub fn terminate(processes: &[Process]) {
+    for process in processes {
+        unsafe { libc::kill(process.pid as libc::pid_t, libc::SIGTERM) };
+    }
+
+    let alive = |process: &&Process| unsafe { libc::kill(process.pid as libc::pid_t, 0) == 0 };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while processes.iter().any(|process| alive(&process)) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    for process in processes.iter().filter(alive) {
+        unsafe { libc::kill(process.pid as libc::pid_t, libc::SIGKILL) };
+    }
+}
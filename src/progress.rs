@@ -0,0 +1,64 @@
+//! Estimating when a run will be done.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+/// Number of commits to run on before estimating the remaining time.
+const MIN_SAMPLES: usize = 3;
+
+/// How much an estimate must change before it is shown again.
+const MIN_CHANGE_SECS: i64 = 60;
+
+#[derive(Debug, Default)]
+pub struct Estimator {
+    durations: Vec<Duration>,
+    last_shown: Option<DateTime<Local>>,
+}
+
+impl Estimator {
+    /// Add the time it took to run on a commit.
+    pub fn add(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    /// Estimated time left to run on the remaining commits, once enough commits
+    /// have been run on.
+    pub fn remaining(&self, commits_left: usize) -> Option<Duration> {
+        if self.durations.len() < MIN_SAMPLES {
+            return None;
+        }
+
+        let total = self.durations.iter().sum::<Duration>();
+        let mean = total / self.durations.len() as u32;
+        Some(mean * commits_left as u32)
+    }
+
+    /// Estimated time the run will be done.
+    pub fn finish_time(&self, commits_left: usize) -> Option<DateTime<Local>> {
+        let remaining = chrono::Duration::from_std(self.remaining(commits_left)?).ok()?;
+        Some(Local::now() + remaining)
+    }
+
+    /// Whether an estimate changed enough since the last one shown to be shown
+    /// again, remembering it as shown if so.
+    pub fn should_show(&mut self, finish_time: DateTime<Local>) -> bool {
+        let changed = self.last_shown.is_none_or(|last_shown| {
+            (finish_time - last_shown).num_seconds().abs() >= MIN_CHANGE_SECS
+        });
+        if changed {
+            self.last_shown = Some(finish_time);
+        }
+        changed
+    }
+}
+
+/// Format a duration for people, e.g. `1h 5m` or `42s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
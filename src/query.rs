@@ -0,0 +1,235 @@
+//! Querying the results of past runs with SQL, for questions that `list` and
+//! `regressions` do not answer, like which commits failed more than twice in
+//! the last month.
+//!
+//! The runs are kept in an SQLite database next to the stored runs, which is
+//! brought up to date with them before each query, so runs that are added,
+//! imported or removed are always reflected in it. This uses the `sqlite3`
+//! command, so that corun does not need to be built with SQLite.
+//!
+//! The database has two tables:
+//!
+//! ```text
+//! runs(id, started, label, command, dir)
+//! results(run_id, started, hash, subject, author, email, date, label, status, code, duration,
+//!         user_time, system_time, max_rss, major_faults, minor_faults, metric, revs, signature, notes)
+//! ```
+//!
+//! where `results.started` is the start time of the run, so that results can
+//! be filtered by time without a join, and `results.notes` are the notes added
+//! with `annotate`, one per line. `results.revs` are the revisions that
+//! selected the commit, if more than one was given, and `results.signature` is
+//! the signature of the error of failures.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::cli::QueryFormat;
+use crate::store::{Run, Store};
+
+pub const COMMAND: &str = "sqlite3";
+
+/// Version of the schema, which the database is created again for when it
+/// changes.
+const SCHEMA_VERSION: u32 = 6;
+
+const SCHEMA: &str = "\
+CREATE TABLE runs (
+    id TEXT PRIMARY KEY,
+    started TEXT,
+    label TEXT,
+    command TEXT NOT NULL,
+    dir TEXT
+);
+CREATE TABLE results (
+    run_id TEXT NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    started TEXT,
+    hash TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    author TEXT NOT NULL,
+    email TEXT NOT NULL,
+    date TEXT NOT NULL,
+    label TEXT NOT NULL,
+    status TEXT NOT NULL,
+    code INTEGER,
+    duration REAL NOT NULL,
+    user_time REAL,
+    system_time REAL,
+    max_rss INTEGER,
+    major_faults INTEGER,
+    minor_faults INTEGER,
+    metric REAL,
+    revs TEXT,
+    signature TEXT,
+    notes TEXT
+);
+CREATE INDEX results_run_id ON results (run_id);
+CREATE INDEX results_hash ON results (hash);
+";
+
+/// Bring the database up to date with the stored runs, and run a query on it.
+pub fn run(store: &Store, db: &Path, sql: &str, format: QueryFormat) -> io::Result<()> {
+    sync(store, db)?;
+
+    let mode = match format {
+        QueryFormat::Table => "-column",
+        QueryFormat::Csv => "-csv",
+        QueryFormat::Json => "-json",
+    };
+    let status = Command::new(COMMAND)
+        .args(["-readonly", "-bail", "-header", mode])
+        .arg(db)
+        .arg(sql)
+        .stdin(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other("query failed"));
+    }
+    Ok(())
+}
+
+/// Add runs to the database that are not in it yet, and remove runs that are
+/// no longer stored.
+fn sync(store: &Store, db: &Path) -> io::Result<()> {
+    if db.exists() && sqlite(db, "PRAGMA user_version;")?.trim() != SCHEMA_VERSION.to_string() {
+        fs::remove_file(db)?;
+    }
+
+    let mut script = String::from("PRAGMA foreign_keys = ON;\nBEGIN;\n");
+    let synced = if db.exists() {
+        sqlite(db, "SELECT id FROM runs;")?
+            .lines()
+            .map(String::from)
+            .collect::<HashSet<_>>()
+    } else {
+        script.push_str(SCHEMA);
+        script.push_str(&format!("PRAGMA user_version = {};\n", SCHEMA_VERSION));
+        HashSet::new()
+    };
+
+    let ids = store.ids()?;
+    for id in &synced {
+        if !ids.contains(id) {
+            script.push_str(&format!("DELETE FROM runs WHERE id = {};\n", quote(id)));
+        }
+    }
+    for id in &ids {
+        if !synced.contains(id) {
+            match store.load(id) {
+                Ok(run) => insert_run(&mut script, &run),
+                Err(err) => eprintln!("Warning: skipping {}", err),
+            }
+        }
+    }
+    script.push_str("COMMIT;\n");
+
+    let mut child = Command::new(COMMAND)
+        .args(["-bail", "-batch"])
+        .arg(db)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(script.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "could not update {}: {}",
+            db.to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+fn insert_run(script: &mut String, run: &Run) {
+    let started = if run.started.is_empty() {
+        "NULL".to_string()
+    } else {
+        quote(&run.started)
+    };
+    script.push_str(&format!(
+        "INSERT INTO runs VALUES ({}, {}, {}, {}, {});\n",
+        quote(&run.id),
+        started,
+        run.label.as_deref().map_or("NULL".to_string(), quote),
+        quote(&run.command.join(" ")),
+        run.dir
+            .as_ref()
+            .map_or("NULL".to_string(), |dir| quote(&dir.to_string_lossy())),
+    ));
+
+    for result in &run.results {
+        script.push_str(&format!(
+            "INSERT INTO results VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {});\n",
+            quote(&run.id),
+            started,
+            quote(&result.info.commit),
+            quote(&result.info.subject),
+            quote(&result.info.author),
+            quote(&result.info.email),
+            quote(&result.info.date),
+            quote(&result.label),
+            quote(result.status.name()),
+            result
+                .status
+                .code()
+                .map_or("NULL".to_string(), |code| code.to_string()),
+            result.duration,
+            match result.usage {
+                Some(usage) => format!(
+                    "{}, {}, {}, {}, {}",
+                    usage.user_time,
+                    usage.system_time,
+                    usage.max_rss,
+                    usage.major_faults,
+                    usage.minor_faults
+                ),
+                None => "NULL, NULL, NULL, NULL, NULL".to_string(),
+            },
+            result
+                .metric
+                .map_or("NULL".to_string(), |metric| metric.to_string()),
+            if result.revs.is_empty() {
+                "NULL".to_string()
+            } else {
+                quote(&result.revs.join(" "))
+            },
+            result
+                .signature
+                .as_deref()
+                .map_or("NULL".to_string(), quote),
+            if result.notes.is_empty() {
+                "NULL".to_string()
+            } else {
+                quote(&result.notes.join("\n"))
+            },
+        ));
+    }
+}
+
+/// Quote a string as an SQL string literal.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Run statements on the database, returning their output.
+fn sqlite(db: &Path, sql: &str) -> io::Result<String> {
+    let output = Command::new(COMMAND)
+        .args(["-bail", "-batch", "-list"])
+        .arg(db)
+        .arg(sql)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{}: {}",
+            db.to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
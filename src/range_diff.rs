@@ -0,0 +1,156 @@
+//! Comparing results on two versions of a series of commits, such as a branch
+//! before and after it was rebased, by pairing up the commits with
+//! `git range-diff`.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::git;
+use crate::report::RunResult;
+use crate::store::Run;
+use crate::term;
+
+/// Commits of two ranges paired up by `git range-diff`.
+pub struct RangeDiff {
+    /// Pairs of commits in the old and the new range.
+    pub pairs: Vec<(String, String)>,
+    /// Commits in the old range without a commit in the new range.
+    pub removed: Vec<String>,
+    /// Commits in the new range without a commit in the old range.
+    pub added: Vec<String>,
+}
+
+impl RangeDiff {
+    /// Commits of all pairs, in the order they are in the ranges, with commits
+    /// that did not change only once.
+    pub fn commits(&self) -> Vec<String> {
+        let mut commits = Vec::new();
+        for (old, new) in &self.pairs {
+            for commit in [old, new] {
+                if !commits.contains(commit) {
+                    commits.push(commit.clone());
+                }
+            }
+        }
+        commits
+    }
+}
+
+/// Pair up the commits of two ranges.
+pub fn pairs(git_dir: &Path, old: &str, new: &str) -> io::Result<RangeDiff> {
+    let output = gitc!(
+        "--git-dir",
+        git_dir,
+        "range-diff",
+        "--no-color",
+        "--no-patch",
+        old,
+        new
+    )?;
+
+    // lines look like `1:  1234567 = 1:  89abcde subject`, with `-:  -------`
+    // for commits that are only in one of the ranges
+    let mut range_diff = RangeDiff {
+        pairs: Vec::new(),
+        removed: Vec::new(),
+        added: Vec::new(),
+    };
+    for line in output.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let (old_commit, new_commit) = match fields[..] {
+            [_, old_commit, _, _, new_commit, ..] => (old_commit, new_commit),
+            _ => continue,
+        };
+        let resolve =
+            |commit: &str| git::get_commit_hash(git_dir, format!("{}^{{commit}}", commit));
+        match (old_commit.starts_with('-'), new_commit.starts_with('-')) {
+            (false, false) => range_diff
+                .pairs
+                .push((resolve(old_commit)?, resolve(new_commit)?)),
+            (false, true) => range_diff.removed.push(resolve(old_commit)?),
+            (true, false) => range_diff.added.push(resolve(new_commit)?),
+            (true, true) => {}
+        }
+    }
+    Ok(range_diff)
+}
+
+/// A pair of results whose status differs between the old and the new commit.
+pub struct Change<'a> {
+    pub old: &'a RunResult,
+    pub new: &'a RunResult,
+}
+
+/// Find pairs of commits whose results differ.
+pub fn changes<'a>(range_diff: &RangeDiff, run: &'a Run) -> Vec<Change<'a>> {
+    let result = |commit: &str, label: &str| {
+        run.results
+            .iter()
+            .find(|result| result.info.commit == commit && result.label == label)
+    };
+
+    let mut changes = Vec::new();
+    for (old, new) in &range_diff.pairs {
+        for new_result in run
+            .results
+            .iter()
+            .filter(|result| &result.info.commit == new)
+        {
+            let old_result = match result(old, &new_result.label) {
+                Some(old_result) => old_result,
+                None => continue,
+            };
+            if old_result.status.name() != new_result.status.name()
+                || old_result.status.code() != new_result.status.code()
+            {
+                changes.push(Change {
+                    old: old_result,
+                    new: new_result,
+                });
+            }
+        }
+    }
+    changes
+}
+
+pub fn print(range_diff: &RangeDiff, changes: &[Change]) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for change in changes {
+        write!(
+            stdout,
+            "{} → {} {} → {} {}",
+            change.old.status.get_ansi_format(),
+            change.new.status.get_ansi_format(),
+            term::paint("33", format!("{:.7}", change.old.info.commit)),
+            term::paint("33", format!("{:.7}", change.new.info.commit)),
+            change.new.info.subject
+        )?;
+        if !change.new.label.is_empty() {
+            write!(
+                stdout,
+                " {}",
+                term::paint("35", format!("({})", change.new.label))
+            )?;
+        }
+        writeln!(stdout)?;
+    }
+
+    if changes.is_empty() {
+        writeln!(
+            stdout,
+            "No changes in {} pairs of commits",
+            range_diff.pairs.len()
+        )?;
+    }
+    if !range_diff.removed.is_empty() || !range_diff.added.is_empty() {
+        writeln!(
+            stdout,
+            "Not run on {} commits only in the old range and {} only in the new range",
+            range_diff.removed.len(),
+            range_diff.added.len()
+        )?;
+    }
+    Ok(())
+}
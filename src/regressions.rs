@@ -0,0 +1,211 @@
+//! Changes in results between two runs.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::report::RunResult;
+use crate::status::Status;
+use crate::store::Run;
+use crate::term;
+
+pub struct Regressions<'a> {
+    /// Failing commits that were not in the previous run, whose parent passed.
+    pub new_failures: Vec<&'a RunResult>,
+    /// Commits that passed in the previous run but fail now, even though the
+    /// commit itself is the same, so the cause is flakiness or the environment.
+    pub changed: Vec<&'a RunResult>,
+}
+
+/// Find results that went from passing to failing between two runs.
+pub fn find<'a>(previous: &'a Run, latest: &'a Run) -> Regressions<'a> {
+    let passed = |results: &'a [RunResult]| -> HashMap<(&'a str, &'a str), bool> {
+        results
+            .iter()
+            .filter(|result| result.status.was_run())
+            .map(|result| {
+                let key = (result.info.commit.as_str(), result.label.as_str());
+                (key, matches!(result.status, Status::Success(_)))
+            })
+            .collect()
+    };
+    let previous_passed = passed(&previous.results);
+    let latest_passed = passed(&latest.results);
+
+    let mut regressions = Regressions {
+        new_failures: Vec::new(),
+        changed: Vec::new(),
+    };
+    for result in &latest.results {
+        if !result.status.is_failure() {
+            continue;
+        }
+
+        let key = (result.info.commit.as_str(), result.label.as_str());
+        match previous_passed.get(&key) {
+            Some(true) => regressions.changed.push(result),
+            Some(false) => {}
+            None => {
+                let parent_passed = result.info.parents.first().and_then(|parent| {
+                    let key = (parent.as_str(), result.label.as_str());
+                    latest_passed
+                        .get(&key)
+                        .or_else(|| previous_passed.get(&key))
+                });
+                if parent_passed == Some(&true) {
+                    regressions.new_failures.push(result);
+                }
+            }
+        }
+    }
+    regressions
+}
+
+pub fn print(previous: &Run, latest: &Run, regressions: &Regressions) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    writeln!(stdout, "Comparing run {} with {}", latest.id, previous.id)?;
+    for (title, results) in &[
+        ("New failures (parent passed):", &regressions.new_failures),
+        (
+            "Changed on the same commit (flaky or environment):",
+            &regressions.changed,
+        ),
+    ] {
+        if results.is_empty() {
+            continue;
+        }
+
+        writeln!(stdout, "\n{}", title)?;
+        for result in results.iter() {
+            write!(
+                stdout,
+                "{} {} {}",
+                result.status.get_ansi_format(),
+                term::paint("33", format!("{:.7}", result.info.commit)),
+                result.info.subject
+            )?;
+            if !result.label.is_empty() {
+                write!(
+                    stdout,
+                    " {}",
+                    term::paint("35", format!("({})", result.label))
+                )?;
+            }
+            writeln!(stdout)?;
+        }
+    }
+
+    if regressions.new_failures.is_empty() && regressions.changed.is_empty() {
+        writeln!(stdout, "No regressions")?;
+    }
+    Ok(())
+}
+
+/// Minimum ratio between durations for a change in duration to be shown.
+const DURATION_RATIO: f64 = 1.5;
+
+/// Minimum difference between durations for a change in duration to be shown,
+/// in seconds, so that short commands are not shown for every small change.
+const DURATION_DIFF: f64 = 1.0;
+
+/// A command on a commit whose result changed between two runs.
+pub struct Change<'a> {
+    pub before: &'a RunResult,
+    pub after: &'a RunResult,
+}
+
+impl Change<'_> {
+    pub fn status_changed(&self) -> bool {
+        self.before.status.name() != self.after.status.name()
+            || self.before.status.code() != self.after.status.code()
+    }
+
+    pub fn duration_changed(&self) -> bool {
+        let (before, after, _) = self.durations();
+        let (shorter, longer) = if before < after {
+            (before, after)
+        } else {
+            (after, before)
+        };
+        longer - shorter >= DURATION_DIFF && longer >= shorter * DURATION_RATIO
+    }
+
+    /// Durations to compare, which are the CPU time where both results have
+    /// it, since it depends less on what else the system is doing than the
+    /// wall time, and what they are.
+    pub fn durations(&self) -> (f64, f64, &'static str) {
+        match (self.before.usage, self.after.usage) {
+            (Some(before), Some(after)) => (before.cpu_time(), after.cpu_time(), "cpu "),
+            _ => (self.before.duration, self.after.duration, ""),
+        }
+    }
+}
+
+/// Find commands on commits in both runs whose status or duration changed
+/// significantly.
+pub fn diff<'a>(a: &'a Run, b: &'a Run) -> Vec<Change<'a>> {
+    let before = a
+        .results
+        .iter()
+        .map(|result| ((result.info.commit.as_str(), result.label.as_str()), result))
+        .collect::<HashMap<_, _>>();
+
+    b.results
+        .iter()
+        .flat_map(|after| {
+            let key = (after.info.commit.as_str(), after.label.as_str());
+            Some(Change {
+                before: before.get(&key)?,
+                after,
+            })
+        })
+        .filter(|change| change.status_changed() || change.duration_changed())
+        .collect()
+}
+
+pub fn print_diff(a: &Run, b: &Run, changes: &[Change]) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    writeln!(stdout, "Comparing run {} with {}", b.id, a.id)?;
+    for change in changes {
+        if change.status_changed() {
+            write!(
+                stdout,
+                "{} → {}",
+                change.before.status.get_ansi_format(),
+                change.after.status.get_ansi_format()
+            )?;
+        } else {
+            write!(stdout, "{}", change.after.status.get_ansi_format())?;
+        }
+        write!(
+            stdout,
+            " {} {}",
+            term::paint("33", format!("{:.7}", change.after.info.commit)),
+            change.after.info.subject
+        )?;
+        if !change.after.label.is_empty() {
+            write!(
+                stdout,
+                " {}",
+                term::paint("35", format!("({})", change.after.label))
+            )?;
+        }
+        if change.duration_changed() {
+            let (before, after, kind) = change.durations();
+            write!(
+                stdout,
+                " {}",
+                term::paint("36", format!("[{}{:.1}s → {:.1}s]", kind, before, after))
+            )?;
+        }
+        writeln!(stdout)?;
+    }
+
+    if changes.is_empty() {
+        writeln!(stdout, "No changes")?;
+    }
+    Ok(())
+}
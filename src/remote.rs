@@ -0,0 +1,195 @@
+//! Running commands as jobs on a job runner elsewhere, with `--remote`, so that
+//! large runs can fan out to more machines than the one corun runs on. This is
+//! experimental.
+//!
+//! corun still checks out each commit, and runs each step with a command that
+//! sends it to the job runner and waits for it (`git corun __remote-job`), so
+//! that logs, timeouts and statuses work as they do for local commands. The job
+//! runner speaks a small HTTP protocol, with paths relative to its URL:
+//!
+//! - `PUT bundles/COMMIT`: a git bundle of the commit, with `HEAD` pointing to
+//!   it. It has all of the history of the commit, so nothing else is needed to
+//!   check it out.
+//! - `POST jobs`: a job to run, as JSON with the `commit`, the `command` (as a
+//!   list of arguments) and the `env` to run it with, which returns JSON with
+//!   the `id` of the job.
+//! - `GET jobs/ID`: JSON with the `state` of the job (`queued`, `running` or
+//!   `done`), and its `exit_code` once it is done.
+//! - `GET jobs/ID/log?offset=N`: the output of the job, from byte `N` on.
+//!
+//! Jobs keep running on the job runner if corun stops waiting for them, e.g.
+//! after a timeout.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::runner;
+use crate::steps::Step;
+
+pub const COMMAND: &str = "curl";
+
+/// How long to wait between asking the job runner about a job.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize)]
+struct Job<'a> {
+    commit: &'a str,
+    command: &'a [String],
+    env: BTreeMap<&'a str, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmittedJob {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobState {
+    state: String,
+    exit_code: Option<i32>,
+}
+
+/// Command to run a step with, which runs it as a job on the job runner, with
+/// the environment variables given (by name, with the values from the
+/// environment of the command).
+pub fn command(
+    url: &str,
+    step: &Step,
+    shell: Option<&str>,
+    env: &[(String, String)],
+) -> io::Result<Vec<String>> {
+    let mut command = vec![
+        env::current_exe()?.to_string_lossy().into_owned(),
+        "__remote-job".to_string(),
+        "--url".to_string(),
+        url.to_string(),
+    ];
+    // the temporary directory is only on this machine
+    for (name, _) in env.iter().filter(|(name, _)| name != "TMPDIR") {
+        command.push("--env".to_string());
+        command.push(name.clone());
+    }
+    command.push("--".to_string());
+    match shell {
+        Some(shell) => {
+            command.push(shell.to_string());
+            command.extend(
+                runner::shell_args(shell, &step.command)
+                    .into_iter()
+                    .map(str::to_string),
+            );
+        }
+        None => command.extend(step.command.iter().cloned()),
+    }
+    Ok(command)
+}
+
+/// Run a command as a job on the job runner, on the commit checked out in the
+/// current directory, printing its output as it comes, and return its exit
+/// code.
+pub fn run_job(url: &str, env_names: &[String], command: &[String]) -> io::Result<i32> {
+    let url = url.trim_end_matches('/');
+
+    let commit = gitc!("rev-parse", "HEAD")?;
+    let bundle = env::temp_dir().join(format!("{}.bundle", commit));
+    gitc!("bundle", "create", "--quiet", bundle, "HEAD")?;
+    let uploaded = curl(
+        &["--upload-file", &bundle.to_string_lossy()],
+        &format!("{}/bundles/{}", url, commit),
+        None,
+    );
+    fs::remove_file(&bundle)?;
+    uploaded?;
+
+    let job = Job {
+        commit: &commit,
+        command,
+        env: env_names
+            .iter()
+            .filter_map(|name| Some((name.as_str(), env::var(name).ok()?)))
+            .collect(),
+    };
+    let submitted: SubmittedJob = serde_json::from_slice(&curl(
+        &[
+            "--header",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+        ],
+        &format!("{}/jobs", url),
+        Some(&serde_json::to_vec(&job)?),
+    )?)?;
+
+    let stdout = io::stdout();
+    let mut offset = 0;
+    loop {
+        let job: JobState =
+            serde_json::from_slice(&curl(&[], &format!("{}/jobs/{}", url, submitted.id), None)?)?;
+        // the output is fetched after the state, so that all of it is printed
+        // once the job is done
+        let output = curl(
+            &[],
+            &format!("{}/jobs/{}/log?offset={}", url, submitted.id, offset),
+            None,
+        )?;
+        offset += output.len();
+        let mut stdout = stdout.lock();
+        stdout.write_all(&output)?;
+        stdout.flush()?;
+
+        match (job.state.as_str(), job.exit_code) {
+            ("done", Some(exit_code)) => return Ok(exit_code),
+            ("done", None) => {
+                return Err(io::Error::other(format!(
+                    "job {} is done without an exit code",
+                    submitted.id
+                )))
+            }
+            ("queued", _) | ("running", _) => thread::sleep(POLL_INTERVAL),
+            (state, _) => {
+                return Err(io::Error::other(format!(
+                    "job {} has an unknown state: {}",
+                    submitted.id, state
+                )))
+            }
+        }
+    }
+}
+
+fn curl(args: &[&str], url: &str, input: Option<&[u8]>) -> io::Result<Vec<u8>> {
+    let mut child = Command::new(COMMAND)
+        .args(["--silent", "--show-error", "--fail"])
+        .args(args)
+        .arg(url)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| io::Error::new(err.kind(), format!("could not run curl: {}", err)))?;
+    if let Some(input) = input {
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(input)?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        let error = error.trim_end_matches('\n');
+        return Err(io::Error::other(format!("curl error: {}", error)));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,462 @@
+//! Reports and summaries of the results of a run.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::DateFormat;
+use crate::plot;
+use crate::runner::Usage;
+use crate::status::Status;
+use crate::store::Run;
+use crate::vcs::CommitInfo;
+
+/// Result of running a command (or a step of it) on a commit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunResult {
+    #[serde(flatten)]
+    pub info: CommitInfo,
+    /// Step and/or matrix variant the command was run for, if any.
+    pub label: String,
+    #[serde(flatten)]
+    pub status: Status,
+    /// Duration of the command, in seconds.
+    pub duration: f64,
+    /// Resources used by the command, if it was run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Hash of the output checked with `--check-reproducible`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_hash: Option<String>,
+    /// Number extracted from the output with `--extract`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metric: Option<f64>,
+    /// Signature of the error, if the command failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Shell command that reproduces a failure without corun.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reproduce: Option<String>,
+    /// Commit whose results were reused, since nothing in `--relevant-paths`
+    /// changed since it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unchanged_since: Option<String>,
+    /// Revisions given as arguments that selected the commit, if there was
+    /// more than one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revs: Vec<String>,
+    /// Notes added with `annotate` after the run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// Write results as a standalone HTML report.
+pub fn write_html(path: &Path, run: &Run, date: &DateFormat) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut title = format!("git corun -- {}", run.command.join(" "));
+    if let Some(label) = &run.label {
+        title = format!("{}: {}", label, title);
+    }
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", escape_html(&title))?;
+    writeln!(
+        out,
+        "<style>body {{ font-family: sans-serif; }} td, th {{ padding: 2px 8px; text-align: left; vertical-align: top; }} .success {{ color: green; }} .failure, .abort {{ color: red; }} .inconclusive {{ color: blue; }} .skipped, .not-run, .cancelled {{ color: gray; }} .xfail {{ color: darkgoldenrod; }} .xpass {{ color: purple; }}</style>"
+    )?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1><code>{}</code></h1>", escape_html(&title))?;
+    let stashes = run
+        .stashes
+        .iter()
+        .map(|stash| (stash, "applied on top of each commit"))
+        .chain(
+            run.stash_as_commit
+                .iter()
+                .map(|stash| (stash, "run on as a commit")),
+        )
+        .collect::<Vec<_>>();
+    if !stashes.is_empty() {
+        writeln!(out, "<ul>")?;
+        for (stash, how) in stashes {
+            write!(
+                out,
+                "<li><code>{}</code> (<code>{:.7}</code>), {}",
+                escape_html(&stash.name),
+                stash.commit,
+                how
+            )?;
+            if let Some(patch) = &stash.patch {
+                let patch = escape_html(&patch.to_string_lossy());
+                write!(out, ": <a href=\"file://{}\">{}</a>", patch, patch)?;
+            }
+            writeln!(out, "</li>")?;
+        }
+        writeln!(out, "</ul>")?;
+    }
+    writeln!(out, "<table>")?;
+    writeln!(
+        out,
+        "<tr><th>Status</th><th>Commit</th><th>Parents</th><th>Subject</th><th>Author</th><th>Date</th><th>Label</th><th>Duration</th><th>CPU time</th><th>Max RSS</th><th>Metric</th><th>Trailers</th><th>Revisions</th><th>Notes</th><th>Reproduce</th></tr>"
+    )?;
+    for result in &run.results {
+        let trailers = result
+            .info
+            .trailers
+            .iter()
+            .map(|trailer| escape_html(&format!("{}: {}", trailer.key, trailer.value)))
+            .collect::<Vec<_>>()
+            .join("<br>");
+        let notes = result
+            .notes
+            .iter()
+            .map(|note| escape_html(note))
+            .collect::<Vec<_>>()
+            .join("<br>");
+        let mut code = result
+            .status
+            .code()
+            .map(|code| format!(" ({})", code))
+            .unwrap_or_default();
+        if let Some(commit) = &result.unchanged_since {
+            code.push_str(&format!(", unchanged since <code>{:.7}</code>", commit));
+        }
+        let parents = result
+            .info
+            .parents
+            .iter()
+            .map(|parent| format!("<code>{:.7}</code>", parent))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        writeln!(
+            out,
+            "<tr class=\"{}\"><td>{}{}</td><td><code>{:.7}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}s</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            result.status.name(),
+            result.status.name(),
+            code,
+            result.info.commit,
+            parents,
+            escape_html(&result.info.subject),
+            escape_html(&result.info.author),
+            escape_html(&format_date(&result.info.date, date)),
+            escape_html(&result.label),
+            result.duration,
+            result
+                .usage
+                .map(|usage| format!("{:.1}s", usage.cpu_time()))
+                .unwrap_or_default(),
+            result
+                .usage
+                .map(|usage| format!("{} MiB", usage.max_rss / 1024))
+                .unwrap_or_default(),
+            result
+                .metric
+                .map(|metric| metric.to_string())
+                .unwrap_or_default(),
+            trailers,
+            escape_html(&result.revs.join(" ")),
+            notes,
+            result
+                .reproduce
+                .as_ref()
+                .map(|command| format!("<code>{}</code>", escape_html(command)))
+                .unwrap_or_default()
+        )?;
+    }
+    writeln!(out, "</table>")?;
+    if run.results.len() > 1 {
+        write!(out, "{}", plot::svg(&run.results))?;
+    }
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    out.flush()
+}
+
+/// Write results as CSV, with one row for each commit (and label), and CRLF
+/// line endings like CSV files are expected to have.
+pub fn write_csv(path: &Path, run: &Run) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(
+        out,
+        "commit,parents,subject,author,email,date,label,status,exit_code,duration,user_time,system_time,max_rss,major_faults,minor_faults,metric,revs,notes,reproduce\r\n"
+    )?;
+    for result in &run.results {
+        let code = result
+            .status
+            .code()
+            .map(|code| code.to_string())
+            .unwrap_or_default();
+        let parents = result.info.parents.join(" ");
+        let usage = match &result.usage {
+            Some(usage) => vec![
+                format!("{:.3}", usage.user_time),
+                format!("{:.3}", usage.system_time),
+                usage.max_rss.to_string(),
+                usage.major_faults.to_string(),
+                usage.minor_faults.to_string(),
+            ],
+            None => vec![String::new(); 5],
+        };
+        let fields = [
+            result.info.commit.as_str(),
+            &parents,
+            &result.info.subject,
+            &result.info.author,
+            &result.info.email,
+            &result.info.date,
+            &result.label,
+            result.status.name(),
+            &code,
+            &format!("{:.3}", result.duration),
+        ];
+        let metric = result
+            .metric
+            .map(|metric| metric.to_string())
+            .unwrap_or_default();
+        let revs = result.revs.join(" ");
+        let notes = result.notes.join("\n");
+        let reproduce = result.reproduce.as_deref().unwrap_or_default();
+        let row = fields
+            .iter()
+            .copied()
+            .chain(usage.iter().map(String::as_str))
+            .chain([metric.as_str(), revs.as_str(), notes.as_str(), reproduce])
+            .map(escape_csv)
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(out, "{}\r\n", row)?;
+    }
+    out.flush()
+}
+
+/// Quote a CSV field if it contains separators, quotes or line breaks.
+fn escape_csv(field: &str) -> String {
+    if field.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Write a result to a file of its own, named by a template with `{commit}`,
+/// `{short}` and `{label}` placeholders, containing the exit code (or `-`) and
+/// the status, e.g. `1 failure`.
+pub fn write_result_file(template: &str, result: &RunResult) -> io::Result<()> {
+    let commit = &result.info.commit;
+    let label = result
+        .label
+        .chars()
+        .map(|c| {
+            if c == '/' || c.is_whitespace() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+    let path = template
+        .replace("{commit}", commit)
+        .replace("{short}", &commit[..commit.len().min(7)])
+        .replace("{label}", &label);
+    let path = Path::new(&path);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let code = match result.status.code() {
+        Some(code) => code.to_string(),
+        None => "-".to_string(),
+    };
+    fs::write(path, format!("{} {}\n", code, result.status.name()))
+}
+
+/// Format an RFC 3339 date like git does with `--date`, or leave it as it is if
+/// it cannot be parsed or formatted.
+pub fn format_date(date: &str, format: &DateFormat) -> String {
+    let parsed = match DateTime::parse_from_rfc3339(date) {
+        Ok(parsed) => parsed,
+        Err(_) => return date.to_string(),
+    };
+
+    match format {
+        DateFormat::Relative => format_relative(Local::now().signed_duration_since(parsed)),
+        DateFormat::Iso => parsed.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        DateFormat::Local => parsed
+            .with_timezone(&Local)
+            .format("%a %b %e %H:%M:%S %Y")
+            .to_string(),
+        DateFormat::Format(format) => {
+            let items = StrftimeItems::new(format).collect::<Vec<_>>();
+            if items.contains(&Item::Error) {
+                return date.to_string();
+            }
+            parsed.format_with_items(items.into_iter()).to_string()
+        }
+    }
+}
+
+/// Format the time since a date, with roughly the same units as git.
+fn format_relative(age: chrono::Duration) -> String {
+    let seconds = age.num_seconds();
+    let (count, unit) = if seconds < 0 {
+        return "in the future".to_string();
+    } else if seconds < 90 {
+        (seconds, "second")
+    } else if seconds < 90 * 60 {
+        (age.num_minutes(), "minute")
+    } else if seconds < 36 * 3600 {
+        (age.num_hours(), "hour")
+    } else if age.num_days() < 14 {
+        (age.num_days(), "day")
+    } else if age.num_days() < 70 {
+        (age.num_weeks(), "week")
+    } else if age.num_days() < 365 {
+        (age.num_days() / 30, "month")
+    } else {
+        (age.num_days() / 365, "year")
+    };
+
+    if count == 1 {
+        format!("{} {} ago", count, unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Trailer to add to commits that passed, with `--suggest-trailers`.
+const TESTED_BY_TRAILER: &str = "Tested-by: git-corun (exit 0)";
+
+/// Print a rebase todo list that adds a `Tested-by` trailer to each commit
+/// that passed, for a linear history of commits, oldest first.
+pub fn print_trailer_todo(commits: &[CommitInfo], results: &[RunResult]) -> io::Result<()> {
+    let onto = match commits.first().map(|info| info.parents.first()) {
+        Some(Some(parent)) => format!("{:.7}", parent),
+        Some(None) => "--root".to_string(),
+        None => return Ok(()),
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    writeln!(
+        stdout,
+        "\n# Rebase todo list to add \"{}\" to the commits that passed.",
+        TESTED_BY_TRAILER
+    )?;
+    writeln!(
+        stdout,
+        "# Save it to a file, e.g. todo.txt, and apply it with:"
+    )?;
+    writeln!(
+        stdout,
+        "#   git -c sequence.editor=\"cp $PWD/todo.txt\" rebase -i {}",
+        onto
+    )?;
+    for info in commits {
+        writeln!(stdout, "pick {:.7} {}", info.commit, info.subject)?;
+
+        let mut commit_results = results
+            .iter()
+            .filter(|result| result.info.commit == info.commit)
+            .peekable();
+        if commit_results.peek().is_some()
+            && commit_results.all(|result| matches!(result.status, Status::Success(_)))
+        {
+            writeln!(
+                stdout,
+                "exec git commit --quiet --amend --no-edit --trailer \"{}\"",
+                TESTED_BY_TRAILER
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the number of passing, failing and other commits for each group of
+/// commits, where a commit may be in several groups.
+///
+/// A commit passes if all commands run on it succeeded, and fails if any of
+/// them failed.
+pub fn print_grouped_summary<F>(title: &str, results: &[RunResult], groups_of: F) -> io::Result<()>
+where
+    F: Fn(&CommitInfo) -> Vec<String>,
+{
+    // (group, passed, failed, other), in order of first appearance
+    let mut counts: Vec<(String, usize, usize, usize)> = Vec::new();
+    let mut indices = HashMap::new();
+
+    for commit_results in results.chunk_by(|a, b| a.info.commit == b.info.commit) {
+        let info = &commit_results[0].info;
+        let failed = commit_results
+            .iter()
+            .any(|result| result.status.is_failure());
+        let passed = commit_results
+            .iter()
+            .all(|result| matches!(result.status, Status::Success(_)));
+
+        for group in groups_of(info) {
+            let index = *indices.entry(group.clone()).or_insert_with(|| {
+                counts.push((group, 0, 0, 0));
+                counts.len() - 1
+            });
+            let entry = &mut counts[index];
+            if failed {
+                entry.2 += 1;
+            } else if passed {
+                entry.1 += 1;
+            } else {
+                entry.3 += 1;
+            }
+        }
+    }
+
+    let width = counts
+        .iter()
+        .map(|(group, ..)| group.chars().count())
+        .chain(Some(title.chars().count()))
+        .max()
+        .unwrap_or_default();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    writeln!(
+        stdout,
+        "\n{:width$}  {:>5}  {:>5}  {:>5}",
+        title,
+        "✔",
+        "✘",
+        "?",
+        width = width
+    )?;
+    for (group, passed, failed, other) in counts {
+        writeln!(
+            stdout,
+            "{:width$}  {:>5}  {:>5}  {:>5}",
+            group,
+            passed,
+            failed,
+            other,
+            width = width
+        )?;
+    }
+
+    Ok(())
+}
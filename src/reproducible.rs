@@ -0,0 +1,59 @@
+//! Checking that commands produce the same output every time they are run,
+//! e.g. that builds are reproducible, by hashing their output.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Hash a file, or the files in a directory with their paths relative to it,
+/// with `git hash-object`. Files that do not exist have the hash `missing`.
+pub fn hash(path: &Path) -> io::Result<String> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok("missing".to_string()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.is_dir() {
+        return hash_objects(&[path.to_string_lossy().to_string()])
+            .map(|mut hashes| hashes.remove(0));
+    }
+
+    let mut files = Vec::new();
+    list_files(path, Path::new(""), &mut files)?;
+    files.sort();
+
+    let paths = files
+        .iter()
+        .map(|file| path.join(file).to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    let listing = files
+        .iter()
+        .zip(hash_objects(&paths)?)
+        .map(|(file, hash)| format!("{} {}\n", hash, file))
+        .collect::<String>();
+    let output = gitc_with_input!(listing; "hash-object", "--stdin")?;
+    Ok(output.trim().to_string())
+}
+
+/// List files in a directory recursively, relative to the directory.
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(root, &path, files)?;
+        } else {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+    Ok(())
+}
+
+fn hash_objects(paths: &[String]) -> io::Result<Vec<String>> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let input = paths.join("\n") + "\n";
+    let output = gitc_with_input!(input; "hash-object", "--no-filters", "--stdin-paths")?;
+    Ok(output.lines().map(str::to_string).collect())
+}
@@ -0,0 +1,500 @@
+//! Running commands, with their output written to a log file.
+//!
+//! Standard output and standard error are also written to log files of their
+//! own, next to the combined log, except when run with a pseudo-terminal.
+//!
+//! Each command is run in its own session, so that it and everything it starts
+//! can be killed together as a process group, when it exits, when it times out
+//! and when corun itself is interrupted.
+
+use std::ffi::{CStr, OsStr};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::jail;
+use crate::status::Status;
+
+/// How long to wait for a process group to exit after SIGTERM, before SIGKILL.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How often to check whether a command has exited, timed out or been interrupted.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RunOptions {
+    /// Also write output to the terminal.
+    pub verbose: bool,
+    /// Kill the command if it runs for longer than this.
+    pub timeout: Option<Duration>,
+    /// Run the command with its output connected to a pseudo-terminal.
+    pub pty: bool,
+    /// Maximum size of the virtual memory of the command, in bytes.
+    pub memory_limit: Option<u64>,
+    /// Jail to run the command in, as given by `Jail::fd`.
+    pub jail: Option<RawFd>,
+    /// Prefix each line of the logs with the time since the command started.
+    pub timestamps: bool,
+}
+
+/// Resources used by a command, including the processes it started that it
+/// waited for.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// CPU time spent in user mode, in seconds.
+    pub user_time: f64,
+    /// CPU time spent in the kernel, in seconds.
+    pub system_time: f64,
+    /// Largest resident set size of any of the processes, in kilobytes.
+    pub max_rss: u64,
+    /// Page faults that needed to read from disk.
+    pub major_faults: u64,
+    /// Page faults that did not need to read from disk.
+    pub minor_faults: u64,
+}
+
+impl Usage {
+    fn from_rusage(usage: &libc::rusage) -> Self {
+        let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1e6;
+        Usage {
+            user_time: seconds(usage.ru_utime),
+            system_time: seconds(usage.ru_stime),
+            max_rss: usage.ru_maxrss as u64,
+            major_faults: usage.ru_majflt as u64,
+            minor_faults: usage.ru_minflt as u64,
+        }
+    }
+
+    /// Total CPU time, in seconds.
+    pub fn cpu_time(&self) -> f64 {
+        self.user_time + self.system_time
+    }
+}
+
+/// Whether corun was interrupted or terminated, so that it stops running
+/// commands.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Stop commands instead of exiting when corun is interrupted or terminated,
+/// since commands in their own session do not get signals from the terminal.
+pub fn handle_interrupts() {
+    extern "C" fn on_signal(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    for &signal in &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        unsafe {
+            libc::signal(signal, on_signal as *const () as libc::sighandler_t);
+        }
+    }
+}
+
+/// Run a command, writing its output to a log file, and also to the terminal if
+/// `verbose` is set.
+///
+/// With a shell, the first element of the command is a script that is run with
+/// it, and the rest are its arguments.
+pub fn run_in(
+    command: &[String],
+    shell: Option<&str>,
+    dir: &Path,
+    env: &[(String, String)],
+    log_path: &Path,
+    options: RunOptions,
+) -> io::Result<(Status, Usage)> {
+    let (exec_name, cmd_args) = match shell {
+        Some(shell) => (shell, shell_args(shell, command)),
+        None => (
+            command[0].as_str(),
+            command[1..].iter().map(String::as_str).collect(),
+        ),
+    };
+
+    let log = Arc::new(Mutex::new(File::create(log_path)?));
+    let start = if options.timestamps {
+        Some(Instant::now())
+    } else {
+        None
+    };
+
+    let mut cmd = Command::new(exec_name);
+    cmd.args(&cmd_args)
+        .current_dir(dir)
+        .envs(env.iter().cloned())
+        .stdin(Stdio::null());
+    if let Some(limit) = options.memory_limit {
+        unsafe {
+            cmd.pre_exec(move || {
+                let limit = libc::rlimit {
+                    rlim_cur: limit as libc::rlim_t,
+                    rlim_max: limit as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &limit) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    if let Some(fd) = options.jail {
+        unsafe {
+            cmd.pre_exec(move || jail::enter(fd));
+        }
+    }
+
+    let (child, threads) = if options.pty {
+        let (master, slave) = open_pty()?;
+        cmd.stdout(slave.try_clone()?).stderr(slave);
+        unsafe {
+            cmd.pre_exec(|| {
+                new_session()?;
+                // make the pseudo-terminal the controlling terminal
+                if libc::ioctl(1, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn()?;
+        // close our copies of the slave, so reading the master ends when the command exits
+        drop(cmd);
+
+        let output: Box<dyn Write + Send> = if options.verbose {
+            Box::new(io::stdout())
+        } else {
+            Box::new(io::sink())
+        };
+        let thread = spawn_tee(master, output, None, log, start);
+        (child, vec![thread])
+    } else {
+        unsafe {
+            cmd.pre_exec(new_session);
+        }
+        let (stdout_output, stderr_output): (Box<dyn Write + Send>, Box<dyn Write + Send>) =
+            if options.verbose {
+                (Box::new(io::stdout()), Box::new(io::stderr()))
+            } else {
+                (Box::new(io::sink()), Box::new(io::sink()))
+            };
+        let stdout_log = File::create(stream_log_path(log_path, "stdout"))?;
+        let stderr_log = File::create(stream_log_path(log_path, "stderr"))?;
+
+        let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let threads = vec![
+            spawn_tee(stdout, stdout_output, Some(stdout_log), log.clone(), start),
+            spawn_tee(stderr, stderr_output, Some(stderr_log), log, start),
+        ];
+        (child, threads)
+    };
+
+    let oom_kills = oom_kill_count();
+    let exit_status = wait(&child, options.timeout);
+    for thread in threads {
+        thread.join().expect("output thread panicked")?;
+    }
+
+    let (exit_status, usage) = exit_status?;
+    let status = match exit_status {
+        // the OOM killer uses SIGKILL, so only count it as OOM if it killed something
+        Some(exit_status)
+            if exit_status.signal() == Some(libc::SIGKILL)
+                && oom_kills.is_some()
+                && oom_kill_count() > oom_kills =>
+        {
+            Status::Oom
+        }
+        Some(exit_status) => Status::from(exit_status),
+        None => Status::Timeout,
+    };
+    Ok((status, usage))
+}
+
+/// Shell command line that runs a command the same way as `run_in`, for
+/// copying and pasting, e.g. `cd /tmp/dir && FOO=1 bash -c 'make test' corun`.
+pub fn command_line(
+    command: &[String],
+    shell: Option<&str>,
+    dir: &Path,
+    env: &[(String, String)],
+) -> String {
+    let mut words = vec![
+        "cd".to_string(),
+        shell_quote(&dir.to_string_lossy()),
+        "&&".to_string(),
+    ];
+    words.extend(
+        env.iter()
+            .map(|(name, value)| format!("{}={}", name, shell_quote(value))),
+    );
+    match shell {
+        Some(shell) => {
+            words.push(shell_quote(shell));
+            words.extend(shell_args(shell, command).into_iter().map(shell_quote));
+        }
+        None => words.extend(command.iter().map(|arg| shell_quote(arg))),
+    }
+    words.join(" ")
+}
+
+/// Path of the log of only standard output or standard error (`stream`) next to
+/// a combined log, e.g. `0123abc.stderr.log` for `0123abc.log`.
+pub fn stream_log_path(log_path: &Path, stream: &str) -> PathBuf {
+    let stem = log_path.file_stem().unwrap_or_default().to_string_lossy();
+    log_path.with_file_name(format!("{}.{}.log", stem, stream))
+}
+
+/// Run the user's shell interactively in a directory, until it exits.
+///
+/// Interrupts are ignored by corun meanwhile, so that pressing Ctrl-C in the
+/// shell does not stop the run.
+pub fn run_shell(dir: &Path, env: &[(String, String)]) -> io::Result<()> {
+    let shell = std::env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into());
+
+    let mut cmd = Command::new(shell);
+    cmd.current_dir(dir).envs(env.iter().cloned());
+    unsafe {
+        // ignored signals stay ignored in child processes
+        cmd.pre_exec(|| {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            Ok(())
+        });
+    }
+
+    let handler = unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+    let status = cmd.status();
+    unsafe { libc::signal(libc::SIGINT, handler) };
+    status.map(drop)
+}
+
+/// Arguments to run a script with a shell, with `$0` set to `corun` and the
+/// rest of the command as `$1`, `$2` and so on.
+///
+/// fish has no `$0` for scripts given with `-c`, and gets the arguments as `$argv`.
+pub fn shell_args<'a>(shell: &str, command: &'a [String]) -> Vec<&'a str> {
+    let (script, args) = command.split_first().expect("command is not empty");
+
+    let mut shell_args = vec!["-c", script.as_str()];
+    if !Path::new(shell).ends_with("fish") {
+        shell_args.push("corun");
+    }
+    shell_args.extend(args.iter().map(String::as_str));
+    shell_args
+}
+
+/// Quote a string for the shell, unless it only has characters that do not
+/// need quoting.
+pub fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Number of processes killed by the OOM killer since boot, where available.
+fn oom_kill_count() -> Option<u64> {
+    let vmstat = std::fs::read_to_string("/proc/vmstat").ok()?;
+    vmstat
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+fn new_session() -> io::Result<()> {
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Wait for a child process to exit, or kill it if it times out (returning
+/// `None`) or if corun is interrupted (returning an error).
+///
+/// Anything else left in its process group is killed when it exits.
+fn wait(child: &Child, timeout: Option<Duration>) -> io::Result<(Option<ExitStatus>, Usage)> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        if let Some(exited) = reap(child, false)? {
+            let (exit_status, usage) = kill_group(child, Some(exited))?;
+            return Ok((Some(exit_status), usage));
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            kill_group(child, None)?;
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            let (_, usage) = kill_group(child, None)?;
+            return Ok((None, usage));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Terminate the process group of a child process, and kill whatever is left
+/// of it if it does not exit in time.
+fn kill_group(
+    child: &Child,
+    mut exited: Option<(ExitStatus, Usage)>,
+) -> io::Result<(ExitStatus, Usage)> {
+    let pgid = child.id() as libc::pid_t;
+
+    unsafe { libc::killpg(pgid, libc::SIGTERM) };
+    let deadline = Instant::now() + KILL_GRACE_PERIOD;
+    while Instant::now() < deadline {
+        // the child must be waited for, since it stays in the group until then
+        if exited.is_none() {
+            exited = reap(child, false)?;
+        }
+        if exited.is_some() && !group_exists(pgid) {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    unsafe { libc::killpg(pgid, libc::SIGKILL) };
+    match exited {
+        Some(exited) => Ok(exited),
+        None => Ok(reap(child, true)?.expect("child has exited")),
+    }
+}
+
+/// Wait for a child process if it has exited (or until it does, if `block` is
+/// set), returning its exit status and resource usage.
+///
+/// This waits with `wait4` instead of with `Child`, which does not get the
+/// resource usage, so `Child` must not be used to wait for it afterwards.
+fn reap(child: &Child, block: bool) -> io::Result<Option<(ExitStatus, Usage)>> {
+    let flags = if block { 0 } else { libc::WNOHANG };
+    let mut status = 0;
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    loop {
+        match unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, flags, &mut usage) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(None),
+            _ => {
+                return Ok(Some((
+                    ExitStatus::from_raw(status),
+                    Usage::from_rusage(&usage),
+                )))
+            }
+        }
+    }
+}
+
+fn group_exists(pgid: libc::pid_t) -> bool {
+    unsafe { libc::killpg(pgid, 0) == 0 }
+}
+
+/// Open a pseudo-terminal, returning the master and slave ends.
+fn open_pty() -> io::Result<(File, File)> {
+    let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    if master == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { File::from_raw_fd(master) };
+
+    let slave_name = unsafe {
+        if libc::grantpt(master.as_raw_fd()) == -1 || libc::unlockpt(master.as_raw_fd()) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let name = libc::ptsname(master.as_raw_fd());
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        OsStr::from_bytes(CStr::from_ptr(name).to_bytes()).to_owned()
+    };
+    let slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(slave_name)?;
+
+    // use the same size as the terminal corun runs in, if any
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(1, libc::TIOCGWINSZ, &mut size) == 0 {
+            libc::ioctl(slave.as_raw_fd(), libc::TIOCSWINSZ, &size);
+        }
+    }
+
+    Ok((master, slave))
+}
+
+/// Copy output of a command to the terminal (or elsewhere) and to logs: a log of
+/// its own, if any, and the combined log. With a start time, each line in the
+/// logs is prefixed with the time since then.
+fn spawn_tee(
+    mut input: impl Read + Send + 'static,
+    mut output: Box<dyn Write + Send>,
+    mut own_log: Option<File>,
+    log: Arc<Mutex<File>>,
+    start: Option<Instant>,
+) -> thread::JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        let mut stamped = Vec::new();
+        let mut at_line_start = true;
+        loop {
+            let len = match input.read(&mut buf) {
+                Ok(len) => len,
+                // reading a pseudo-terminal fails once all of its slaves are closed
+                Err(err) if err.raw_os_error() == Some(libc::EIO) => 0,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if len == 0 {
+                return Ok(());
+            }
+
+            output.write_all(&buf[..len])?;
+            output.flush()?;
+
+            let logged = match start {
+                Some(start) => {
+                    stamped.clear();
+                    for line in buf[..len].split_inclusive(|&b| b == b'\n') {
+                        if at_line_start {
+                            let elapsed = start.elapsed().as_secs_f64();
+                            write!(stamped, "[{:9.3}s] ", elapsed)?;
+                        }
+                        stamped.extend_from_slice(line);
+                        at_line_start = line.ends_with(b"\n");
+                    }
+                    &stamped[..]
+                }
+                None => &buf[..len],
+            };
+            if let Some(own_log) = &mut own_log {
+                own_log.write_all(logged)?;
+            }
+            log.lock().unwrap().write_all(logged)?;
+        }
+    })
+}
@@ -0,0 +1,150 @@
+//! Error signatures of failures, such as the first compiler error or the name
+//! of the first failed test, to tell apart the different ways commits fail.
+//!
+//! Failing commits are grouped by their signatures at the end of a run. The
+//! signature is taken from the first line of the log that looks like an
+//! error, with line and column numbers removed so that the same error in a
+//! file that moved around counts as the same, or else the last line of the
+//! log. Other patterns can be set in git config, which are checked first and
+//! whose first capture group (or whole match) is used:
+//!
+//! ```text
+//! [corun]
+//!     errorSignature = "^ERROR \\[\\w+\\] (.*)"
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::git;
+use crate::report::RunResult;
+use crate::term;
+
+const DEFAULT_PATTERNS: &[&str] = &[
+    // rustc, and tools that copy its style
+    r"^(error(\[\w+\])?: .*)",
+    // gcc, clang and others
+    r"^(\S+: (fatal )?error: .*)",
+    // cargo test
+    r"^(test \S+ \.\.\. FAILED)",
+    // go test
+    r"^(--- FAIL: \S+)",
+    // pytest
+    r"^(FAILED \S+)",
+    // exceptions in Python, Java and others
+    r"^(\w*(Error|Exception): .*)",
+];
+
+pub struct Signatures {
+    patterns: Vec<Regex>,
+    /// Colors, and timestamps added with `--timestamps`.
+    noise: Regex,
+    location: Regex,
+}
+
+impl Signatures {
+    /// Get the patterns for signatures, with ones from the git config of a
+    /// repository first if set.
+    pub fn load(git_dir: Option<&Path>) -> io::Result<Self> {
+        let configured = match git_dir {
+            Some(git_dir) => git::get_config_values(git_dir, "corun.errorSignature")?,
+            None => Vec::new(),
+        };
+        let patterns = configured
+            .iter()
+            .map(String::as_str)
+            .chain(DEFAULT_PATTERNS.iter().copied())
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|err| {
+                    io::Error::other(format!("corun.errorSignature {}: {}", pattern, err))
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Signatures {
+            patterns,
+            noise: Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]|^\[ *[0-9.]+s\] ").unwrap(),
+            location: Regex::new(r":\d+(:\d+)?\b").unwrap(),
+        })
+    }
+
+    /// Get the signature of the failure in a log.
+    pub fn extract(&self, log_path: &Path) -> io::Result<Option<String>> {
+        let log = String::from_utf8_lossy(&fs::read(log_path)?).into_owned();
+        let lines = log
+            .lines()
+            .map(|line| self.noise.replace_all(line, "").trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+
+        for pattern in &self.patterns {
+            for line in &lines {
+                if let Some(captures) = pattern.captures(line) {
+                    let text = captures.get(1).or_else(|| captures.get(0)).unwrap();
+                    return Ok(Some(
+                        self.location.replace_all(text.as_str(), "").into_owned(),
+                    ));
+                }
+            }
+        }
+        Ok(lines.last().cloned())
+    }
+}
+
+/// Print failing commits grouped by the signatures of their failures, most
+/// common first.
+pub fn print_clusters(results: &[RunResult]) -> io::Result<()> {
+    let failures = results
+        .iter()
+        .filter(|result| result.status.is_failure())
+        .collect::<Vec<_>>();
+
+    // (signature, commits), in order of first appearance
+    let mut clusters: Vec<(&str, Vec<&str>)> = Vec::new();
+    for result in &failures {
+        let signature = result.signature.as_deref().unwrap_or("(no output)");
+        let commit = result.info.commit.as_str();
+        match clusters.iter_mut().find(|(other, _)| *other == signature) {
+            Some((_, commits)) => {
+                if !commits.contains(&commit) {
+                    commits.push(commit);
+                }
+            }
+            None => clusters.push((signature, vec![commit])),
+        }
+    }
+    clusters.sort_by_key(|(_, commits)| std::cmp::Reverse(commits.len()));
+    let failed_commits = failures
+        .iter()
+        .map(|result| &result.info.commit)
+        .collect::<HashSet<_>>()
+        .len();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    writeln!(
+        stdout,
+        "\n{} distinct errors on {} failing commits:",
+        clusters.len(),
+        failed_commits
+    )?;
+    for (signature, commits) in clusters {
+        writeln!(
+            stdout,
+            "{:>5}  {}",
+            commits.len(),
+            term::paint("31", signature)
+        )?;
+        let commits = commits
+            .iter()
+            .map(|commit| format!("{:.7}", commit))
+            .collect::<Vec<_>>();
+        writeln!(stdout, "       {}", term::paint("33", commits.join(" ")))?;
+    }
+    Ok(())
+}
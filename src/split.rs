@@ -0,0 +1,79 @@
+//! Splitting a large commit into partial commits by directory, which are made
+//! as synthetic commits so that each part can be run on by itself, to find out
+//! which part of the commit a failure comes from.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use crate::git;
+use crate::synthetic::Scratch;
+
+/// Make a commit for each top-level directory changed by a commit, with only
+/// the changes in that directory applied to its (first) parent. Changes to
+/// files at the top level are made into a commit of their own.
+pub fn by_path(git_dir: &Path, commit: &str) -> io::Result<Vec<String>> {
+    let commit = git::get_commit_hash(git_dir, format!("{}^{{commit}}", commit))
+        .map_err(|_| io::Error::other(format!("not a commit: {}", commit)))?;
+    let rev = format!("{}^", commit);
+    let parent = gitc!(
+        "--git-dir",
+        git_dir,
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        rev
+    )
+    .map_err(|_| io::Error::other(format!("{:.7} has no parent", commit)))?;
+
+    let changed = gitc!(
+        "--git-dir",
+        git_dir,
+        "diff-tree",
+        "-r",
+        "-z",
+        "--name-only",
+        "--no-renames",
+        parent,
+        commit
+    )?;
+
+    // changed files by top-level directory, or "" for files at the top level
+    let mut parts = BTreeMap::<&str, Vec<&str>>::new();
+    for path in changed.split('\0').filter(|path| !path.is_empty()) {
+        let dir = match path.find('/') {
+            Some(index) => &path[..=index],
+            None => "",
+        };
+        parts.entry(dir).or_default().push(path);
+    }
+    if parts.len() < 2 {
+        return Err(io::Error::other(format!(
+            "{:.7} only changes one directory, so it cannot be split",
+            commit
+        )));
+    }
+
+    let subject = gitc!("--git-dir", git_dir, "log", "-1", "--format=%s", commit)?;
+    let scratch = Scratch::create(git_dir, &parent)?;
+    let mut commits = Vec::new();
+    for (dir, paths) in &parts {
+        let source = format!("--source={}", commit);
+        let mut args = vec!["restore", &source, "--staged", "--worktree", "--"];
+        args.extend(paths);
+        scratch.checkout(&parent)?;
+        scratch.git(&args)?;
+
+        let message = format!(
+            "{} [only {}]",
+            subject,
+            if dir.is_empty() { "top level" } else { dir }
+        );
+        scratch.git(&["commit", "--quiet", "-C", &commit])?;
+        scratch.git(&["commit", "--quiet", "--amend", "-m", &message])?;
+        commits.push(scratch.head()?);
+    }
+
+    scratch.keep("split", &commits)?;
+    Ok(commits)
+}
@@ -0,0 +1,60 @@
+//! Series of stacked commits, such as a branch of commits that are each
+//! reviewed and landed by themselves.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use crate::synthetic::Scratch;
+
+/// Check that commits are a linear history, and return the commit they are
+/// based on, which is the parent of the oldest one.
+pub fn base(git_dir: &Path, commits: &[String]) -> io::Result<String> {
+    let in_stack = commits.iter().map(String::as_str).collect::<HashSet<_>>();
+
+    let mut parents = HashSet::new();
+    let mut bases = HashSet::new();
+    for commit in commits {
+        let rev = format!("{}^@", commit);
+        let commit_parents = gitc!("--git-dir", git_dir, "rev-parse", rev)?;
+        let parent = match commit_parents.lines().collect::<Vec<_>>()[..] {
+            [parent] => parent.to_string(),
+            [] => return Err(io::Error::other(format!("{:.7} has no parent", commit))),
+            _ => return Err(io::Error::other(format!("{:.7} is a merge commit", commit))),
+        };
+        if !parents.insert(parent.clone()) {
+            return Err(io::Error::other(format!(
+                "more than one commit has {:.7} as parent, so the commits are not a linear history",
+                parent
+            )));
+        }
+        if !in_stack.contains(parent.as_str()) {
+            bases.insert(parent);
+        }
+    }
+
+    match bases.len() {
+        1 => Ok(bases.into_iter().next().unwrap()),
+        0 => Err(io::Error::other("no commits to run on")),
+        _ => Err(io::Error::other(
+            "commits are not a linear history, since they have more than one base",
+        )),
+    }
+}
+
+/// Make a commit for each commit with only its own changes applied to the base,
+/// returning them in the same order.
+pub fn independent(git_dir: &Path, base: &str, commits: &[String]) -> io::Result<Vec<String>> {
+    let scratch = Scratch::create(git_dir, base)?;
+    let mut independent = Vec::new();
+    for commit in commits {
+        scratch.checkout(base)?;
+        let picked = scratch
+            .cherry_pick(commit)
+            .map_err(|err| io::Error::other(format!("{}, so it cannot land by itself", err)))?;
+        independent.push(picked);
+    }
+
+    scratch.keep("stack", &independent)?;
+    Ok(independent)
+}
@@ -0,0 +1,161 @@
+use std::fmt::Display;
+use std::process::ExitStatus;
+
+use serde::{Deserialize, Serialize};
+
+use crate::term;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "status", content = "exit_code", rename_all = "lowercase")]
+pub enum Status {
+    /// Process is still running.
+    Pending,
+    /// Process exited with exit code 0.
+    Success(i32),
+    /// Process exited with exit code 1-124, 126 or 127.
+    Failure(i32),
+    /// Process exited with exit code 125.
+    Inconclusive(i32),
+    /// Process exited with any other exit code.
+    Abort(Option<i32>),
+    /// Process was killed because it ran for too long.
+    Timeout,
+    /// Process was killed by the kernel because the system ran out of memory.
+    Oom,
+    /// Process was not run, because a step it depends on did not succeed.
+    Skipped,
+    /// Process failed, as it was expected to.
+    Xfail(Option<i32>),
+    /// Process exited with exit code 0, but was expected to fail.
+    Xpass(i32),
+    /// Process was not run, because the run was cut short before it.
+    #[serde(rename = "not-run")]
+    NotRun,
+    /// Process was stopped, because corun was interrupted.
+    Cancelled,
+}
+
+impl Status {
+    /// Color (name and ANSI code) and symbol used to show the status.
+    pub fn style(self) -> (&'static str, u8, &'static str) {
+        match self {
+            Status::Pending => ("yellow", 33, "●"),
+            Status::Success(_) => ("green", 32, "✔"),
+            Status::Failure(_) => ("red", 31, "✘"),
+            Status::Inconclusive(_) => ("blue", 34, "?"),
+            Status::Abort(_) => ("red", 31, "!"),
+            Status::Timeout => ("red", 31, "⧖"),
+            Status::Oom => ("red", 31, "M"),
+            Status::Skipped => ("white", 37, "-"),
+            Status::Xfail(_) => ("yellow", 33, "x"),
+            Status::Xpass(_) => ("magenta", 35, "✔"),
+            Status::NotRun => ("white", 37, "·"),
+            Status::Cancelled => ("yellow", 33, "^"),
+        }
+    }
+
+    pub fn get_format(self) -> impl Display {
+        let (color, _, _) = self.style();
+        format!("%C(bold)%C({}){}%Creset", color, self.text())
+    }
+
+    /// Same as `get_format`, but with ANSI escape codes instead of git placeholders.
+    pub fn get_ansi_format(self) -> impl Display {
+        let (_, color, _) = self.style();
+        term::paint(&format!("1;{}", color), self.text())
+    }
+
+    /// Text that shows the status, e.g. `✘  1`, or `FAIL exit 1` if statuses
+    /// are shown as words.
+    pub fn text(self) -> String {
+        if term::words_enabled() {
+            return self.words();
+        }
+        let (_, _, symbol) = self.style();
+        match self.code() {
+            Some(code) => format!("{}{:>3}", symbol, (code & 0xff) as u8),
+            None => format!("{}   ", symbol),
+        }
+    }
+
+    /// Status in words, for screen readers and terminals without Unicode.
+    fn words(self) -> String {
+        let words = match self {
+            Status::Pending => "RUNNING",
+            Status::Success(_) => "PASS",
+            Status::Failure(_) => "FAIL",
+            Status::Inconclusive(_) => "INCONCLUSIVE",
+            Status::Abort(_) => "ABORT",
+            Status::Timeout => "TIMEOUT",
+            Status::Oom => "OUT OF MEMORY",
+            Status::Skipped => "SKIPPED",
+            Status::Xfail(_) => "EXPECTED FAIL",
+            Status::Xpass(_) => "UNEXPECTED PASS",
+            Status::NotRun => "NOT RUN",
+            Status::Cancelled => "CANCELLED",
+        };
+        match self.code() {
+            Some(code) if code != 0 => format!("{} exit {}", words, code),
+            _ => words.to_string(),
+        }
+    }
+
+    /// Name of the status, as used in reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Success(_) => "success",
+            Status::Failure(_) => "failure",
+            Status::Inconclusive(_) => "inconclusive",
+            Status::Abort(_) => "abort",
+            Status::Timeout => "timeout",
+            Status::Oom => "oom",
+            Status::Skipped => "skipped",
+            Status::Xfail(_) => "xfail",
+            Status::Xpass(_) => "xpass",
+            Status::NotRun => "not-run",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_failure(self) -> bool {
+        matches!(
+            self,
+            Status::Failure(_) | Status::Abort(_) | Status::Timeout | Status::Oom
+        )
+    }
+
+    /// Whether the command ran to the end, as opposed to a run that was cut
+    /// short before it or while it was running.
+    pub fn was_run(self) -> bool {
+        !matches!(self, Status::NotRun | Status::Cancelled)
+    }
+
+    pub fn code(&self) -> Option<i32> {
+        match *self {
+            Status::Pending
+            | Status::Timeout
+            | Status::Oom
+            | Status::Skipped
+            | Status::NotRun
+            | Status::Cancelled => None,
+            Status::Success(code) => Some(code),
+            Status::Failure(code) => Some(code),
+            Status::Inconclusive(code) => Some(code),
+            Status::Abort(code) => code,
+            Status::Xfail(code) => code,
+            Status::Xpass(code) => Some(code),
+        }
+    }
+}
+
+impl From<ExitStatus> for Status {
+    fn from(exit_status: ExitStatus) -> Status {
+        match exit_status.code() {
+            Some(code @ 0) => Status::Success(code),
+            Some(code @ 1..=124) | Some(code @ 126) | Some(code @ 127) => Status::Failure(code),
+            Some(code @ 125) => Status::Inconclusive(code),
+            code => Status::Abort(code),
+        }
+    }
+}
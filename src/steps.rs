@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::cli::StepSpec;
+use crate::runner::shell_quote;
+
+/// A command to run on each commit.
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// Name of the step, which is empty for the command given after `--`.
+    pub name: String,
+    /// Indices of the steps that must succeed before this step is run.
+    pub deps: Vec<usize>,
+    pub command: Vec<String>,
+    pub shell: bool,
+    /// Environment variables set only for this step.
+    pub env: Vec<(String, String)>,
+}
+
+impl Step {
+    /// Command line to run the step with a shell, e.g. in an `exec` line of a
+    /// rebase todo list, with environment variables set.
+    pub fn shell_line(&self, shell: &str, env: &[(String, String)]) -> String {
+        let mut words = env
+            .iter()
+            .chain(&self.env)
+            .map(|(name, value)| format!("{}={}", name, shell_quote(value)))
+            .collect::<Vec<_>>();
+        if self.shell {
+            let (script, args) = self.command.split_first().expect("command is not empty");
+            words.push(shell_quote(shell));
+            words.push("-c".to_string());
+            words.push(shell_quote(script));
+            if !args.is_empty() {
+                words.push("corun".to_string());
+            }
+            words.extend(args.iter().map(|arg| shell_quote(arg)));
+        } else {
+            words.extend(self.command.iter().map(|word| shell_quote(word)));
+        }
+        words.join(" ")
+    }
+}
+
+/// Create steps from specifications, ordered so that each step comes after its
+/// dependencies.
+pub fn from_specs(specs: &[StepSpec]) -> Result<Vec<Step>, String> {
+    let mut indices = HashMap::new();
+    for (index, spec) in specs.iter().enumerate() {
+        if indices.insert(spec.name.as_str(), index).is_some() {
+            return Err(format!("duplicate step: {}", spec.name));
+        }
+    }
+
+    // depth-first search, so that dependencies are visited first
+    fn visit(
+        index: usize,
+        specs: &[StepSpec],
+        indices: &HashMap<&str, usize>,
+        visiting: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), String> {
+        if order.contains(&index) {
+            return Ok(());
+        }
+        if visiting.contains(&index) {
+            return Err(format!("steps depend on each other: {}", specs[index].name));
+        }
+
+        visiting.push(index);
+        for dep in &specs[index].deps {
+            let dep_index = *indices.get(dep.as_str()).ok_or_else(|| {
+                format!(
+                    "step {} depends on unknown step: {}",
+                    specs[index].name, dep
+                )
+            })?;
+            visit(dep_index, specs, indices, visiting, order)?;
+        }
+        visiting.pop();
+
+        order.push(index);
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    for index in 0..specs.len() {
+        visit(index, specs, &indices, &mut Vec::new(), &mut order)?;
+    }
+
+    Ok(order
+        .iter()
+        .map(|&index| {
+            let spec = &specs[index];
+            Step {
+                name: spec.name.clone(),
+                deps: spec
+                    .deps
+                    .iter()
+                    .map(|dep| {
+                        let dep_index = indices[dep.as_str()];
+                        order.iter().position(|&index| index == dep_index).unwrap()
+                    })
+                    .collect(),
+                command: vec![spec.command.clone()],
+                shell: true,
+                env: Vec::new(),
+            }
+        })
+        .collect())
+}
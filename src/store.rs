@@ -0,0 +1,304 @@
+//! Results of past runs, stored as one JSON file per run in the repository.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bench;
+use crate::git::StashEntry;
+use crate::report::RunResult;
+use crate::vcs::Vcs;
+
+/// A run of a command on a list of commits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Run {
+    /// Identifier of the run, based on the time it was started.
+    #[serde(default)]
+    pub id: String,
+    /// Time the run was started, in RFC 3339 format.
+    #[serde(default)]
+    pub started: String,
+    /// Name given to the run with `--label`.
+    #[serde(default)]
+    pub label: Option<String>,
+    pub command: Vec<String>,
+    /// Directory the commits were checked out in.
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    pub results: Vec<RunResult>,
+    /// Arguments corun was run with, for `replay`.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Commits that were run on, in order.
+    #[serde(default)]
+    pub commits: Vec<String>,
+    /// Stash entries that were applied, oldest first.
+    #[serde(default)]
+    pub stashes: Vec<StashEntry>,
+    /// Stash entry that was run on as a commit, with `--stash-as-commit`.
+    #[serde(default)]
+    pub stash_as_commit: Option<StashEntry>,
+    /// Environment variables corun was run with that change how commands
+    /// build and behave (see `RECORDED_ENV`).
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Settings of the run, if it was run with `--bench`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bench: Option<bench::Settings>,
+}
+
+impl Run {
+    /// Read a run from a JSON file, e.g. a report written with `--json`.
+    pub fn read(path: &Path) -> io::Result<Run> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Write a run to a JSON file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        self.write_to(File::create(path)?)
+    }
+
+    fn write_to(&self, file: File) -> io::Result<()> {
+        let mut out = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)?;
+        out.flush()
+    }
+}
+
+/// Environment variables that change how commands build and behave, which are
+/// recorded for a run and set again when it is replayed. Names ending with `*`
+/// match all variables starting with the rest. Other variables, e.g. ones with
+/// credentials or ones of the session like `PATH`, `HOME` and `SSH_AUTH_SOCK`,
+/// are not recorded.
+const RECORDED_ENV: &[&str] = &[
+    "CORUN_*",
+    "LANG",
+    "LC_*",
+    "TZ",
+    "SOURCE_DATE_EPOCH",
+    "CC",
+    "CXX",
+    "CFLAGS",
+    "CXXFLAGS",
+    "CPPFLAGS",
+    "LDFLAGS",
+    "MAKEFLAGS",
+    "RUSTFLAGS",
+    "RUSTDOCFLAGS",
+    "RUST_BACKTRACE",
+    "RUST_LOG",
+    "RUST_TEST_THREADS",
+    "CARGO_BUILD_*",
+    "CARGO_PROFILE_*",
+    "CARGO_INCREMENTAL",
+    "GOFLAGS",
+    "GOOS",
+    "GOARCH",
+    "CGO_ENABLED",
+    "NODE_ENV",
+    "NODE_OPTIONS",
+    "PYTHONHASHSEED",
+    "PYTHONOPTIMIZE",
+    "JAVA_TOOL_OPTIONS",
+];
+
+/// Whether an environment variable is recorded for runs and replayed.
+pub fn is_recorded_env(name: &str) -> bool {
+    RECORDED_ENV
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == *pattern,
+        })
+}
+
+/// Environment variables to record for a run.
+pub fn env_snapshot() -> BTreeMap<String, String> {
+    env::vars()
+        .filter(|(name, _)| is_recorded_env(name))
+        .collect()
+}
+
+/// How long, and how many, runs are kept for.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_entries: Option<usize>,
+}
+
+impl Retention {
+    pub fn is_set(&self) -> bool {
+        self.max_age.is_some() || self.max_entries.is_some()
+    }
+}
+
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    /// Open the store of the results of runs in a repository.
+    pub fn open(vcs: &dyn Vcs) -> Self {
+        Store {
+            dir: vcs.store_dir().join("runs"),
+        }
+    }
+
+    /// Directory the runs are stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Save a run, changing its identifier if one with the same identifier
+    /// already exists.
+    pub fn save(&self, run: &mut Run) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let base_id = run.id.clone();
+        for n in 1.. {
+            if n > 1 {
+                run.id = format!("{}-{}", base_id, n);
+            }
+            let path = self.path(&run.id)?;
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return run.write_to(file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!()
+    }
+
+    /// Replace a run that is already stored, e.g. after adding notes to it.
+    pub fn update(&self, run: &Run) -> io::Result<()> {
+        run.write(&self.path(&run.id)?)
+    }
+
+    pub fn load(&self, id: &str) -> io::Result<Run> {
+        Run::read(&self.path(id)?)
+            .map_err(|err| io::Error::new(err.kind(), format!("run {}: {}", id, err)))
+    }
+
+    /// Load a run by its identifier, or read it from a JSON file if one exists
+    /// at that path.
+    pub fn load_or_read(&self, id_or_path: &str) -> io::Result<Run> {
+        let path = Path::new(id_or_path);
+        if path.is_file() {
+            Run::read(path)
+        } else {
+            self.load(id_or_path)
+        }
+    }
+
+    pub fn remove(&self, id: &str) -> io::Result<()> {
+        fs::remove_file(self.path(id)?)
+    }
+
+    /// Path of the file a run is stored in, which must be in the directory of
+    /// the store, so identifiers from imported reports cannot point elsewhere.
+    fn path(&self, id: &str) -> io::Result<PathBuf> {
+        if !is_valid_id(id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid run identifier: {:?}", id),
+            ));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    /// Remove runs that are older than the retention allows, and the oldest
+    /// runs beyond the number it allows, returning the runs that are kept.
+    ///
+    /// Runs without a start time, e.g. ones imported from old reports, are only
+    /// removed to keep the number of runs down.
+    pub fn prune(&self, retention: &Retention) -> io::Result<Vec<Run>> {
+        let runs = self.list()?;
+        let now = Utc::now();
+
+        let mut kept = Vec::new();
+        for (index, run) in runs.iter().enumerate() {
+            let too_old = match (
+                retention.max_age,
+                DateTime::parse_from_rfc3339(&run.started),
+            ) {
+                (Some(max_age), Ok(started)) => (now - started.with_timezone(&Utc))
+                    .to_std()
+                    .is_ok_and(|age| age > max_age),
+                _ => false,
+            };
+            let too_many = retention
+                .max_entries
+                .is_some_and(|max_entries| runs.len() - index > max_entries);
+
+            if too_old || too_many {
+                self.remove(&run.id)?;
+            } else {
+                kept.push(run.clone());
+            }
+        }
+        Ok(kept)
+    }
+
+    /// All stored runs, oldest first. Runs that cannot be read are skipped
+    /// with a warning, so that one corrupt file does not hide all the others.
+    pub fn list(&self) -> io::Result<Vec<Run>> {
+        Ok(self
+            .ids()?
+            .iter()
+            .filter_map(|id| match self.load(id) {
+                Ok(run) => Some(run),
+                Err(err) => {
+                    eprintln!("Warning: skipping {}", err);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Identifiers of all stored runs, oldest first.
+    pub fn ids(&self) -> io::Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut ids = fs::read_dir(&self.dir)?
+            .flat_map(Result::ok)
+            .flat_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                Some(name.strip_suffix(".json")?.to_string())
+            })
+            .collect::<Vec<_>>();
+        ids.sort_by(|a, b| id_order(a).cmp(&id_order(b)));
+        Ok(ids)
+    }
+}
+
+/// Whether an identifier of a run can be used as the name of its file, with
+/// only letters, digits, `.`, `_` and `-`, and without `..`.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && !id.contains("..")
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Key to sort identifiers of runs by, which orders runs started in the same
+/// second by the number `Store::save` added to them (so `-10` after `-2`).
+fn id_order(id: &str) -> (&str, u64) {
+    let base_len = "YYYYMMDD-HHMMSS".len();
+    if id.len() > base_len && id.as_bytes()[base_len] == b'-' {
+        if let Ok(n) = id[base_len + 1..].parse() {
+            return (&id[..base_len], n);
+        }
+    }
+    (id, 1)
+}
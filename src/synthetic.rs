@@ -0,0 +1,117 @@
+//! Commits that do not exist in the repository, e.g. for testing a proposed
+//! rewrite of history before actually doing it.
+//!
+//! These are made in a scratch clone that shares objects with the repository,
+//! and are then fetched into the repository under `refs/corun/`, so that they
+//! can be run on like any other commit.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+
+/// A scratch clone to make synthetic commits in, which is removed when dropped.
+pub struct Scratch {
+    git_dir: PathBuf,
+    dir: PathBuf,
+}
+
+impl Scratch {
+    /// Create a scratch clone of a repository, with a commit checked out.
+    pub fn create(git_dir: &Path, base: &str) -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("git-corun-scratch-{}", std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+
+        let scratch = Scratch {
+            git_dir: git_dir.to_path_buf(),
+            dir,
+        };
+        gitc!(
+            "clone",
+            "--quiet",
+            "--shared",
+            "--no-checkout",
+            scratch.git_dir,
+            scratch.dir
+        )?;
+        git::ensure_identity(&scratch.dir)?;
+        scratch.checkout(base)?;
+        Ok(scratch)
+    }
+
+    /// Run a git command in the scratch clone.
+    pub fn git(&self, args: &[&str]) -> io::Result<String> {
+        gitc!("-C", self.dir, ..args)
+    }
+
+    pub fn checkout(&self, commit: &str) -> io::Result<()> {
+        self.git(&["checkout", "--quiet", "--force", "--detach", commit])?;
+        Ok(())
+    }
+
+    pub fn head(&self) -> io::Result<String> {
+        self.git(&["rev-parse", "HEAD"])
+    }
+
+    /// Apply the changes of a commit on top of the current commit, keeping its
+    /// message and author, and return the new commit.
+    pub fn cherry_pick(&self, commit: &str) -> io::Result<String> {
+        let result = self.git(&[
+            "cherry-pick",
+            "--allow-empty",
+            "--keep-redundant-commits",
+            commit,
+        ]);
+        if result.is_err() {
+            let _ = self.git(&["cherry-pick", "--abort"]);
+            return Err(io::Error::other(format!(
+                "could not apply {:.7} without conflicts",
+                commit
+            )));
+        }
+        self.head()
+    }
+
+    /// Fetch commits into the repository, so they can be run on, keeping them
+    /// from being garbage collected with a ref under `refs/corun/`.
+    pub fn keep(&self, name: &str, commits: &[String]) -> io::Result<()> {
+        // remove refs to commits kept before under the same name
+        let prefix = format!("refs/corun/{}/", name);
+        let old_refs = gitc!(
+            "--git-dir",
+            self.git_dir,
+            "for-each-ref",
+            "--format=delete %(refname)",
+            prefix
+        )?;
+        if !old_refs.is_empty() {
+            let input = old_refs + "\n";
+            gitc_with_input!(input; "--git-dir", self.git_dir, "update-ref", "--stdin")?;
+        }
+
+        let refspecs = commits
+            .iter()
+            .enumerate()
+            .map(|(index, commit)| format!("+{}:refs/corun/{}/{}", commit, name, index))
+            .collect::<Vec<_>>();
+        gitc!(
+            "--git-dir",
+            self.git_dir,
+            "fetch",
+            "--quiet",
+            "--no-tags",
+            self.dir,
+            ..&refspecs
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+static WORDS: AtomicBool = AtomicBool::new(false);
+
+static STATUS_FD: AtomicI32 = AtomicI32::new(1);
+
+/// Write status lines of commits to a file descriptor other than stdout.
+pub fn set_status_fd(fd: RawFd) -> io::Result<()> {
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(
+            err.kind(),
+            format!("status fd {}: {}", fd, err),
+        ));
+    }
+    STATUS_FD.store(fd, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Output for status lines of commits, which is stdout unless changed with
+/// `set_status_fd`.
+pub fn status_output() -> io::Result<File> {
+    let fd = unsafe { libc::dup(STATUS_FD.load(Ordering::SeqCst)) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Use colors in output or not, from a `color.ui` setting like git's, where
+/// `auto` (the default) uses colors if status lines go to a terminal.
+pub fn set_color(setting: Option<&str>) {
+    let color = match setting {
+        Some("always") => true,
+        Some("never") | Some("false") => false,
+        _ => status_is_terminal(),
+    };
+    COLOR.store(color, Ordering::SeqCst);
+}
+
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::SeqCst)
+}
+
+/// Show statuses as words rather than symbols, for screen readers and
+/// terminals without Unicode.
+pub fn set_words(words: bool) {
+    WORDS.store(words, Ordering::SeqCst);
+}
+
+pub fn words_enabled() -> bool {
+    WORDS.load(Ordering::SeqCst)
+}
+
+/// Text with an ANSI color (or other SGR code), if colors are enabled.
+pub fn paint(code: &str, text: impl Display) -> String {
+    if color_enabled() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Set the title of the terminal, or of the pane when running inside tmux.
+/// Does nothing when stderr is not a terminal, e.g. when it goes to a log.
+pub fn set_title(title: impl AsRef<str>) -> io::Result<()> {
+    let stderr = io::stderr();
+    if !stderr.is_terminal() {
+        return Ok(());
+    }
+    let mut stderr = stderr.lock();
+    write!(stderr, "\x1b]2;{}\x07", title.as_ref())?;
+    stderr.flush()
+}
+
+/// Ring the bell of the terminal, e.g. to get attention when in another window.
+pub fn bell() -> io::Result<()> {
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    write!(stderr, "\x07")?;
+    stderr.flush()
+}
+
+/// Whether status lines go to a terminal, so that they can be rewritten in place.
+pub fn status_is_terminal() -> bool {
+    status_output().is_ok_and(|output| output.is_terminal())
+}
+
+/// Whether stdin is a terminal, so that the user can be asked things.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Ask a yes/no question, which is answered with no if stdin is not a terminal.
+pub fn confirm(question: impl AsRef<str>) -> io::Result<bool> {
+    if !is_interactive() {
+        return Ok(false);
+    }
+    let stdin = io::stdin();
+
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    write!(stderr, "{} [y/N] ", question.as_ref())?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    stdin.lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
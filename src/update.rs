@@ -0,0 +1,226 @@
+//! Updating corun to the latest release on GitHub, for prebuilt binaries that
+//! were not installed with cargo.
+//!
+//! This is only available when built with the `self-update` feature, and uses
+//! `curl` to talk to GitHub. Downloaded binaries are only used if their SHA-256
+//! matches the checksum published with the release, which is checked with
+//! `sha256sum`.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::git;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/frxstrem/git-corun/releases/latest";
+
+/// How often to check for a new version, when checking is enabled with
+/// `corun.checkForUpdates`.
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Target the binary was built for, which release assets are named after.
+const TARGET: &str = env!("CORUN_TARGET");
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    fn asset(&self, name: &str) -> io::Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| io::Error::other(format!("release {} has no {}", self.tag_name, name)))
+    }
+}
+
+/// Update the running binary to the latest release, or only print whether
+/// there is a newer one if `check_only` is set.
+pub fn self_update(check_only: bool) -> io::Result<()> {
+    let release = latest_release(None)?;
+    if !is_newer(release.version(), env!("CARGO_PKG_VERSION")) {
+        eprintln!(
+            "git-corun {} is the latest version",
+            env!("CARGO_PKG_VERSION")
+        );
+        return Ok(());
+    }
+    if check_only {
+        eprintln!(
+            "git-corun {} is available (current version is {})",
+            release.version(),
+            env!("CARGO_PKG_VERSION")
+        );
+        return Ok(());
+    }
+
+    let asset_name = format!("git-corun-{}", TARGET);
+    let asset = release.asset(&asset_name)?;
+    let checksum_asset = release.asset(&format!("{}.sha256", asset_name))?;
+    let checksum = String::from_utf8_lossy(&curl(&[], &checksum_asset.browser_download_url)?)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    // download next to the binary, so it can be replaced with a rename
+    let exe = std::env::current_exe()?;
+    let new_exe = exe.with_extension("new");
+    let verified = curl(
+        &["--output".as_ref(), new_exe.as_os_str()],
+        &asset.browser_download_url,
+    )
+    .and_then(|_| {
+        let actual = sha256(&new_exe)?;
+        if checksum.is_empty() || actual != checksum {
+            return Err(io::Error::other(format!(
+                "checksum of {} does not match the release ({} instead of {})",
+                asset_name, actual, checksum
+            )));
+        }
+        Ok(())
+    });
+    if let Err(err) = verified {
+        let _ = fs::remove_file(&new_exe);
+        return Err(err);
+    }
+    fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755))?;
+    fs::rename(&new_exe, &exe)?;
+
+    eprintln!(
+        "Updated git-corun from {} to {}",
+        env!("CARGO_PKG_VERSION"),
+        release.version()
+    );
+    Ok(())
+}
+
+/// Print a notice if a newer version is available, if enabled with
+/// `corun.checkForUpdates`. GitHub is asked at most once a day, and failures
+/// are ignored, so that this never gets in the way.
+pub fn notify_if_outdated(git_dir: Option<&Path>) {
+    let enabled = git_dir
+        .and_then(|git_dir| git::get_config_bool(git_dir, "corun.checkForUpdates").ok())
+        .flatten()
+        .unwrap_or(false);
+    if !enabled {
+        return;
+    }
+
+    if let Some(latest) = cached_latest_version() {
+        if is_newer(&latest, env!("CARGO_PKG_VERSION")) {
+            eprintln!(
+                "git-corun {} is available (current version is {}); update with: git corun self-update",
+                latest,
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+    }
+}
+
+/// Latest version, as last fetched from GitHub, fetching it again if that was
+/// more than a day ago.
+fn cached_latest_version() -> Option<String> {
+    let path = cache_file()?;
+    let fresh = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < CHECK_INTERVAL);
+
+    if fresh {
+        return fs::read_to_string(&path)
+            .ok()
+            .map(|version| version.trim().to_string());
+    }
+
+    let version = latest_release(Some(Duration::from_secs(5)))
+        .ok()?
+        .version()
+        .to_string();
+    fs::create_dir_all(path.parent()?).ok()?;
+    fs::write(&path, &version).ok()?;
+    Some(version)
+}
+
+fn cache_file() -> Option<PathBuf> {
+    Some(dirs::cache_dir()?.join("git-corun").join("latest-version"))
+}
+
+fn latest_release(timeout: Option<Duration>) -> io::Result<Release> {
+    let max_time = timeout.map(|timeout| timeout.as_secs().to_string());
+    let mut args = vec![
+        "--header".as_ref(),
+        "Accept: application/vnd.github+json".as_ref(),
+    ];
+    if let Some(max_time) = &max_time {
+        args.extend(&["--max-time".as_ref(), max_time.as_ref()]);
+    }
+
+    let output = curl(&args, LATEST_RELEASE_URL)?;
+    Ok(serde_json::from_slice(&output)?)
+}
+
+fn curl(args: &[&OsStr], url: &str) -> io::Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(args)
+        .arg(url)
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("could not run curl: {}", err)))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        let error = error.trim_end_matches('\n');
+        return Err(io::Error::other(format!("curl error: {}", error)));
+    }
+    Ok(output.stdout)
+}
+
+/// SHA-256 of a file, in lowercase hex.
+fn sha256(path: &Path) -> io::Result<String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("could not run sha256sum: {}", err)))?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "sha256sum error: {}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase())
+}
+
+/// Whether a version is newer than another, comparing their numeric parts.
+fn is_newer(version: &str, than: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    parts(version) > parts(than)
+}
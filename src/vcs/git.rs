@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{parse_trailers, CommitInfo, Vcs};
+use crate::cli::DateFormat;
+use crate::git;
+use crate::status::Status;
+
+pub struct GitVcs {
+    git_dir: PathBuf,
+    /// Top-level directory of the work tree, unless the repository is bare.
+    work_tree: Option<PathBuf>,
+}
+
+impl GitVcs {
+    pub fn open() -> io::Result<Self> {
+        let git_dir = git::get_git_dir().map_err(|err| {
+            if !err.to_string().contains("not a git repository") {
+                return err;
+            }
+            let cwd = env::current_dir().unwrap_or_default();
+            io::Error::other(format!(
+                "not a git repository: {} (or any of its parent directories)",
+                cwd.to_string_lossy()
+            ))
+        })?;
+        Ok(GitVcs::with_git_dir(git_dir))
+    }
+
+    pub fn with_git_dir(git_dir: PathBuf) -> Self {
+        GitVcs {
+            git_dir,
+            work_tree: git::get_work_tree().ok(),
+        }
+    }
+}
+
+impl Vcs for GitVcs {
+    fn git_dir(&self) -> Option<&Path> {
+        Some(&self.git_dir)
+    }
+
+    fn root(&self) -> io::Result<PathBuf> {
+        self.work_tree
+            .clone()
+            .ok_or_else(|| io::Error::other("repository has no work tree"))
+    }
+
+    fn default_rev(&self) -> &'static str {
+        "HEAD"
+    }
+
+    fn expand_commits(&self, revs: &str) -> io::Result<Vec<String>> {
+        git::get_commit_hashes(&self.git_dir, revs)
+    }
+
+    fn range_ends(&self, revs: &str) -> io::Result<Vec<String>> {
+        git::get_range_ends(&self.git_dir, revs)
+    }
+
+    fn newest_first(&self, commits: &[String]) -> io::Result<Vec<String>> {
+        git::sort_commits_by_date(&self.git_dir, commits)
+    }
+
+    fn commits_touching(&self, commits: &[String], pattern: &str) -> io::Result<HashSet<String>> {
+        git::get_commits_touching(&self.git_dir, commits, pattern)
+    }
+
+    fn clone_to(&self, dst_dir: &Path) -> io::Result<()> {
+        git::clone_local(&self.git_dir, dst_dir)
+    }
+
+    fn fetch_commits(&self, work_dir: &Path, commits: &[String]) -> io::Result<()> {
+        git::fetch_commits(work_dir, commits)
+    }
+
+    fn checkout(&self, work_dir: &Path, commit: &str) -> io::Result<()> {
+        git::checkout_detached(work_dir, commit)
+    }
+
+    fn clean(&self, work_dir: &Path) -> io::Result<()> {
+        git::clean_work_dir(work_dir)
+    }
+
+    fn is_pristine(&self, work_dir: &Path, commit: &str) -> io::Result<bool> {
+        Ok(git::get_head_commit(work_dir)? == commit && git::is_work_dir_clean(work_dir)?)
+    }
+
+    fn metadata_dir(&self, work_dir: &Path) -> PathBuf {
+        work_dir.join(".git").join("corun")
+    }
+
+    fn store_dir(&self) -> PathBuf {
+        self.git_dir.join("corun")
+    }
+
+    fn show_commit(
+        &self,
+        commit: &str,
+        status: Status,
+        label: &str,
+        date: &DateFormat,
+    ) -> io::Result<()> {
+        let base_format = "%C(yellow)%h %C(bold)%G? %Creset%C(cyan)[%Cgreen%ad%C(cyan) by %Cred%aN%C(cyan)]%Creset %s";
+        let mut format = format!("{} {}", status.get_format(), base_format);
+        if !label.is_empty() {
+            format += &format!(" %C(magenta)({})%Creset", label.replace('%', "%%"));
+        }
+
+        let date = match date {
+            DateFormat::Relative => "relative".to_string(),
+            DateFormat::Iso => "iso".to_string(),
+            DateFormat::Local => "local".to_string(),
+            DateFormat::Format(format) => format!("format:{}", format),
+        };
+
+        git::show_commit(
+            &self.git_dir,
+            self.work_tree.as_deref(),
+            commit,
+            &format,
+            &date,
+        )
+    }
+
+    fn commit_info(&self, commit: &str) -> io::Result<CommitInfo> {
+        let format = "%H%n%P%n%aN%n%aE%n%aI%n%s%n%(trailers:only,unfold)";
+        let output = git::format_commit(&self.git_dir, self.work_tree.as_deref(), commit, format)?;
+
+        let mut lines = output.split('\n');
+        let mut next = || lines.next().unwrap_or_default().to_string();
+        Ok(CommitInfo {
+            commit: next(),
+            parents: next().split_whitespace().map(str::to_string).collect(),
+            author: next(),
+            email: next(),
+            date: next(),
+            subject: next(),
+            trailers: parse_trailers(lines),
+        })
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{parse_trailers, run_output, split_lines, CommitInfo, Vcs};
+use crate::cli::DateFormat;
+use crate::status::Status;
+use crate::term;
+
+/// Mercurial repositories, with revisions resolved as hg revsets.
+pub struct HgVcs {
+    root: PathBuf,
+}
+
+impl HgVcs {
+    pub fn open() -> io::Result<Self> {
+        Ok(HgVcs {
+            root: PathBuf::from(run_output("hg", &["root"])?),
+        })
+    }
+
+    fn log(&self, revset: &str, template: &str) -> io::Result<String> {
+        run_output(
+            "hg",
+            gitc_args!(
+                "--repository",
+                self.root,
+                "log",
+                "--rev",
+                revset,
+                "--template",
+                template
+            ),
+        )
+    }
+}
+
+impl Vcs for HgVcs {
+    fn git_dir(&self) -> Option<&Path> {
+        None
+    }
+
+    fn root(&self) -> io::Result<PathBuf> {
+        Ok(self.root.clone())
+    }
+
+    fn default_rev(&self) -> &'static str {
+        "."
+    }
+
+    fn expand_commits(&self, revs: &str) -> io::Result<Vec<String>> {
+        Ok(split_lines(&self.log(revs, "{node}\n")?))
+    }
+
+    fn range_ends(&self, revs: &str) -> io::Result<Vec<String>> {
+        let revset = format!("heads({0}) + roots({0})", revs);
+        Ok(split_lines(&self.log(&revset, "{node}\n")?))
+    }
+
+    fn newest_first(&self, commits: &[String]) -> io::Result<Vec<String>> {
+        // An empty revset is a syntax error to hg.
+        if commits.is_empty() {
+            return Ok(Vec::new());
+        }
+        let revset = format!("sort({}, -date)", commits.join("+"));
+        Ok(split_lines(&self.log(&revset, "{node}\n")?))
+    }
+
+    fn commits_touching(&self, commits: &[String], pattern: &str) -> io::Result<HashSet<String>> {
+        if commits.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let revset = format!(
+            "({}) and file('{}')",
+            commits.join("+"),
+            pattern.replace('\'', "\\'")
+        );
+        Ok(split_lines(&self.log(&revset, "{node}\n")?)
+            .into_iter()
+            .collect())
+    }
+
+    fn clone_to(&self, dst_dir: &Path) -> io::Result<()> {
+        run_output(
+            "hg",
+            gitc_args!("clone", "--quiet", "--noupdate", self.root, dst_dir),
+        )?;
+        Ok(())
+    }
+
+    fn fetch_commits(&self, work_dir: &Path, commits: &[String]) -> io::Result<()> {
+        let revs = commits
+            .iter()
+            .flat_map(|commit| vec!["--rev", commit.as_str()]);
+        run_output(
+            "hg",
+            gitc_args!("--repository", work_dir, "pull", "--quiet", ..revs),
+        )?;
+        Ok(())
+    }
+
+    fn checkout(&self, work_dir: &Path, commit: &str) -> io::Result<()> {
+        run_output(
+            "hg",
+            gitc_args!(
+                "--repository",
+                work_dir,
+                "update",
+                "--quiet",
+                "--clean",
+                "--rev",
+                commit
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn clean(&self, work_dir: &Path) -> io::Result<()> {
+        run_output(
+            "hg",
+            gitc_args!(
+                "--repository",
+                work_dir,
+                "--config",
+                "extensions.purge=",
+                "purge",
+                "--all"
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn is_pristine(&self, work_dir: &Path, commit: &str) -> io::Result<bool> {
+        let parent = run_output(
+            "hg",
+            gitc_args!(
+                "--repository",
+                work_dir,
+                "log",
+                "--rev",
+                ".",
+                "--template",
+                "{node}"
+            ),
+        )?;
+        let status = run_output(
+            "hg",
+            gitc_args!(
+                "--repository",
+                work_dir,
+                "status",
+                "--no-status",
+                "--modified",
+                "--added",
+                "--removed",
+                "--deleted",
+                "--unknown",
+                "--ignored"
+            ),
+        )?;
+        Ok(parent == commit && status.is_empty())
+    }
+
+    fn metadata_dir(&self, work_dir: &Path) -> PathBuf {
+        work_dir.join(".hg").join("corun")
+    }
+
+    fn store_dir(&self) -> PathBuf {
+        self.root.join(".hg").join("corun")
+    }
+
+    fn show_commit(
+        &self,
+        commit: &str,
+        status: Status,
+        label: &str,
+        date: &DateFormat,
+    ) -> io::Result<()> {
+        let date = match date {
+            DateFormat::Relative => "{date|age}".to_string(),
+            DateFormat::Iso => "{date|isodate}".to_string(),
+            DateFormat::Local => "{date|localdate|date}".to_string(),
+            DateFormat::Format(format) => format!(
+                "{{date(date, \"{}\")}}",
+                format.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+        };
+        let template = format!(
+            "{} {}{}{}{}{} {{desc|firstline}}",
+            term::paint("33", "{node|short}"),
+            term::paint("36", "["),
+            term::paint("32", date),
+            term::paint("36", " by "),
+            term::paint("31", "{mailmap(author)|person}"),
+            term::paint("36", "]"),
+        );
+        let line = self.log(commit, &template)?;
+
+        let mut output = term::status_output()?;
+        if label.is_empty() {
+            writeln!(output, "{} {}", status.get_ansi_format(), line)?;
+        } else {
+            writeln!(
+                output,
+                "{} {} {}",
+                status.get_ansi_format(),
+                line,
+                term::paint("35", format!("({})", label))
+            )?;
+        }
+        output.flush()
+    }
+
+    fn commit_info(&self, commit: &str) -> io::Result<CommitInfo> {
+        let template = "{node}\n{p1node} {p2node}\n{mailmap(author)|person}\n{mailmap(author)|email}\n{date|rfc3339date}\n{desc}";
+        let output = self.log(commit, template)?;
+
+        let mut lines = output.splitn(6, '\n');
+        let mut next = || lines.next().unwrap_or_default().to_string();
+        let (commit, parents, author, email, date) = (next(), next(), next(), next(), next());
+        let desc = next();
+
+        // hg has no trailers of its own, so use the last paragraph of the description
+        let last_paragraph = desc.rsplit("\n\n").next().unwrap_or_default();
+        let trailers = if desc.contains("\n\n") {
+            parse_trailers(last_paragraph.lines())
+        } else {
+            Vec::new()
+        };
+
+        Ok(CommitInfo {
+            commit,
+            // hg uses the null revision for missing parents
+            parents: parents
+                .split(' ')
+                .filter(|parent| !parent.is_empty() && !parent.chars().all(|c| c == '0'))
+                .map(str::to_string)
+                .collect(),
+            author,
+            email,
+            date,
+            subject: desc.lines().next().unwrap_or_default().to_string(),
+            trailers,
+        })
+    }
+}
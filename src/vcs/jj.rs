@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{run_output, split_lines, CommitInfo, GitVcs, Vcs};
+use crate::cli::DateFormat;
+use crate::git;
+use crate::status::Status;
+
+/// Jujutsu repositories that are colocated with a git repository.
+///
+/// Revisions are resolved as jj revsets, and everything else is done by git.
+pub struct JjVcs {
+    root: PathBuf,
+    git: GitVcs,
+}
+
+impl JjVcs {
+    pub fn open() -> io::Result<Self> {
+        let root = PathBuf::from(run_output("jj", &["root"])?);
+
+        let git_dir = git::get_git_dir().map_err(|_| {
+            io::Error::other("only jj repositories colocated with git are supported")
+        })?;
+
+        Ok(JjVcs {
+            root,
+            git: GitVcs::with_git_dir(git_dir),
+        })
+    }
+
+    fn log(&self, revset: &str) -> io::Result<Vec<String>> {
+        let output = run_output(
+            "jj",
+            gitc_args!(
+                "--repository",
+                self.root,
+                "--ignore-working-copy",
+                "log",
+                "--no-graph",
+                "--reversed",
+                "--revisions",
+                revset,
+                "--template",
+                r#"commit_id ++ "\n""#
+            ),
+        )?;
+        Ok(split_lines(&output))
+    }
+}
+
+impl Vcs for JjVcs {
+    fn git_dir(&self) -> Option<&Path> {
+        self.git.git_dir()
+    }
+
+    fn root(&self) -> io::Result<PathBuf> {
+        Ok(self.root.clone())
+    }
+
+    fn default_rev(&self) -> &'static str {
+        "@"
+    }
+
+    fn expand_commits(&self, revs: &str) -> io::Result<Vec<String>> {
+        self.log(revs)
+    }
+
+    fn range_ends(&self, revs: &str) -> io::Result<Vec<String>> {
+        self.log(&format!("heads({0}) | roots({0})", revs))
+    }
+
+    fn newest_first(&self, commits: &[String]) -> io::Result<Vec<String>> {
+        self.git.newest_first(commits)
+    }
+
+    fn commits_touching(&self, commits: &[String], pattern: &str) -> io::Result<HashSet<String>> {
+        self.git.commits_touching(commits, pattern)
+    }
+
+    fn clone_to(&self, dst_dir: &Path) -> io::Result<()> {
+        self.git.clone_to(dst_dir)
+    }
+
+    fn fetch_commits(&self, work_dir: &Path, commits: &[String]) -> io::Result<()> {
+        self.git.fetch_commits(work_dir, commits)
+    }
+
+    fn checkout(&self, work_dir: &Path, commit: &str) -> io::Result<()> {
+        self.git.checkout(work_dir, commit)
+    }
+
+    fn clean(&self, work_dir: &Path) -> io::Result<()> {
+        self.git.clean(work_dir)
+    }
+
+    fn is_pristine(&self, work_dir: &Path, commit: &str) -> io::Result<bool> {
+        self.git.is_pristine(work_dir, commit)
+    }
+
+    fn metadata_dir(&self, work_dir: &Path) -> PathBuf {
+        self.git.metadata_dir(work_dir)
+    }
+
+    fn store_dir(&self) -> PathBuf {
+        self.git.store_dir()
+    }
+
+    fn show_commit(
+        &self,
+        commit: &str,
+        status: Status,
+        label: &str,
+        date: &DateFormat,
+    ) -> io::Result<()> {
+        self.git.show_commit(commit, status, label, date)
+    }
+
+    fn commit_info(&self, commit: &str) -> io::Result<CommitInfo> {
+        self.git.commit_info(commit)
+    }
+}
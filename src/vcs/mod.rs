@@ -0,0 +1,175 @@
+//! Version control systems that commits can be checked out from.
+//!
+//! Features that are specific to git (stashes, commit sets, marks) use the
+//! `git` module directly, and are only available when `Vcs::git_dir` is set.
+
+mod git;
+mod hg;
+mod jj;
+
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{DateFormat, VcsKind};
+use crate::status::Status;
+
+pub use self::git::GitVcs;
+pub use self::hg::HgVcs;
+pub use self::jj::JjVcs;
+
+pub trait Vcs {
+    /// Git directory of the repository, if it is (or is backed by) a git repository.
+    fn git_dir(&self) -> Option<&Path>;
+
+    /// Root directory of the working copy of the repository.
+    fn root(&self) -> io::Result<PathBuf>;
+
+    /// Revision to run on if none are given.
+    fn default_rev(&self) -> &'static str;
+
+    /// Expand a revision or range of revisions to a list of commits.
+    fn expand_commits(&self, revs: &str) -> io::Result<Vec<String>>;
+
+    /// Commits at the ends of a revision or range of revisions, which change
+    /// when the commits it expands to do, and are quicker to find than these.
+    fn range_ends(&self, revs: &str) -> io::Result<Vec<String>>;
+
+    /// Sort commits by date, newest first.
+    fn newest_first(&self, commits: &[String]) -> io::Result<Vec<String>>;
+
+    /// Get the commits that change any files matching a pattern.
+    fn commits_touching(&self, commits: &[String], pattern: &str) -> io::Result<HashSet<String>>;
+
+    /// Clone the repository into an empty directory.
+    fn clone_to(&self, dst_dir: &Path) -> io::Result<()>;
+
+    /// Fetch commits that were added after cloning into a clone.
+    fn fetch_commits(&self, work_dir: &Path, commits: &[String]) -> io::Result<()>;
+
+    /// Check out a commit in a clone, discarding any local changes.
+    fn checkout(&self, work_dir: &Path, commit: &str) -> io::Result<()>;
+
+    /// Remove all untracked and ignored files from a clone.
+    fn clean(&self, work_dir: &Path) -> io::Result<()>;
+
+    /// Check that a clone has a commit checked out, without any local changes
+    /// or untracked or ignored files.
+    fn is_pristine(&self, work_dir: &Path, commit: &str) -> io::Result<bool>;
+
+    /// Directory in a clone for corun's own files, which cleaning does not remove.
+    fn metadata_dir(&self, work_dir: &Path) -> PathBuf;
+
+    /// Directory in the repository for corun's own files, e.g. results of past runs.
+    fn store_dir(&self) -> PathBuf;
+
+    /// Print a commit on a single line, prefixed by a status and followed by a label.
+    fn show_commit(
+        &self,
+        commit: &str,
+        status: Status,
+        label: &str,
+        date: &DateFormat,
+    ) -> io::Result<()>;
+
+    /// Get the metadata of a commit, for reports and summaries.
+    fn commit_info(&self, commit: &str) -> io::Result<CommitInfo>;
+}
+
+/// Metadata of a commit.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CommitInfo {
+    pub commit: String,
+    #[serde(default)]
+    pub parents: Vec<String>,
+    pub author: String,
+    pub email: String,
+    /// Author date, in RFC 3339 format.
+    pub date: String,
+    pub subject: String,
+    /// Trailers at the end of the commit message, e.g. `Reviewed-by: ...`.
+    pub trailers: Vec<Trailer>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+}
+
+impl CommitInfo {
+    /// Values of all trailers with a key, which is matched case-insensitively.
+    pub fn trailer_values(&self, key: &str) -> Vec<&str> {
+        self.trailers
+            .iter()
+            .filter(|trailer| trailer.key.eq_ignore_ascii_case(key))
+            .map(|trailer| trailer.value.as_str())
+            .collect()
+    }
+}
+
+/// Open the repository in the current directory.
+pub fn open(kind: VcsKind) -> io::Result<Box<dyn Vcs>> {
+    Ok(match kind {
+        VcsKind::Git => Box::new(GitVcs::open()?),
+        VcsKind::Jj => Box::new(JjVcs::open()?),
+        VcsKind::Hg => Box::new(HgVcs::open()?),
+    })
+}
+
+/// Run a command and return its output, or its error output as an error.
+fn run_output<S>(program: &str, args: &[S]) -> io::Result<String>
+where
+    S: AsRef<OsStr>,
+{
+    crate::git::run::trace(program, args);
+    let output = Command::new(program).args(args).output().map_err(|err| {
+        if err.kind() == io::ErrorKind::NotFound {
+            io::Error::new(err.kind(), format!("{} not found", program))
+        } else {
+            err
+        }
+    })?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        let error = error.trim_end_matches('\n');
+        return Err(io::Error::other(format!("{} error: {}", program, error)));
+    }
+
+    let result = String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_string();
+    Ok(result)
+}
+
+fn split_lines(output: &str) -> Vec<String> {
+    output
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse `Key: value` lines into trailers, skipping any other lines.
+fn parse_trailers<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<Trailer> {
+    lines
+        .into_iter()
+        .flat_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '-') {
+                return None;
+            }
+            Some(Trailer {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}